@@ -1,6 +1,6 @@
 use std::{cell::RefCell, rc::Rc};
 
-use crate::{Transform, TransformContext};
+use crate::{Transform, TransformContext, Children};
 
 pub enum Namespace {
   /// `http://www.w3.org/1999/xhtml`
@@ -83,16 +83,19 @@ pub struct CommentNode {
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct RootNode {
+  /// 模板源码, 生成 source map 的 sourcesContent
+  pub source: String,
   pub children: Rc<RefCell<Vec<Rc<RefCell<Node>>>>>,
   pub helpers: Vec<String>,
   pub components: Vec<String>,
   pub directives: Vec<String>,
-  pub hoists: Vec<String>,
+  pub hoists: Vec<Option<JsChildNode>>,
   pub imports: Vec<String>,
   pub cached: usize,
   pub temps: usize,
   pub ssr_helpers: Vec<String>,
-  pub code_gen_node: Option<VnodeCall>,
+  /// 渲染函数返回的节点, 在所有 transform 完成后生成
+  pub code_gen_node: Option<Box<Node>>,
   pub filters: Vec<String>,
   pub loc: SourceLocation,
 }
@@ -149,6 +152,10 @@ pub enum RenderSlotCallArgs {
   CompoundExpressionNode(CompoundExpressionNode),
   ObjectProperty(ObjectExpression),
   TemplateChildNode(TemplateChildNode),
+  /// 插槽的 props
+  PropExpression(PropExpression),
+  /// 插槽没有传入时渲染的后备内容
+  FunctionExpression(FunctionExpression),
 }
 
 
@@ -184,7 +191,7 @@ pub enum CompoundExpressionNodeChild {
   RawText(String)
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub enum ConstantTypes {
   NotConstant,
   CanSkipPatch,
@@ -204,6 +211,8 @@ pub struct IfBranchNode {
   pub children: Rc<RefCell<Vec<Rc<RefCell<Node>>>>>,
   pub uer_key: Option<Prop>,
   pub is_template_if: bool,
+  /// 分支的 key, 在同一个父节点下的所有 v-if 分支中递增
+  pub key: usize,
   pub loc: SourceLocation,
 }
 
@@ -213,13 +222,17 @@ pub struct ForNode {
   pub value_alias: Option<ExpressionNode>,
   pub key_alias: Option<ExpressionNode>,
   pub object_index_alias: Option<ExpressionNode>,
-  pub children: Vec<TemplateChildNode>,
+  pub children: Rc<RefCell<Vec<Rc<RefCell<Node>>>>>,
   pub loc: SourceLocation,
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct TextCallNode {
   pub content: TextCallNodeContent,
+  /// `createTextVNode(text, 1 /* TEXT */)`, 动态文本才带 TEXT 标记
+  pub code_gen: CallExpression,
+  /// 静态文本提升后的 `_hoisted_N`, 生成代码时代替 `code_gen`
+  pub hoisted: Option<SimpleExpressionNode>,
   pub loc: SourceLocation,
 }
 
@@ -255,14 +268,25 @@ pub struct VnodeCall {
   pub props: Option<PropExpression>,
   pub patch_flag: Option<String>,
   pub dynamic_props: Option<DynamicProps>,
-  pub children: Vec<Node>,
-  pub directives: String,
+  pub children: Option<VnodeCallChildren>,
+  /// `withDirectives` 的运行时指令参数
+  pub directives: Option<ArrayExpression>,
   pub is_block: bool,
   pub disable_tracking: bool,
   pub is_component: bool,
   pub loc: SourceLocation,
 }
 
+#[derive(Debug, Clone, PartialEq)]
+pub enum VnodeCallChildren {
+  TemplateChildNodes(Rc<RefCell<Vec<Rc<RefCell<Node>>>>>),
+  SimpleExpressionNode(SimpleExpressionNode),
+  /// 组件的子节点编译为插槽对象 `{ default: _withCtx(() => [...]), _: 1 }`
+  SlotsExpression(ObjectExpression),
+  /// 有带 v-if/v-for 的插槽时, `createSlots({ ... }, [...])`
+  DynamicSlotsExpression(CallExpression),
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum DynamicProps {
   RawText(String),
@@ -319,11 +343,14 @@ pub enum PropExpression {
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum JsChildNode {
+  VnodeCall(VnodeCall),
   CallExpression(CallExpression),
+  ObjectExpression(ObjectExpression),
   FunctionExpression(FunctionExpression),
   ExpressionNode(ExpressionNode),
   ArrayExpression(ArrayExpression),
   ConditionalExpression(ConditionalExpression),
+  CacheExpression(CacheExpression),
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -384,6 +411,8 @@ pub enum Returns {
   TemplateChildNode(TemplateChildNode),
   TemplateChildNodes(Vec<TemplateChildNode>),
   JsChildNode(Box<JsChildNode>),
+  /// 插槽函数返回的子节点数组
+  Children(Rc<RefCell<Vec<Rc<RefCell<Node>>>>>),
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -408,12 +437,12 @@ pub struct CacheExpression {
   pub newline: bool,
 }
 
+/// `withMemo(deps, () => vnode, _cache, index)`
 #[derive(Debug, Clone, PartialEq)]
 pub struct MemoExpression {
+  pub deps: ExpressionNode,
+  pub value: Box<VnodeCall>,
   pub index: usize,
-  pub value: Box<JsChildNode>,
-  pub is_vnode: bool,
-  pub newline: bool,
 }
 
 pub struct TreeNode {
@@ -435,6 +464,16 @@ impl Node {
     transform.post_transform(self, ctx);
   }
 
+  pub fn children(&self) -> Option<Children> {
+    match self {
+      Node::Root(root_node) => Some(root_node.children.clone()),
+      Node::ElementNode(element_node) => Some(element_node.children.clone()),
+      Node::IFBranch(branch) => Some(branch.children.clone()),
+      Node::For(for_node) => Some(for_node.children.clone()),
+      _ => None,
+    }
+  }
+
   pub fn get_children(&mut self) -> Rc<RefCell<Vec<Rc<RefCell<Node>>>>> {
     match self {
       Node::Root(root_node) => root_node.children.clone(),
@@ -463,12 +502,27 @@ pub fn create_if_branch(
   } else {
     dir.exp
   };
+  let is_template_if = node.tag_type == ElementTagType::TemplateNode;
+  // `<template v-if>` 只渲染子节点, 其他元素自身作为分支的唯一子节点
+  let children = if is_template_if && find_prop(node.clone(), "for").is_none() {
+    node.children.clone()
+  } else {
+    let mut el = node.clone();
+    let props = node.props.borrow()
+      .iter()
+      .filter(|p| !matches!(p, Prop::Directive(d) if d.name == dir.name))
+      .cloned()
+      .collect();
+    el.props = Rc::new(RefCell::new(props));
+    Rc::new(RefCell::new(vec![Rc::new(RefCell::new(Node::ElementNode(el)))]))
+  };
   IfBranchNode { 
     condition, 
-    children: node.children.clone(),
+    children,
     uer_key: find_prop(node.clone(), "key"), 
-    is_template_if: false, 
-    loc: Default::default()
+    is_template_if, 
+    key: 0,
+    loc: dir.loc,
   }
 }
 
@@ -476,7 +530,7 @@ pub fn find_prop(
   node: ElementNodeBase,
   name: &str
 ) -> Option<Prop> {
-  for p in node.props.clone().into_inner().iter() {
+  for p in node.props.borrow().iter() {
     match p {
       Prop::Directive(d) => {
         if d.name == name {
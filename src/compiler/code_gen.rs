@@ -1,21 +1,1478 @@
-use crate::{Node, TextNode};
+use std::{cell::RefCell, rc::Rc};
+
+use sourcemap::{SourceMap, SourceMapBuilder};
+
+use crate::{
+  Node, TextNode, RootNode, ElementNodeBase, ElementTagType, VnodeCall, VnodeCallChildren,
+  PropExpression, DynamicProps, ExpressionNode, SimpleExpressionNode, CompoundExpressionNode,
+  CompoundExpressionNodeChild, InterpolationNode, TextCallNode, IfNode,
+  IfBranchNode, ForNode, JsChildNode, CallExpression, CallExpressionArgument, ObjectExpression,
+  ArrayExpression, ElementValue, FunctionExpression, Params, Returns, ConditionalExpression,
+  CacheExpression, MemoExpression, TemplateChildNode, RenderSlotCall, RenderSlotCallArgs, CommentNode, Property,
+  Position, ConstantTypes, PatchFlags, CodegenOptions, CodegenMode, format_patch_flag, to_js_string_literal,
+  to_valid_asset_id, is_simple_identifier, is_builtin_symbol_tag, inject_prop, has_key_prop, Prop,
+  OPEN_BLOCK, CREATE_BLOCK, CREATE_ELEMENT_BLOCK, CREATE_VNODE, CREATE_ELEMENT_VNODE,
+  CREATE_COMMENT, CREATE_TEXT, RESOLVE_COMPONENT, RESOLVE_DIRECTIVE, WITH_DIRECTIVES, RENDER_LIST, TO_DISPLAY_STRING,
+  SET_BLOCK_TRACKING, WITH_MEMO, WITH_CTX, FRAGMENT, CREATE_STATIC,
+};
+
+const PURE_ANNOTATION: &str = "/*#__PURE__*/";
+
+pub(crate) type Children = Rc<RefCell<Vec<Rc<RefCell<Node>>>>>;
+
+/// 生成代码中的位置(字节偏移)到模板位置的映射
+struct Mapping {
+  offset: usize,
+  source: Position,
+  name: Option<String>,
+}
+
 pub struct CodeGen<'a> {
   pub code: String,
-  pub ast: &'a Node
+  pub ast: &'a Node,
+  pub options: CodegenOptions,
+  /// 生成代码中用到的运行时 helper
+  pub helpers: Vec<String>,
+  /// 开启 `source_map` 时生成的 v3 source map
+  pub map: Option<SourceMap>,
+  /// `code` 中渲染函数之前的部分: helper 的引入和提升的节点
+  pub preamble: String,
+  mappings: Vec<Mapping>,
+  pub(crate) indent_level: usize,
+  /// 提升的节点在模块加载时创建, 标记为无副作用以便 tree-shaking
+  pure: bool,
 }
 
 impl<'a> CodeGen<'a> {
-  pub fn new(ast: &'a Node) -> Self {
+  pub fn new(ast: &'a Node, options: CodegenOptions) -> Self {
     Self {
       code: String::new(),
-      ast
+      ast,
+      options,
+      helpers: vec![],
+      preamble: String::new(),
+      map: None,
+      mappings: vec![],
+      indent_level: 0,
+      pure: false,
     }
   }
 
-  pub fn generate(&mut self){
+  pub fn generate(&mut self) {
+    let Node::Root(root) = self.ast else {
+      return;
+    };
+    for helper in root.helpers.iter() {
+      self.helper(helper);
+    }
+
+    // helper 在生成过程中才收集完整, 先生成提升的节点和渲染函数, 最后补上前导代码
+    self.gen_hoists(root);
+    let hoists = std::mem::take(&mut self.code);
+    let hoist_mappings = self.mappings.len();
+    self.gen_render_function(root);
+    let render = std::mem::take(&mut self.code);
+
+    match self.options.mode {
+      CodegenMode::Module => self.gen_module_preamble(root),
+      CodegenMode::Function => self.gen_function_preamble(root),
+    }
+    // 前导代码插入在最前面, 之前记录的映射需要整体后移
+    let preamble_len = self.code.len();
+    self.push(&hoists);
+    self.preamble = self.code.clone();
+    self.push(match self.options.mode {
+      CodegenMode::Module => "export ",
+      CodegenMode::Function => "return ",
+    });
+    let render_start = self.code.len();
+    self.push(&render);
+    for (index, mapping) in self.mappings.iter_mut().enumerate() {
+      mapping.offset += if index < hoist_mappings { preamble_len } else { render_start };
+    }
+
+    if self.options.source_map {
+      self.map = Some(self.build_source_map(root));
+    }
+  }
+
+  fn build_source_map(&self, root: &RootNode) -> SourceMap {
+    let mut builder = SourceMapBuilder::new(None);
+    let source_id = builder.add_source(&self.options.filename);
+    builder.set_source_contents(source_id, Some(&root.source));
+
+    // 映射按偏移递增, 逐段累计生成代码的行列号, 列号按 UTF-16 计算
+    let (mut line, mut column, mut last_offset) = (0u32, 0u32, 0usize);
+    for mapping in self.mappings.iter() {
+      for c in self.code[last_offset..mapping.offset].chars() {
+        if c == '\n' {
+          line += 1;
+          column = 0;
+        } else {
+          column += c.len_utf16() as u32;
+        }
+      }
+      last_offset = mapping.offset;
+      builder.add(
+        line,
+        column,
+        mapping.source.line as u32 - 1,
+        mapping.source.column as u32 - 1,
+        Some(&self.options.filename),
+        mapping.name.as_deref(),
+      );
+    }
+    builder.into_sourcemap()
+  }
+
+  /// 记录当前生成位置对应的模板位置, 没有位置信息的节点(编译器生成的节点)跳过
+  fn add_mapping(&mut self, source: Position, name: Option<String>) {
+    if !self.options.source_map || source.line == 0 {
+      return;
+    }
+    self.mappings.push(Mapping {
+      offset: self.code.len(),
+      source,
+      name,
+    });
+  }
+
+  /// 模块模式总是给标识符加前缀, 不能使用 `with`
+  fn prefix_identifiers(&self) -> bool {
+    self.options.prefix_identifiers || self.options.mode == CodegenMode::Module
+  }
+
+  /// `import { createElementVNode as _createElementVNode } from "vue"`
+  fn gen_module_preamble(&mut self, root: &RootNode) {
+    if !self.helpers.is_empty() {
+      let helpers = self.helpers
+        .iter()
+        .map(|h| format!("{} as _{}", h, h))
+        .collect::<Vec<_>>()
+        .join(", ");
+      self.push(&format!(
+        "import {{ {} }} from {}\n",
+        helpers,
+        to_js_string_literal(&self.options.runtime_module_name),
+      ));
+    }
+    for import in root.imports.iter() {
+      self.push(import);
+      self.push("\n");
+    }
+    if !self.helpers.is_empty() || !root.imports.is_empty() {
+      self.push("\n");
+    }
+  }
+
+  /// `const { createElementVNode: _createElementVNode } = Vue`
+  fn gen_function_preamble(&mut self, root: &RootNode) {
+    if self.helpers.is_empty() {
+      return;
+    }
+    let vue_binding = self.options.runtime_global_name.clone();
+    if self.prefix_identifiers() {
+      let helpers = alias_helpers(self.helpers.iter());
+      self.push(&format!("const {{ {} }} = {}\n", helpers, vue_binding));
+    } else {
+      // 其余 helper 在 `with` 块中解构, 提升的节点在渲染函数外创建, 需要提前解构
+      self.push(&format!("const _Vue = {}\n", vue_binding));
+      if !root.hoists.is_empty() {
+        let static_helpers = [CREATE_VNODE, CREATE_ELEMENT_VNODE, CREATE_COMMENT, CREATE_TEXT, CREATE_STATIC];
+        let helpers = alias_helpers(
+          static_helpers.iter().filter(|h| self.helpers.iter().any(|helper| helper == *h))
+        );
+        if !helpers.is_empty() {
+          self.push(&format!("const {{ {} }} = _Vue\n", helpers));
+        }
+      }
+    }
+    self.push("\n");
+  }
+
+  fn gen_render_function(&mut self, root: &RootNode) {
+    let mut args = vec!["_ctx", "_cache"];
+    if self.options.binding_metadata.is_some() && !self.options.inline {
+      args.extend(["$props", "$setup", "$data", "$options"]);
+    }
+    self.push(&format!("function render({}) {{", args.join(", ")));
+    self.indent();
+
+    let use_with_block = !self.prefix_identifiers();
+    if use_with_block {
+      self.push("with (_ctx) {");
+      self.indent();
+    }
+    let body_start = self.code.len();
+    let body_mappings = self.mappings.len();
+
+    if !root.components.is_empty() {
+      self.gen_assets(&root.components, "component");
+      if !root.directives.is_empty() {
+        self.newline();
+      }
+    }
+    if !root.directives.is_empty() {
+      self.gen_assets(&root.directives, "directive");
+    }
+    if !root.components.is_empty() || !root.directives.is_empty() {
+      self.push("\n");
+      self.newline();
+    }
+
+    self.push("return ");
+    self.gen_root(root);
+
+    if use_with_block {
+      if !self.helpers.is_empty() {
+        let destructure = format!(
+          "const {{ {} }} = _Vue\n\n{}",
+          alias_helpers(self.helpers.iter()),
+          "  ".repeat(self.indent_level),
+        );
+        self.code.insert_str(body_start, &destructure);
+        for mapping in self.mappings[body_mappings..].iter_mut() {
+          mapping.offset += destructure.len();
+        }
+      }
+      self.deindent(false);
+      self.push("}");
+    }
+    self.deindent(false);
+    self.push("}");
   }
 
   pub fn generate_text(&mut self, node: &TextNode) {
-    self.code.push_str(node.content.as_str());
-  }  
+    self.add_mapping(node.loc.start, None);
+    self.push(&to_js_string_literal(&node.content));
+    self.add_mapping(node.loc.end, None);
+  }
+
+  /// 记录用到的 helper, 返回生成代码中的别名
+  pub fn helper(&mut self, name: &str) -> String {
+    if !self.helpers.iter().any(|h| h == name) {
+      self.helpers.push(name.to_string());
+    }
+    format!("_{}", name)
+  }
+
+  fn push(&mut self, code: &str) {
+    self.code.push_str(code);
+  }
+
+  fn newline(&mut self) {
+    self.code.push('\n');
+    self.code.push_str(&"  ".repeat(self.indent_level));
+  }
+
+  fn indent(&mut self) {
+    self.indent_level += 1;
+    self.newline();
+  }
+
+  fn deindent(&mut self, without_newline: bool) {
+    self.indent_level -= 1;
+    if !without_newline {
+      self.newline();
+    }
+  }
+
+  fn gen_hoists(&mut self, root: &RootNode) {
+    if root.hoists.is_empty() {
+      return;
+    }
+    self.pure = true;
+    for (index, exp) in root.hoists.iter().enumerate() {
+      if let Some(exp) = exp {
+        self.push(&format!("const _hoisted_{} = ", index + 1));
+        self.gen_js_child(exp);
+        self.newline();
+      }
+    }
+    self.pure = false;
+    self.newline();
+  }
+
+  fn gen_assets(&mut self, assets: &[String], asset_type: &str) {
+    let resolver = self.helper(if asset_type == "directive" { RESOLVE_DIRECTIVE } else { RESOLVE_COMPONENT });
+    for (index, id) in assets.iter().enumerate() {
+      self.push(&format!(
+        "const {} = {}({})",
+        to_valid_asset_id(id, asset_type),
+        resolver,
+        to_js_string_literal(id),
+      ));
+      if index < assets.len() - 1 {
+        self.newline();
+      }
+    }
+  }
+
+  fn gen_root(&mut self, root: &RootNode) {
+    match root.code_gen_node.as_deref() {
+      None => self.push("null"),
+      // 没有 vnode 的根元素(`<template>`, v-once 等)也要作为 block
+      Some(Node::ElementNode(el)) => self.gen_element(el, true),
+      Some(node) => self.gen_node(node),
+    }
+  }
+
+  fn gen_node(&mut self, node: &Node) {
+    match node {
+      Node::ElementNode(el) => self.gen_element(el, false),
+      Node::Text(text) => self.generate_text(text),
+      Node::Comment(comment) => self.gen_comment(comment),
+      Node::Interpolation(interpolation) => self.gen_interpolation(interpolation),
+      Node::SimpleExpression(exp) => self.gen_simple_expression(exp),
+      Node::CompoundExpression(exp) => self.gen_compound_expression(exp),
+      Node::TextCall(text_call) => self.gen_text_call(text_call),
+      Node::If(if_node) => self.gen_if(if_node),
+      Node::For(for_node) => self.gen_for(for_node, None),
+      Node::VnodeCall(vnode) => self.gen_vnode_call(vnode),
+      _ => {},
+    }
+  }
+
+  fn gen_element(&mut self, el: &ElementNodeBase, as_block: bool) {
+    let Some(code_gen) = &el.code_gen else {
+      // 没有 codegen 的 `<template>` 直接渲染子节点, 作为 block 收集其中的动态节点
+      self.gen_fragment(&el.children, None, true);
+      return;
+    };
+    if let Some(exp) = &code_gen.simple_expression_node {
+      self.gen_simple_expression(exp);
+    } else if let Some(cache) = &code_gen.cache_expression {
+      self.gen_cache_expression(cache);
+    } else if let Some(memo) = &code_gen.memo_expression {
+      self.gen_memo_expression(memo);
+    } else if let Some(call) = &code_gen.render_slot_call {
+      self.gen_render_slot_call(call);
+    } else if let Some(vnode) = &code_gen.vnode_call {
+      if as_block && !vnode.is_block {
+        let mut vnode = vnode.clone();
+        vnode.is_block = true;
+        self.gen_vnode_call(&vnode);
+      } else {
+        self.gen_vnode_call(vnode);
+      }
+    } else {
+      self.gen_fragment(&el.children, None, as_block);
+    }
+  }
+
+  fn gen_vnode_call(&mut self, vnode: &VnodeCall) {
+    if vnode.directives.is_some() {
+      let with_directives = self.helper(WITH_DIRECTIVES);
+      self.push(&format!("{}(", with_directives));
+    }
+    if vnode.is_block {
+      let open_block = self.helper(OPEN_BLOCK);
+      let disable_tracking = if vnode.disable_tracking { "true" } else { "" };
+      self.push(&format!("({}({}), ", open_block, disable_tracking));
+    }
+    if self.pure {
+      self.push(PURE_ANNOTATION);
+    }
+    let call_helper = match (vnode.is_block, vnode.is_component) {
+      (true, true) => CREATE_BLOCK,
+      (true, false) => CREATE_ELEMENT_BLOCK,
+      (false, true) => CREATE_VNODE,
+      (false, false) => CREATE_ELEMENT_VNODE,
+    };
+    let call_helper = self.helper(call_helper);
+    self.add_mapping(vnode.loc.start, None);
+    self.push(&format!("{}(", call_helper));
+
+    let arg_count = vnode_arg_count(vnode);
+
+    self.gen_vnode_tag(vnode);
+    if arg_count > 1 {
+      self.push(", ");
+      match &vnode.props {
+        Some(props) => self.gen_prop_expression(props),
+        None => self.push("null"),
+      }
+    }
+    if arg_count > 2 {
+      self.push(", ");
+      match &vnode.children {
+        Some(children) => self.gen_vnode_children(children),
+        None => self.push("null"),
+      }
+    }
+    if arg_count > 3 {
+      self.push(", ");
+      match &vnode.patch_flag {
+        Some(patch_flag) => self.push(patch_flag),
+        None => self.push("null"),
+      }
+    }
+    if arg_count > 4 {
+      self.push(", ");
+      match &vnode.dynamic_props {
+        Some(DynamicProps::RawText(text)) => self.push(text),
+        Some(DynamicProps::SimpleExpressionNode(exp)) => self.gen_simple_expression(exp),
+        None => self.push("null"),
+      }
+    }
+
+    self.push(")");
+    if vnode.is_block {
+      self.push(")");
+    }
+    if let Some(directives) = &vnode.directives {
+      self.push(", ");
+      self.gen_array_expression(directives);
+      self.push(")");
+    }
+  }
+
+  fn gen_vnode_tag(&mut self, vnode: &VnodeCall) {
+    if is_builtin_symbol_tag(&vnode.tag) && (vnode.is_component || vnode.tag == FRAGMENT) {
+      let tag = self.helper(&vnode.tag);
+      self.push(&tag);
+    } else if vnode.is_component {
+      self.push(&vnode.tag);
+    } else {
+      self.push(&to_js_string_literal(&vnode.tag));
+    }
+  }
+
+  fn gen_vnode_children(&mut self, children: &VnodeCallChildren) {
+    match children {
+      VnodeCallChildren::SimpleExpressionNode(exp) => self.gen_simple_expression(exp),
+      VnodeCallChildren::SlotsExpression(slots) => self.gen_object_expression(slots),
+      VnodeCallChildren::DynamicSlotsExpression(call) => self.gen_call_expression(call),
+      VnodeCallChildren::TemplateChildNodes(nodes) => {
+        let render_nodes = nodes.borrow().clone();
+        // 单个文本子节点直接作为 children
+        if let [child] = &render_nodes[..] {
+          let child = child.borrow();
+          if is_text_like(&child) {
+            self.gen_node(&child);
+            return;
+          }
+        }
+        self.gen_children_array(nodes);
+      },
+    }
+  }
+
+  pub(crate) fn gen_children_array(&mut self, children: &Children) {
+    let nodes = children.borrow().clone();
+    let multilines = nodes.len() > 3 || nodes.iter().any(|n| !is_text_like(&n.borrow()));
+    self.push("[");
+    if multilines {
+      self.indent();
+    }
+    for (index, node) in nodes.iter().enumerate() {
+      self.gen_node(&node.borrow());
+      if index < nodes.len() - 1 {
+        if multilines {
+          self.push(",");
+          self.newline();
+        } else {
+          self.push(", ");
+        }
+      }
+    }
+    if multilines {
+      self.deindent(false);
+    }
+    self.push("]");
+  }
+
+  /// `(openBlock(), createElementBlock(Fragment, props, [...], STABLE_FRAGMENT))`
+  fn gen_fragment_block(&mut self, children: &Children, key: Option<usize>) {
+    self.gen_fragment(children, key.map(key_property), true);
+  }
+
+  fn gen_fragment(&mut self, children: &Children, key: Option<Property>, is_block: bool) {
+    let mut vnode = VnodeCall {
+      tag: FRAGMENT.to_string(),
+      children: Some(VnodeCallChildren::TemplateChildNodes(children.clone())),
+      patch_flag: Some(format_patch_flag(PatchFlags::STABLE_FRAGMENT)),
+      is_block,
+      ..Default::default()
+    };
+    if let Some(key) = key {
+      inject_prop(&mut vnode, key);
+    }
+    self.push_fragment(&vnode);
+  }
+
+  /// Fragment 的 children 总是数组
+  fn push_fragment(&mut self, vnode: &VnodeCall) {
+    if vnode.is_block {
+      let open_block = self.helper(OPEN_BLOCK);
+      self.push(&format!("({}(), ", open_block));
+    }
+    let call_helper = self.helper(if vnode.is_block { CREATE_ELEMENT_BLOCK } else { CREATE_ELEMENT_VNODE });
+    let fragment = self.helper(FRAGMENT);
+    self.push(&format!("{}({}, ", call_helper, fragment));
+    match &vnode.props {
+      Some(props) => self.gen_prop_expression(props),
+      None => self.push("null"),
+    }
+    self.push(", ");
+    if let Some(VnodeCallChildren::TemplateChildNodes(children)) = &vnode.children {
+      self.gen_children_array(children);
+    }
+    if let Some(patch_flag) = &vnode.patch_flag {
+      self.push(", ");
+      self.push(patch_flag);
+    }
+    self.push(")");
+    if vnode.is_block {
+      self.push(")");
+    }
+  }
+
+  fn gen_if(&mut self, if_node: &IfNode) {
+    self.gen_if_branches(&if_node.branches, 0);
+  }
+
+  fn gen_if_branches(&mut self, branches: &[IfBranchNode], index: usize) {
+    let branch = &branches[index];
+    let Some(condition) = &branch.condition else {
+      self.gen_if_branch(branch);
+      return;
+    };
+
+    self.gen_conditional_test(condition);
+    self.indent();
+    self.indent_level += 1;
+    self.push("? ");
+    self.gen_if_branch(branch);
+    self.indent_level -= 1;
+    self.newline();
+    self.push(": ");
+    if index + 1 < branches.len() {
+      if branches[index + 1].condition.is_some() {
+        self.gen_if_branches(branches, index + 1);
+      } else {
+        self.indent_level += 1;
+        self.gen_if_branches(branches, index + 1);
+        self.indent_level -= 1;
+      }
+    } else {
+      let create_comment = self.helper(CREATE_COMMENT);
+      self.push(&format!("{}(\"v-if\", true)", create_comment));
+    }
+    self.deindent(true);
+  }
+
+  fn gen_if_branch(&mut self, branch: &IfBranchNode) {
+    let key = branch.key;
+    let children = branch.children.borrow().clone();
+    if let [child] = &children[..] {
+      let child = child.borrow();
+      if let Node::ElementNode(el) = &*child {
+        // `<template v-if>` 中只有一个元素时与 SSR 一致, 不包裹 Fragment。
+        // 元素的 vnode 已经在 v-if 的转换中作为带 key 的 block
+        if let Some(vnode) = el.code_gen.as_ref().and_then(|c| c.vnode_call.as_ref()) {
+          self.gen_vnode_call(vnode);
+          return;
+        }
+        if el.code_gen.is_none() {
+          self.gen_fragment(&el.children, Some(key_property(key)), true);
+        } else {
+          self.gen_element(el, true);
+        }
+        return;
+      }
+      if let Node::For(for_node) = &*child {
+        self.gen_for(for_node, Some(key));
+        return;
+      }
+    }
+    self.gen_fragment_block(&branch.children, Some(key));
+  }
+
+  fn gen_conditional_test(&mut self, test: &ExpressionNode) {
+    match test {
+      ExpressionNode::SimpleExpressionNode(exp) => {
+        let needs_parens = !is_simple_identifier(&exp.content);
+        if needs_parens {
+          self.push("(");
+        }
+        self.gen_simple_expression(exp);
+        if needs_parens {
+          self.push(")");
+        }
+      },
+      ExpressionNode::CompoundExpressionNode(exp) => {
+        self.push("(");
+        self.gen_compound_expression(exp);
+        self.push(")");
+      },
+    }
+  }
+
+  /// 作为 v-if 分支时, 分支的 key 加在列表的 Fragment 上
+  fn gen_for(&mut self, for_node: &ForNode, key: Option<usize>) {
+    let (is_stable, fragment_flag) = for_fragment_flag(for_node);
+    let item_children = for_item_children(for_node);
+    let item_key = for_item_key(for_node);
+    let children = item_children.borrow().clone();
+
+    let open_block = self.helper(OPEN_BLOCK);
+    let create_block = self.helper(CREATE_ELEMENT_BLOCK);
+    let fragment = self.helper(FRAGMENT);
+    let render_list = self.helper(RENDER_LIST);
+    self.push(&format!(
+      "({}({}), {}({}, ",
+      open_block,
+      if is_stable { "" } else { "true" },
+      create_block,
+      fragment,
+    ));
+    match key {
+      Some(key) => self.gen_object_expression(&ObjectExpression { properties: vec![key_property(key)] }),
+      None => self.push("null"),
+    }
+    self.push(&format!(", {}(", render_list));
+    self.gen_expression(&for_node.source);
+    self.push(", (");
+    self.push(&for_loop_params(for_node));
+    self.push(") => {");
+    self.indent();
+    self.push("return ");
+    match &children[..] {
+      [child] if matches!(&*child.borrow(), Node::ElementNode(_)) => {
+        if let Node::ElementNode(el) = &*child.borrow() {
+          let vnode = el.code_gen.as_ref().and_then(|c| c.vnode_call.as_ref());
+          match vnode {
+            Some(vnode) => {
+              let mut vnode = vnode.clone();
+              vnode.is_block = !is_stable;
+              if let Some(key) = item_key {
+                inject_prop(&mut vnode, key);
+              }
+              self.gen_vnode_call(&vnode);
+            },
+            None => self.gen_element(el, !is_stable),
+          }
+        }
+      },
+      _ => self.gen_fragment(&item_children, item_key, !is_stable),
+    }
+    self.deindent(false);
+    self.push(&format!("}}), {}))", format_patch_flag(fragment_flag)));
+  }
+
+  fn gen_text_call(&mut self, node: &TextCallNode) {
+    match &node.hoisted {
+      Some(hoisted) => self.push(&hoisted.content),
+      None => self.gen_call_expression(&node.code_gen),
+    }
+  }
+
+  fn gen_comment(&mut self, node: &CommentNode) {
+    if self.pure {
+      self.push(PURE_ANNOTATION);
+    }
+    let create_comment = self.helper(CREATE_COMMENT);
+    self.add_mapping(node.loc.start, None);
+    self.push(&format!("{}({})", create_comment, to_js_string_literal(&node.content)));
+  }
+
+  fn gen_interpolation(&mut self, node: &InterpolationNode) {
+    if self.pure {
+      self.push(PURE_ANNOTATION);
+    }
+    let to_display_string = self.helper(TO_DISPLAY_STRING);
+    self.push(&format!("{}(", to_display_string));
+    self.gen_expression(&node.content);
+    self.push(")");
+  }
+
+  fn gen_expression(&mut self, exp: &ExpressionNode) {
+    match exp {
+      ExpressionNode::SimpleExpressionNode(exp) => self.gen_simple_expression(exp),
+      ExpressionNode::CompoundExpressionNode(exp) => self.gen_compound_expression(exp),
+    }
+  }
+
+  fn gen_simple_expression(&mut self, exp: &SimpleExpressionNode) {
+    // 加了前缀的标识符保留原名, 方便调试器显示变量名
+    let name = exp.content
+      .strip_prefix("_ctx.")
+      .filter(|name| !exp.is_static && is_simple_identifier(name))
+      .map(|name| name.to_string());
+    self.add_mapping(exp.loc.start, name);
+    if exp.is_static {
+      self.push(&to_js_string_literal(&exp.content));
+    } else {
+      self.push(&exp.content);
+    }
+    self.add_mapping(exp.loc.end, None);
+  }
+
+  pub(crate) fn gen_compound_expression(&mut self, exp: &CompoundExpressionNode) {
+    for child in exp.children.iter() {
+      match child {
+        CompoundExpressionNodeChild::SimpleExpressionNode(exp) => self.gen_simple_expression(exp),
+        CompoundExpressionNodeChild::InterpolationNode(n) => self.gen_interpolation(n),
+        CompoundExpressionNodeChild::TextNode(text) => self.generate_text(text),
+        CompoundExpressionNodeChild::RawText(text) => self.push(text),
+      }
+    }
+  }
+
+  fn gen_expression_as_property_key(&mut self, key: &ExpressionNode) {
+    match key {
+      ExpressionNode::CompoundExpressionNode(exp) => {
+        self.push("[");
+        self.gen_compound_expression(exp);
+        self.push("]");
+      },
+      ExpressionNode::SimpleExpressionNode(exp) if exp.is_static => {
+        if is_simple_identifier(&exp.content) {
+          self.push(&exp.content);
+        } else {
+          self.push(&to_js_string_literal(&exp.content));
+        }
+      },
+      ExpressionNode::SimpleExpressionNode(exp) => {
+        self.push(&format!("[{}]", exp.content));
+      },
+    }
+  }
+
+  fn gen_prop_expression(&mut self, props: &PropExpression) {
+    match props {
+      PropExpression::ObjectExpression(obj) => self.gen_object_expression(obj),
+      PropExpression::CallExpression(call) => self.gen_call_expression(call),
+      PropExpression::SimpleExpression(exp) => self.gen_expression(exp),
+    }
+  }
+
+  fn gen_js_child(&mut self, node: &JsChildNode) {
+    match node {
+      JsChildNode::VnodeCall(vnode) => self.gen_vnode_call(vnode),
+      JsChildNode::CallExpression(call) => self.gen_call_expression(call),
+      JsChildNode::ObjectExpression(obj) => self.gen_object_expression(obj),
+      JsChildNode::FunctionExpression(func) => self.gen_function_expression(func),
+      JsChildNode::ExpressionNode(exp) => self.gen_expression(exp),
+      JsChildNode::ArrayExpression(arr) => self.gen_array_expression(arr),
+      JsChildNode::ConditionalExpression(exp) => self.gen_conditional_expression(exp),
+      JsChildNode::CacheExpression(cache) => self.gen_cache_expression(cache),
+    }
+  }
+
+  fn gen_call_expression(&mut self, call: &CallExpression) {
+    if self.pure {
+      self.push(PURE_ANNOTATION);
+    }
+    let callee = self.helper(&call.callee);
+    self.push(&format!("{}(", callee));
+    for (index, arg) in call.arguments.iter().enumerate() {
+      match arg {
+        CallExpressionArgument::RawText(text) => self.push(text),
+        CallExpressionArgument::JsChildNode(node) => self.gen_js_child(node),
+        CallExpressionArgument::PropExpression(props) => self.gen_prop_expression(props),
+        CallExpressionArgument::TemplateChildNode(node) => self.gen_template_child(node),
+        CallExpressionArgument::TemplateChildNodes(nodes) => self.gen_template_children(nodes),
+      }
+      if index < call.arguments.len() - 1 {
+        self.push(", ");
+      }
+    }
+    self.push(")");
+  }
+
+  fn gen_render_slot_call(&mut self, call: &RenderSlotCall) {
+    let callee = self.helper(&call.call);
+    self.push(&format!("{}(", callee));
+    for (index, arg) in call.args.iter().enumerate() {
+      match arg {
+        RenderSlotCallArgs::RawText(text) => self.push(text),
+        RenderSlotCallArgs::SimpleExpressionNode(exp) => self.gen_simple_expression(exp),
+        RenderSlotCallArgs::CompoundExpressionNode(exp) => self.gen_compound_expression(exp),
+        RenderSlotCallArgs::ObjectProperty(obj) => self.gen_object_expression(obj),
+        RenderSlotCallArgs::TemplateChildNode(node) => self.gen_template_child(node),
+        RenderSlotCallArgs::PropExpression(props) => self.gen_prop_expression(props),
+        RenderSlotCallArgs::FunctionExpression(func) => self.gen_function_expression(func),
+      }
+      if index < call.args.len() - 1 {
+        self.push(", ");
+      }
+    }
+    self.push(")");
+  }
+
+  fn gen_object_expression(&mut self, obj: &ObjectExpression) {
+    if obj.properties.is_empty() {
+      self.push("{}");
+      return;
+    }
+    let multilines = obj.properties.len() > 1 || obj.properties.iter().any(|p| {
+      !matches!(&*p.value, JsChildNode::ExpressionNode(ExpressionNode::SimpleExpressionNode(_)))
+    });
+    self.push(if multilines { "{" } else { "{ " });
+    if multilines {
+      self.indent();
+    }
+    for (index, Property { key, value }) in obj.properties.iter().enumerate() {
+      self.gen_expression_as_property_key(key);
+      self.push(": ");
+      self.gen_js_child(value);
+      if index < obj.properties.len() - 1 {
+        self.push(",");
+        self.newline();
+      }
+    }
+    if multilines {
+      self.deindent(false);
+    }
+    self.push(if multilines { "}" } else { " }" });
+  }
+
+  fn gen_array_expression(&mut self, arr: &ArrayExpression) {
+    let multilines = arr.elements.len() > 3 || arr.elements.iter().any(|e| !matches!(
+      e,
+      ElementValue::RawText(_) | ElementValue::ExpressionNode(JsChildNode::ExpressionNode(_))
+    ));
+    self.push("[");
+    if multilines {
+      self.indent();
+    }
+    for (index, element) in arr.elements.iter().enumerate() {
+      match element {
+        ElementValue::RawText(text) => self.push(text),
+        ElementValue::ExpressionNode(node) => self.gen_js_child(node),
+      }
+      if index < arr.elements.len() - 1 {
+        if multilines {
+          self.push(",");
+          self.newline();
+        } else {
+          self.push(", ");
+        }
+      }
+    }
+    if multilines {
+      self.deindent(false);
+    }
+    self.push("]");
+  }
+
+  fn gen_function_expression(&mut self, func: &FunctionExpression) {
+    if func.is_slot {
+      let with_ctx = self.helper(WITH_CTX);
+      self.push(&format!("{}(", with_ctx));
+    }
+    self.push("(");
+    match &func.params {
+      Some(Params::RawText(text)) => self.push(text),
+      Some(Params::ExpressionNode(exp)) => self.gen_expression(exp),
+      None => {},
+    }
+    self.push(") => ");
+    if func.newline {
+      self.push("{");
+      self.indent();
+    }
+    if let Some(returns) = &func.returns {
+      if func.newline {
+        self.push("return ");
+      }
+      match returns {
+        Returns::TemplateChildNode(node) => self.gen_template_child(node),
+        Returns::TemplateChildNodes(nodes) => self.gen_template_children(nodes),
+        Returns::JsChildNode(node) => self.gen_js_child(node),
+        Returns::Children(nodes) => self.gen_children_array(nodes),
+      }
+    }
+    if func.newline {
+      self.deindent(false);
+      self.push("}");
+    }
+    if func.is_slot {
+      if func.is_non_scoped_slot == Some(true) {
+        self.push(", undefined, true");
+      }
+      self.push(")");
+    }
+  }
+
+  fn gen_conditional_expression(&mut self, exp: &ConditionalExpression) {
+    match &*exp.test {
+      JsChildNode::ExpressionNode(test) => self.gen_conditional_test(test),
+      test => {
+        self.push("(");
+        self.gen_js_child(test);
+        self.push(")");
+      },
+    }
+    if exp.newline {
+      self.indent();
+    }
+    self.indent_level += 1;
+    if !exp.newline {
+      self.push(" ");
+    }
+    self.push("? ");
+    self.gen_js_child(&exp.consequent);
+    self.indent_level -= 1;
+    if exp.newline {
+      self.newline();
+    } else {
+      self.push(" ");
+    }
+    self.push(": ");
+    let is_nested = matches!(&*exp.alternate, JsChildNode::ConditionalExpression(_));
+    if !is_nested {
+      self.indent_level += 1;
+    }
+    self.gen_js_child(&exp.alternate);
+    if !is_nested {
+      self.indent_level -= 1;
+    }
+    if exp.newline {
+      self.deindent(true);
+    }
+  }
+
+  fn gen_cache_expression(&mut self, cache: &CacheExpression) {
+    self.push(&format!("_cache[{}] || (", cache.index));
+    if cache.is_vnode {
+      let set_block_tracking = self.helper(SET_BLOCK_TRACKING);
+      self.indent();
+      self.push(&format!("{}(-1),", set_block_tracking));
+      self.newline();
+    }
+    self.push(&format!("_cache[{}] = ", cache.index));
+    self.gen_js_child(&cache.value);
+    if cache.is_vnode {
+      let set_block_tracking = self.helper(SET_BLOCK_TRACKING);
+      self.push(",");
+      self.newline();
+      self.push(&format!("{}(1),", set_block_tracking));
+      self.newline();
+      self.push(&format!("_cache[{}]", cache.index));
+      self.deindent(false);
+    }
+    self.push(")");
+  }
+
+  fn gen_memo_expression(&mut self, memo: &MemoExpression) {
+    let with_memo = self.helper(WITH_MEMO);
+    self.push(&format!("{}(", with_memo));
+    self.gen_expression(&memo.deps);
+    self.push(", () => ");
+    self.gen_vnode_call(&memo.value);
+    self.push(&format!(", _cache, {})", memo.index));
+  }
+
+  fn gen_template_child(&mut self, node: &TemplateChildNode) {
+    match node {
+      TemplateChildNode::ElementNode(el) => self.gen_element(el, false),
+      TemplateChildNode::TextNode(text) => self.generate_text(text),
+      TemplateChildNode::CommentNode(comment) => self.gen_comment(comment),
+      TemplateChildNode::InterpolationNode(n) => self.gen_interpolation(n),
+      TemplateChildNode::CompoundExpressionNode(exp) => self.gen_compound_expression(exp),
+      TemplateChildNode::IfNode(if_node) => self.gen_if(if_node),
+      TemplateChildNode::ForNode(for_node) => self.gen_for(for_node, None),
+      TemplateChildNode::TextCallNode(text_call) => self.gen_text_call(text_call),
+    }
+  }
+
+  fn gen_template_children(&mut self, nodes: &[TemplateChildNode]) {
+    self.push("[");
+    for (index, node) in nodes.iter().enumerate() {
+      self.gen_template_child(node);
+      if index < nodes.len() - 1 {
+        self.push(", ");
+      }
+    }
+    self.push("]");
+  }
+}
+
+/// `toDisplayString: _toDisplayString, ...`
+fn alias_helpers<T: AsRef<str>>(helpers: impl Iterator<Item = T>) -> String {
+  helpers
+    .map(|h| format!("{}: _{}", h.as_ref(), h.as_ref()))
+    .collect::<Vec<_>>()
+    .join(", ")
+}
+
+/// 去掉末尾为空的参数后的参数个数, 中间为空的参数用 null 占位
+fn vnode_arg_count(vnode: &VnodeCall) -> usize {
+  if vnode.dynamic_props.is_some() {
+    5
+  } else if vnode.patch_flag.is_some() {
+    4
+  } else if vnode.children.is_some() {
+    3
+  } else if vnode.props.is_some() {
+    2
+  } else {
+    1
+  }
+}
+
+/// v-for 生成的 Fragment 是否稳定以及对应的 patch flag
+fn for_fragment_flag(for_node: &ForNode) -> (bool, PatchFlags) {
+  // 数据源为常量时, 列表的结构不会变化
+  let is_stable = matches!(
+    &for_node.source,
+    ExpressionNode::SimpleExpressionNode(exp) if exp.constant_type > ConstantTypes::NotConstant
+  );
+  let children = for_node.children.borrow().clone();
+  let is_keyed = match &children[..] {
+    [child] => match &*child.borrow() {
+      Node::ElementNode(el) => has_key_prop(&el.props.borrow()),
+      _ => false,
+    },
+    _ => false,
+  };
+  let flag = if is_stable {
+    PatchFlags::STABLE_FRAGMENT
+  } else if is_keyed {
+    PatchFlags::KEYED_FRAGMENT
+  } else {
+    PatchFlags::UNKEYED_FRAGMENT
+  };
+  (is_stable, flag)
+}
+
+/// 每次迭代渲染的子节点, `<template v-for>` 渲染模板的子节点
+pub(crate) fn for_item_children(for_node: &ForNode) -> Children {
+  if let [child] = &for_node.children.borrow().clone()[..] {
+    if let Node::ElementNode(el) = &*child.borrow() {
+      if el.tag_type == ElementTagType::TemplateNode {
+        return el.children.clone();
+      }
+    }
+  }
+  for_node.children.clone()
+}
+
+/// `<template v-for>` 上的 key 加在每次迭代渲染的节点上
+pub(crate) fn for_item_key(for_node: &ForNode) -> Option<Property> {
+  let [child] = &for_node.children.borrow().clone()[..] else {
+    return None;
+  };
+  let Node::ElementNode(el) = &*child.borrow() else {
+    return None;
+  };
+  if el.tag_type != ElementTagType::TemplateNode {
+    return None;
+  }
+  let props = el.props.borrow();
+  let value = props.iter().find_map(|p| match p {
+    Prop::Normal(attr) if attr.name == "key" => Some(JsChildNode::ExpressionNode(
+      ExpressionNode::SimpleExpressionNode(SimpleExpressionNode {
+        content: attr.value.as_ref().map(|v| v.content.clone()).unwrap_or_default(),
+        is_static: true,
+        constant_type: ConstantTypes::CanStringify,
+        loc: attr.loc,
+      })
+    )),
+    Prop::Directive(dir) if has_key_prop(std::slice::from_ref(p)) => {
+      dir.exp.clone().map(JsChildNode::ExpressionNode)
+    },
+    _ => None,
+  })?;
+  Some(Property {
+    key: ExpressionNode::SimpleExpressionNode(SimpleExpressionNode {
+      content: "key".to_string(),
+      is_static: true,
+      constant_type: ConstantTypes::CanStringify,
+      loc: Default::default(),
+    }),
+    value: Box::new(value),
+  })
+}
+
+fn is_text_like(node: &Node) -> bool {
+  matches!(
+    node,
+    Node::Text(_) | Node::Interpolation(_) | Node::CompoundExpression(_) | Node::SimpleExpression(_)
+  )
+}
+
+pub(crate) fn key_property(key: usize) -> Property {
+  Property {
+    key: ExpressionNode::SimpleExpressionNode(SimpleExpressionNode {
+      content: "key".to_string(),
+      is_static: true,
+      constant_type: ConstantTypes::CanStringify,
+      loc: Default::default(),
+    }),
+    value: Box::new(JsChildNode::ExpressionNode(ExpressionNode::SimpleExpressionNode(
+      SimpleExpressionNode {
+        content: key.to_string(),
+        is_static: false,
+        constant_type: ConstantTypes::CanHoist,
+        loc: Default::default(),
+      }
+    ))),
+  }
+}
+
+/// `(item, __, index)`, 省略的中间参数用下划线占位
+pub(crate) fn for_loop_params(for_node: &ForNode) -> String {
+  let aliases = [&for_node.value_alias, &for_node.key_alias, &for_node.object_index_alias];
+  let len = aliases.iter().rposition(|a| a.is_some()).map_or(0, |i| i + 1);
+  aliases[..len]
+    .iter()
+    .enumerate()
+    .map(|(index, alias)| match alias {
+      Some(ExpressionNode::SimpleExpressionNode(alias)) => alias.content.clone(),
+      _ => "_".repeat(index + 1),
+    })
+    .collect::<Vec<_>>()
+    .join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+  use std::{cell::RefCell, rc::Rc};
+
+  use crate::{
+    Parser, TransformRunner, TransformContext, TransformVIf, TransformVFor, TrackSlotScopes,
+    TrackVForSlotScopes, Expression, TransformElement, TransformText, CodeGen, CodegenOptions,
+    CodegenMode, BindingMetadata, BindingTypes, DirectiveTransform, DirectiveTransformRes,
+    DirectiveProp, Node, Property, JsChildNode, ExpressionNode, SimpleExpressionNode, ConstantTypes,
+  };
+
+  fn compile(source: &str, hoist_static: bool) -> String {
+    let code = compile_with_options(source, hoist_static, CodegenOptions::default());
+    // 只比较渲染函数本身, 前导代码由单独的测试覆盖
+    code[code.find("function render").unwrap()..].to_string()
+  }
+
+  fn compile_with_options(source: &str, hoist_static: bool, options: CodegenOptions) -> String {
+    let mut parser = Parser::new(source);
+    let ast = Rc::new(RefCell::new(parser.parse()));
+    let mut ctx = TransformContext::new("hello.vue".to_string());
+    ctx.hoist_static = hoist_static;
+    ctx.prefix_identifiers = options.prefix_identifiers || options.mode == CodegenMode::Module;
+    ctx.inline = options.inline;
+    if let Some(binding_metadata) = &options.binding_metadata {
+      ctx.binding_metadata = binding_metadata.clone();
+    }
+    let mut runner = TransformRunner::new(
+      vec![
+        Box::new(TransformVIf),
+        Box::new(TransformVFor),
+        Box::new(TrackVForSlotScopes),
+        Box::new(TrackSlotScopes),
+        Box::new(Expression {}),
+        Box::new(TransformText),
+        Box::new(TransformElement),
+      ],
+      ctx,
+    );
+    runner.transform(ast.clone());
+    let ast = ast.borrow();
+    let mut code_gen = CodeGen::new(&ast, options);
+    code_gen.generate();
+    code_gen.code
+  }
+
+  #[test]
+  fn test_gen_element_with_interpolation() {
+    assert_eq!(
+      compile("<div>{{ msg }}</div>", false),
+      "function render(_ctx, _cache) {\n  return (_openBlock(), _createElementBlock(\"div\", null, _toDisplayString(_ctx.msg), 1 /* TEXT */))\n}"
+    );
+  }
+
+  #[test]
+  fn test_gen_hoisted_static() {
+    let code = compile_with_options(
+      "<div><span class=\"a\">hi</span>{{ msg }}</div>",
+      true,
+      CodegenOptions { mode: CodegenMode::Module, ..Default::default() },
+    );
+    assert!(code.contains(
+      "const _hoisted_1 = /*#__PURE__*/_createElementVNode(\"span\", { class: \"a\" }, \"hi\", -1 /* HOISTED */)\n\nexport function render(_ctx, _cache) {"
+    ));
+    assert!(code.contains("_hoisted_1,"));
+
+    let code = compile_with_options(
+      "<div><span :id=\"a\"></span>hello</div>",
+      true,
+      CodegenOptions { mode: CodegenMode::Module, ..Default::default() },
+    );
+    assert!(code.contains("const _hoisted_1 = /*#__PURE__*/_createTextVNode(\"hello\")\n"));
+    assert!(code.contains("8 /* PROPS */, [\"id\"]),\n    _hoisted_1\n  ]))"));
+  }
+
+  #[test]
+  fn test_gen_global_call_not_hoisted() {
+    assert_eq!(compile("<div><span>{{ Date.now() }}</span><b :title=\"Math.random()\"></b><i>{{ 1 + 2 }}</i></div>", true), "function render(_ctx, _cache) {\n  return (_openBlock(), _createElementBlock(\"div\", null, [\n    _createElementVNode(\"span\", null, _toDisplayString(Date.now()), 1 /* TEXT */),\n    _createElementVNode(\"b\", { title: Math.random() }, null, 8 /* PROPS */, [\"title\"]),\n    _hoisted_1\n  ]))\n}");
+  }
+
+  #[test]
+  fn test_gen_v_if() {
+    assert_eq!(
+      compile("<div v-if=\"ok\">a</div><p v-else-if=\"b\">b</p><span v-else>c</span>", false),
+      "function render(_ctx, _cache) {\n  return (_ctx.ok)\n    ? (_openBlock(), _createElementBlock(\"div\", { key: 0 }, \"a\"))\n    : (_ctx.b)\n      ? (_openBlock(), _createElementBlock(\"p\", { key: 1 }, \"b\"))\n      : (_openBlock(), _createElementBlock(\"span\", { key: 2 }, \"c\"))\n}"
+    );
+  }
+
+  #[test]
+  fn test_gen_v_if_without_else() {
+    assert_eq!(
+      compile("<div v-if=\"ok\">a</div>", false),
+      "function render(_ctx, _cache) {\n  return (_ctx.ok)\n    ? (_openBlock(), _createElementBlock(\"div\", { key: 0 }, \"a\"))\n    : _createCommentVNode(\"v-if\", true)\n}"
+    );
+  }
+
+  #[test]
+  fn test_gen_event_listener_flags() {
+    assert_eq!(
+      compile("<form><input @input=\"onInput\"/><button @click=\"submit\">go</button></form>", true),
+      "function render(_ctx, _cache) {\n  return (_openBlock(), _createElementBlock(\"form\", null, [\n    _createElementVNode(\"input\", { onInput: _ctx.onInput }, null, 40 /* PROPS, HYDRATE_EVENTS */, [\"onInput\"]),\n    _createElementVNode(\"button\", { onClick: _ctx.submit }, \"go\", 8 /* PROPS */, [\"onClick\"])\n  ]))\n}"
+    );
+  }
+
+  #[test]
+  fn test_gen_dynamic_args() {
+    assert_eq!(
+      compile("<div :[key]=\"value\" @[event]=\"handler\"></div>", false),
+      "function render(_ctx, _cache) {\n  return (_openBlock(), _createElementBlock(\"div\", _normalizeProps({\n    [_ctx.key || \"\"]: _ctx.value,\n    [_toHandlerKey(_ctx.event)]: _ctx.handler\n  }), null, 16 /* FULL_PROPS */))\n}"
+    );
+    assert_eq!(
+      compile("<div :id=\"id\" v-bind=\"attrs\"></div>", false),
+      "function render(_ctx, _cache) {\n  return (_openBlock(), _createElementBlock(\"div\", _mergeProps({ id: _ctx.id }, _ctx.attrs), null, 16 /* FULL_PROPS */))\n}"
+    );
+    assert_eq!(
+      compile("<div v-bind=\"attrs\"></div>", false),
+      "function render(_ctx, _cache) {\n  return (_openBlock(), _createElementBlock(\"div\", _normalizeProps(_guardReactiveProps(_ctx.attrs)), null, 16 /* FULL_PROPS */))\n}"
+    );
+  }
+
+  #[test]
+  fn test_gen_class_style() {
+    assert_eq!(compile("<div class=\"foo\" :class=\"bar\"></div>", false), "function render(_ctx, _cache) {\n  return (_openBlock(), _createElementBlock(\"div\", {\n    class: _normalizeClass([\"foo\", _ctx.bar])\n  }, null, 2 /* CLASS */))\n}");
+    assert_eq!(compile("<div style=\"color: red; background: url(a;b)\"></div>", false), "function render(_ctx, _cache) {\n  return (_openBlock(), _createElementBlock(\"div\", { style: {\"color\":\"red\",\"background\":\"url(a;b)\"} }))\n}");
+    assert_eq!(compile("<div style=\"color: red\" :style=\"s\"></div>", false), "function render(_ctx, _cache) {\n  return (_openBlock(), _createElementBlock(\"div\", {\n    style: _normalizeStyle([{\"color\":\"red\"}, _ctx.s])\n  }, null, 4 /* STYLE */))\n}");
+    assert_eq!(compile("<Comp :class=\"c\" :style=\"s\"></Comp>", false), "function render(_ctx, _cache) {\n  const _component_Comp = _resolveComponent(\"Comp\")\n\n  return (_openBlock(), _createBlock(_component_Comp, {\n    class: _normalizeClass(_ctx.c),\n    style: _normalizeStyle(_ctx.s)\n  }, null, 8 /* PROPS */, [\"class\", \"style\"]))\n}");
+    assert_eq!(compile("<div :class=\"c\" :[key]=\"v\"></div>", false), "function render(_ctx, _cache) {\n  return (_openBlock(), _createElementBlock(\"div\", _normalizeProps({\n    class: _ctx.c,\n    [_ctx.key || \"\"]: _ctx.v\n  }), null, 16 /* FULL_PROPS */))\n}");
+  }
+
+  #[test]
+  fn test_gen_attribute_without_value() {
+    assert_eq!(compile("<div style disabled></div>", false), "function render(_ctx, _cache) {\n  return (_openBlock(), _createElementBlock(\"div\", {\n    style: {},\n    disabled: \"\"\n  }))\n}");
+  }
+
+  #[test]
+  fn test_gen_v_on_object() {
+    assert_eq!(compile("<div v-on=\"handlers\"></div>", false), "function render(_ctx, _cache) {\n  return (_openBlock(), _createElementBlock(\"div\", _toHandlers(_ctx.handlers), null, 16 /* FULL_PROPS */))\n}");
+  }
+
+  #[test]
+  fn test_gen_dedupe_props() {
+    assert_eq!(compile("<div @click=\"a\" v-on:click=\"b\"></div>", false), "function render(_ctx, _cache) {\n  return (_openBlock(), _createElementBlock(\"div\", {\n    onClick: [_ctx.a, _ctx.b]\n  }, null, 8 /* PROPS */, [\"onClick\"]))\n}");
+    assert_eq!(compile("<div class=\"a\" :class=\"b\" v-bind=\"obj\" v-bind:class=\"c\"></div>", false), "function render(_ctx, _cache) {\n  return (_openBlock(), _createElementBlock(\"div\", _mergeProps({\n    class: [\"a\", _ctx.b]\n  }, _ctx.obj, { class: _ctx.c }), null, 16 /* FULL_PROPS */))\n}");
+  }
+
+  #[test]
+  fn test_gen_root() {
+    assert_eq!(compile("<div></div><span></span>", false), "function render(_ctx, _cache) {\n  return (_openBlock(), _createElementBlock(_Fragment, null, [\n    _createElementVNode(\"div\"),\n    _createElementVNode(\"span\")\n  ], 64 /* STABLE_FRAGMENT */))\n}");
+    assert_eq!(compile("<!-- c --><div></div>", false), "function render(_ctx, _cache) {\n  return (_openBlock(), _createElementBlock(_Fragment, null, [\n    _createCommentVNode(\" c \"),\n    _createElementVNode(\"div\")\n  ], 2112 /* STABLE_FRAGMENT, DEV_ROOT_FRAGMENT */))\n}");
+    assert_eq!(compile("{{ msg }}", false), "function render(_ctx, _cache) {\n  return _toDisplayString(_ctx.msg)\n}");
+    assert_eq!(compile("", false), "function render(_ctx, _cache) {\n  return null\n}");
+    assert_eq!(compile("\n  <div>hi</div>\n", false), "function render(_ctx, _cache) {\n  return (_openBlock(), _createElementBlock(\"div\", null, \"hi\"))\n}");
+    assert_eq!(compile("\n<div></div>\n<span></span> <b></b>\n", false), "function render(_ctx, _cache) {\n  return (_openBlock(), _createElementBlock(_Fragment, null, [\n    _createElementVNode(\"div\"),\n    _createElementVNode(\"span\"),\n    _createTextVNode(),\n    _createElementVNode(\"b\")\n  ], 64 /* STABLE_FRAGMENT */))\n}");
+    assert_eq!(compile("\n  \n", false), "function render(_ctx, _cache) {\n  return null\n}");
+  }
+
+  #[test]
+  fn test_gen_text_call() {
+    assert_eq!(compile("hello {{ msg }}", false), "function render(_ctx, _cache) {\n  return \"hello \" + _toDisplayString(_ctx.msg)\n}");
+    assert_eq!(compile("<div>a {{ b }}</div>", false), "function render(_ctx, _cache) {\n  return (_openBlock(), _createElementBlock(\"div\", null, \"a \" + _toDisplayString(_ctx.b), 1 /* TEXT */))\n}");
+    assert_eq!(compile("<div>a <b></b>{{ c }}</div>", false), "function render(_ctx, _cache) {\n  return (_openBlock(), _createElementBlock(\"div\", null, [\n    _createTextVNode(\"a \"),\n    _createElementVNode(\"b\"),\n    _createTextVNode(_toDisplayString(_ctx.c), 1 /* TEXT */)\n  ]))\n}");
+    assert_eq!(compile("<Comp>hi</Comp>", false), "function render(_ctx, _cache) {\n  const _component_Comp = _resolveComponent(\"Comp\")\n\n  return (_openBlock(), _createBlock(_component_Comp, null, {\n    default: _withCtx(() => [\n      _createTextVNode(\"hi\")\n    ]),\n    _: 1 /* STABLE */\n  }))\n}");
+  }
+
+  #[test]
+  fn test_gen_template_fragment() {
+    assert_eq!(compile("<div><template v-if=\"a\"><div></div><span></span></template></div>", false), "function render(_ctx, _cache) {\n  return (_openBlock(), _createElementBlock(\"div\", null, [\n    (_ctx.a)\n      ? (_openBlock(), _createElementBlock(_Fragment, { key: 0 }, [\n          _createElementVNode(\"div\"),\n          _createElementVNode(\"span\")\n        ], 64 /* STABLE_FRAGMENT */))\n      : _createCommentVNode(\"v-if\", true)\n  ]))\n}");
+    assert_eq!(compile("<div><template v-for=\"i in list\"><span>{{ i }}</span><b></b></template></div>", false), "function render(_ctx, _cache) {\n  return (_openBlock(), _createElementBlock(\"div\", null, [\n    (_openBlock(true), _createElementBlock(_Fragment, null, _renderList(_ctx.list, (i) => {\n      return (_openBlock(), _createElementBlock(_Fragment, null, [\n        _createElementVNode(\"span\", null, _toDisplayString(i), 1 /* TEXT */),\n        _createElementVNode(\"b\")\n      ], 64 /* STABLE_FRAGMENT */))\n    }), 256 /* UNKEYED_FRAGMENT */))\n  ]))\n}");
+    assert_eq!(compile("<div><template v-for=\"i in list\" :key=\"i\"><span>{{ i }}</span></template></div>", false), "function render(_ctx, _cache) {\n  return (_openBlock(), _createElementBlock(\"div\", null, [\n    (_openBlock(true), _createElementBlock(_Fragment, null, _renderList(_ctx.list, (i) => {\n      return (_openBlock(), _createElementBlock(\"span\", { key: i }, _toDisplayString(i), 1 /* TEXT */))\n    }), 128 /* KEYED_FRAGMENT */))\n  ]))\n}");
+    assert_eq!(compile("<div><template v-for=\"i in list\" :key=\"i\"><span></span><b></b></template></div>", false), "function render(_ctx, _cache) {\n  return (_openBlock(), _createElementBlock(\"div\", null, [\n    (_openBlock(true), _createElementBlock(_Fragment, null, _renderList(_ctx.list, (i) => {\n      return (_openBlock(), _createElementBlock(_Fragment, { key: i }, [\n        _createElementVNode(\"span\"),\n        _createElementVNode(\"b\")\n      ], 64 /* STABLE_FRAGMENT */))\n    }), 128 /* KEYED_FRAGMENT */))\n  ]))\n}");
+    assert_eq!(compile("<div><template><b></b></template></div>", false), "function render(_ctx, _cache) {\n  return (_openBlock(), _createElementBlock(\"div\", null, [\n    (_openBlock(), _createElementBlock(_Fragment, null, [\n      _createElementVNode(\"b\")\n    ], 64 /* STABLE_FRAGMENT */))\n  ]))\n}");
+    assert_eq!(compile("<template><b></b><i></i></template>", false), "function render(_ctx, _cache) {\n  return (_openBlock(), _createElementBlock(_Fragment, null, [\n    _createElementVNode(\"b\"),\n    _createElementVNode(\"i\")\n  ], 64 /* STABLE_FRAGMENT */))\n}");
+  }
+
+  #[test]
+  fn test_gen_component_slots() {
+    assert_eq!(compile("<Comp><template #foo=\"{ a }\"><span>{{ a }}</span></template><b></b></Comp>", false), "function render(_ctx, _cache) {\n  const _component_Comp = _resolveComponent(\"Comp\")\n\n  return (_openBlock(), _createBlock(_component_Comp, null, {\n    foo: _withCtx(({ a }) => [\n      _createElementVNode(\"span\", null, _toDisplayString(a), 1 /* TEXT */)\n    ]),\n    default: _withCtx(() => [\n      _createElementVNode(\"b\")\n    ]),\n    _: 1 /* STABLE */\n  }))\n}");
+    assert_eq!(compile("<Comp v-slot=\"{ a }\"><span>{{ a }}</span></Comp>", false), "function render(_ctx, _cache) {\n  const _component_Comp = _resolveComponent(\"Comp\")\n\n  return (_openBlock(), _createBlock(_component_Comp, null, {\n    default: _withCtx(({ a }) => [\n      _createElementVNode(\"span\", null, _toDisplayString(a), 1 /* TEXT */)\n    ]),\n    _: 1 /* STABLE */\n  }))\n}");
+    assert_eq!(compile("<div v-for=\"item in list\"><Comp><span>{{ item }}</span></Comp></div>", false), "function render(_ctx, _cache) {\n  const _component_Comp = _resolveComponent(\"Comp\")\n\n  return (_openBlock(true), _createElementBlock(_Fragment, null, _renderList(_ctx.list, (item) => {\n    return (_openBlock(), _createElementBlock(\"div\", null, [\n      _createVNode(_component_Comp, null, {\n        default: _withCtx(() => [\n          _createElementVNode(\"span\", null, _toDisplayString(item), 1 /* TEXT */)\n        ]),\n        _: 2 /* DYNAMIC */\n      }, 1024 /* DYNAMIC_SLOTS */)\n    ]))\n  }), 256 /* UNKEYED_FRAGMENT */))\n}");
+    assert_eq!(compile("<Comp><template #[name]><b></b></template></Comp>", false), "function render(_ctx, _cache) {\n  const _component_Comp = _resolveComponent(\"Comp\")\n\n  return (_openBlock(), _createBlock(_component_Comp, null, {\n    [_ctx.name]: _withCtx(() => [\n      _createElementVNode(\"b\")\n    ]),\n    _: 2 /* DYNAMIC */\n  }, 1024 /* DYNAMIC_SLOTS */))\n}");
+    assert_eq!(compile("<Comp><template v-if=\"a\" #foo><b></b></template><template v-else-if=\"b\" #bar>b</template><template v-else #baz>c</template></Comp>", false), "function render(_ctx, _cache) {\n  const _component_Comp = _resolveComponent(\"Comp\")\n\n  return (_openBlock(), _createBlock(_component_Comp, null, _createSlots({ _: 2 /* DYNAMIC */ }, [\n    (_ctx.a)\n      ? {\n          name: \"foo\",\n          fn: _withCtx(() => [\n            _createElementVNode(\"b\")\n          ]),\n          key: \"0\"\n        }\n      : (_ctx.b)\n        ? {\n            name: \"bar\",\n            fn: _withCtx(() => [\n              _createTextVNode(\"b\")\n            ]),\n            key: \"1\"\n          }\n        : {\n            name: \"baz\",\n            fn: _withCtx(() => [\n              _createTextVNode(\"c\")\n            ]),\n            key: \"2\"\n          }\n  ]), 1024 /* DYNAMIC_SLOTS */))\n}");
+    assert_eq!(compile("<Comp><template v-for=\"(s, i) in list\" #[s]=\"{ x }\">{{ x }}{{ i }}</template></Comp>", false), "function render(_ctx, _cache) {\n  const _component_Comp = _resolveComponent(\"Comp\")\n\n  return (_openBlock(), _createBlock(_component_Comp, null, _createSlots({ _: 2 /* DYNAMIC */ }, [\n    _renderList(_ctx.list, (s, i) => {\n      return {\n        name: s,\n        fn: _withCtx(({ x }) => [\n          _createTextVNode(_toDisplayString(x) + _toDisplayString(i), 1 /* TEXT */)\n        ])\n      }\n    })\n  ]), 1024 /* DYNAMIC_SLOTS */))\n}");
+    assert_eq!(compile("<Comp><template #default><template><b></b></template></template></Comp>", false), "function render(_ctx, _cache) {\n  const _component_Comp = _resolveComponent(\"Comp\")\n\n  return (_openBlock(), _createBlock(_component_Comp, null, {\n    default: _withCtx(() => [\n      (_openBlock(), _createElementBlock(_Fragment, null, [\n        _createElementVNode(\"b\")\n      ], 64 /* STABLE_FRAGMENT */))\n    ]),\n    _: 1 /* STABLE */\n  }))\n}");
+  }
+
+  #[test]
+  fn test_gen_template_ref() {
+    assert_eq!(compile("<div><span ref=\"el\"></span></div>", false), "function render(_ctx, _cache) {\n  return (_openBlock(), _createElementBlock(\"div\", null, [\n    _createElementVNode(\"span\", { ref: \"el\" }, null, 512 /* NEED_PATCH */)\n  ]))\n}");
+    assert_eq!(compile("<div v-for=\"item in list\" :ref=\"item.ref\"></div>", false), "function render(_ctx, _cache) {\n  return (_openBlock(true), _createElementBlock(_Fragment, null, _renderList(_ctx.list, (item) => {\n    return (_openBlock(), _createElementBlock(\"div\", {\n      ref_for: true,\n      ref: item.ref\n    }, null, 512 /* NEED_PATCH */))\n  }), 256 /* UNKEYED_FRAGMENT */))\n}");
+  }
+
+  #[test]
+  fn test_gen_inline_setup_ref() {
+    let mut binding_metadata = BindingMetadata::new();
+    binding_metadata.insert("inputEl".to_string(), BindingTypes::SetupRef);
+    let code = compile_with_options("<div ref=\"inputEl\"></div>", false, CodegenOptions {
+      inline: true,
+      binding_metadata: Some(binding_metadata),
+      ..Default::default()
+    });
+    assert_eq!(&code[code.find("function render").unwrap()..], "function render(_ctx, _cache) {\n  return (_openBlock(), _createElementBlock(\"div\", {\n    ref_key: \"inputEl\",\n    ref: inputEl\n  }, null, 512 /* NEED_PATCH */))\n}");
+  }
+
+  #[test]
+  fn test_gen_v_for() {
+    assert_eq!(
+      compile("<div v-for=\"(item, i) in list\">{{ item }}</div>", false),
+      "function render(_ctx, _cache) {\n  return (_openBlock(true), _createElementBlock(_Fragment, null, _renderList(_ctx.list, (item, i) => {\n    return (_openBlock(), _createElementBlock(\"div\", null, _toDisplayString(item), 1 /* TEXT */))\n  }), 256 /* UNKEYED_FRAGMENT */))\n}"
+    );
+  }
+
+  #[test]
+  fn test_gen_component_assets() {
+    assert_eq!(
+      compile("<MyComp></MyComp>", false),
+      "function render(_ctx, _cache) {\n  const _component_MyComp = _resolveComponent(\"MyComp\")\n\n  return (_openBlock(), _createBlock(_component_MyComp))\n}"
+    );
+  }
+
+  #[test]
+  fn test_module_mode_preamble() {
+    let code = compile_with_options(
+      "<div>{{ msg }}</div>",
+      false,
+      CodegenOptions {
+        mode: CodegenMode::Module,
+        runtime_module_name: "@shell/vue".to_string(),
+        ..Default::default()
+      },
+    );
+    assert!(code.starts_with(
+      "import { openBlock as _openBlock, createElementBlock as _createElementBlock, toDisplayString as _toDisplayString } from \"@shell/vue\"\n\nexport function render(_ctx, _cache) {"
+    ));
+  }
+
+  #[test]
+  fn test_function_mode_preamble() {
+    let code = compile_with_options(
+      "<div>{{ msg }}</div>",
+      false,
+      CodegenOptions { runtime_global_name: "SharedVue".to_string(), ..Default::default() },
+    );
+    assert!(code.starts_with(
+      "const { openBlock: _openBlock, createElementBlock: _createElementBlock, toDisplayString: _toDisplayString } = SharedVue\n\nreturn function render(_ctx, _cache) {"
+    ));
+  }
+
+  #[test]
+  fn test_function_mode_with_block() {
+    let code = compile_with_options(
+      "<div>{{ msg }}</div>",
+      false,
+      CodegenOptions { prefix_identifiers: false, ..Default::default() },
+    );
+    assert_eq!(
+      code,
+      "const _Vue = Vue\n\nreturn function render(_ctx, _cache) {\n  with (_ctx) {\n    const { openBlock: _openBlock, createElementBlock: _createElementBlock, toDisplayString: _toDisplayString } = _Vue\n\n    return (_openBlock(), _createElementBlock(\"div\", null, _toDisplayString(msg), 1 /* TEXT */))\n  }\n}"
+    );
+  }
+
+  /// `v-track="id"` -> `{ "data-track": id }`
+  struct TransformTrack {
+    need_runtime: bool,
+  }
+
+  impl DirectiveTransform for TransformTrack {
+    fn transform(&self, dir: &mut DirectiveProp, _node: &mut Node, _ctx: &mut TransformContext) -> DirectiveTransformRes {
+      let properties = match (&dir.exp, self.need_runtime) {
+        (Some(exp), false) => vec![Property {
+          key: ExpressionNode::SimpleExpressionNode(SimpleExpressionNode {
+            content: "data-track".to_string(),
+            is_static: true,
+            constant_type: ConstantTypes::CanStringify,
+            loc: Default::default(),
+          }),
+          value: Box::new(JsChildNode::ExpressionNode(exp.clone())),
+        }],
+        _ => vec![],
+      };
+      DirectiveTransformRes { properties, need_runtime: self.need_runtime }
+    }
+  }
+
+  fn compile_with_track(source: &str, need_runtime: bool) -> String {
+    let mut parser = Parser::new(source);
+    let ast = Rc::new(RefCell::new(parser.parse()));
+    let mut ctx = TransformContext::new("hello.vue".to_string());
+    ctx.add_directive_transform("track", TransformTrack { need_runtime });
+    let mut runner = TransformRunner::new(
+      vec![Box::new(Expression {}), Box::new(TransformText), Box::new(TransformElement)],
+      ctx,
+    );
+    runner.transform(ast.clone());
+    let ast = ast.borrow();
+    let mut code_gen = CodeGen::new(&ast, CodegenOptions::default());
+    code_gen.generate();
+    code_gen.code[code_gen.code.find("function render").unwrap()..].to_string()
+  }
+
+  #[test]
+  fn test_gen_directive_transform() {
+    assert_eq!(compile_with_track("<div v-track=\"id\">hi</div>", false), "function render(_ctx, _cache) {\n  return (_openBlock(), _createElementBlock(\"div\", { \"data-track\": _ctx.id }, \"hi\", 8 /* PROPS */, [\"data-track\"]))\n}");
+    // 需要运行时处理时, 与没有注册转换的自定义指令一样通过 withDirectives 处理, 子节点需要是 vnode
+    assert_eq!(compile_with_track("<div v-track=\"id\">hi</div>", true), "function render(_ctx, _cache) {\n  const _directive_track = _resolveDirective(\"track\")\n\n  return _withDirectives((_openBlock(), _createElementBlock(\"div\", null, [\n    _createTextVNode(\"hi\")\n  ], 512 /* NEED_PATCH */)), [\n    [_directive_track, _ctx.id]\n  ])\n}");
+    assert_eq!(compile("<div v-foo:bar.a.b=\"id\">hi</div>", false), "function render(_ctx, _cache) {\n  const _directive_foo = _resolveDirective(\"foo\")\n\n  return _withDirectives((_openBlock(), _createElementBlock(\"div\", null, [\n    _createTextVNode(\"hi\")\n  ], 512 /* NEED_PATCH */)), [\n    [\n      _directive_foo,\n      _ctx.id,\n      \"bar\",\n      {\n        a: true,\n        b: true\n      }\n    ]\n  ])\n}");
+    assert_eq!(compile("<div v-foo.a></div>", false), "function render(_ctx, _cache) {\n  const _directive_foo = _resolveDirective(\"foo\")\n\n  return _withDirectives((_openBlock(), _createElementBlock(\"div\", null, null, 512 /* NEED_PATCH */)), [\n    [\n      _directive_foo,\n      void 0,\n      void 0,\n      { a: true }\n    ]\n  ])\n}");
+  }
+
+  #[test]
+  fn test_source_map() {
+    let mut parser = Parser::new("<div>\n  <p>{{ msg }}</p>\n</div>");
+    let ast = Rc::new(RefCell::new(parser.parse()));
+    let mut runner = TransformRunner::new(
+      vec![Box::new(Expression {}), Box::new(TransformText), Box::new(TransformElement)],
+      TransformContext::new("App.vue".to_string()),
+    );
+    runner.transform(ast.clone());
+    let ast = ast.borrow();
+    let options = CodegenOptions {
+      mode: CodegenMode::Module,
+      source_map: true,
+      filename: "App.vue".to_string(),
+      ..Default::default()
+    };
+    let mut code_gen = CodeGen::new(&ast, options);
+    code_gen.generate();
+
+    let map = code_gen.map.unwrap();
+    assert_eq!(map.get_source(0), Some("App.vue"));
+    assert_eq!(map.get_source_contents(0), Some("<div>\n  <p>{{ msg }}</p>\n</div>"));
+
+    let position_of = |needle: &str| code_gen.code
+      .lines()
+      .enumerate()
+      .find_map(|(line, code)| code.find(needle).map(|column| (line as u32, column as u32)))
+      .unwrap();
+
+    let (line, column) = position_of("_ctx.msg");
+    let token = map.lookup_token(line, column).unwrap();
+    assert_eq!((token.get_src_line(), token.get_src_col()), (1, 8));
+    assert_eq!(token.get_name(), Some("msg"));
+
+    let (line, column) = position_of("_createElementBlock(\"div\"");
+    let token = map.lookup_token(line, column).unwrap();
+    assert_eq!((token.get_src_line(), token.get_src_col()), (0, 0));
+  }
 }
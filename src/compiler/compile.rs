@@ -0,0 +1,235 @@
+use std::{cell::RefCell, collections::HashMap, rc::Rc};
+
+use sourcemap::SourceMap;
+
+use crate::{
+  Node, Parser, TransformRunner, TransformContext, Transform, DirectiveTransform, CodeGen,
+  SsrCodeGen, CompilerOptions, CompilerError, CodegenMode, TransformVIf, TransformVFor,
+  TrackSlotScopes, Expression, Slot, TransformText, TransformElement, TransformBind, TransformVOn,
+  TransformVModel, TransformOnce, TransformMemo, TrackVForSlotScopes, stringify_static,
+};
+
+pub struct CodegenResult {
+  pub code: String,
+  /// `code` 中渲染函数之前的部分, 内联到 `<script setup>` 时需要放到模块顶层
+  pub preamble: String,
+  pub ast: Node,
+  pub map: Option<SourceMap>,
+  pub errors: Vec<CompilerError>,
+}
+
+pub type TransformPreset = (Vec<Box<dyn Transform>>, HashMap<String, Rc<dyn DirectiveTransform>>);
+
+/// 内置的节点转换和指令转换, 与 compiler-core 的顺序一致。
+/// post_transform 按注册顺序执行(compiler-core 中退出函数逆序执行),
+/// 所以文本要在元素之前注册, 元素生成 vnode 时看到的是合并后的文本;
+/// v-once、v-if、v-memo 在 on_exit 中处理, 晚于所有 post_transform, 可以看到元素的 vnode
+pub fn get_base_transform_preset() -> TransformPreset {
+  let node_transforms: Vec<Box<dyn Transform>> = vec![
+    Box::new(TransformOnce),
+    Box::new(TransformVIf),
+    Box::new(TransformMemo),
+    Box::new(TransformVFor),
+    Box::new(TrackVForSlotScopes),
+    Box::new(Expression {}),
+    Box::new(Slot {}),
+    Box::new(TrackSlotScopes),
+    Box::new(TransformText),
+    Box::new(TransformElement),
+  ];
+  let directive_transforms: HashMap<String, Rc<dyn DirectiveTransform>> = HashMap::from([
+    ("on".to_string(), Rc::new(TransformVOn) as Rc<dyn DirectiveTransform>),
+    ("bind".to_string(), Rc::new(TransformBind)),
+    ("model".to_string(), Rc::new(TransformVModel)),
+  ]);
+  (node_transforms, directive_transforms)
+}
+
+/// SSR 的元素和文本在代码生成时直接拼接为字符串, 只需要处理结构指令和表达式
+fn get_ssr_transform_preset() -> Vec<Box<dyn Transform>> {
+  vec![
+    Box::new(TransformVIf),
+    Box::new(TransformVFor),
+    Box::new(TrackSlotScopes),
+    Box::new(Expression {}),
+  ]
+}
+
+/// 把模板编译为渲染函数: 解析、转换、生成代码
+pub fn compile(template: &str, options: CompilerOptions) -> CodegenResult {
+  let CompilerOptions {
+    parse,
+    codegen,
+    hoist_static,
+    cache_handlers,
+    ssr,
+    is_dev,
+    node_transforms,
+    directive_transforms,
+  } = options;
+
+  let mut parser = Parser::new_with_options(template, parse);
+  let ast = Rc::new(RefCell::new(parser.parse()));
+
+  let mut ctx = TransformContext::new(codegen.filename.clone());
+  // SSR 直接输出字符串, 没有需要提升的 vnode
+  ctx.hoist_static = hoist_static && !ssr;
+  // 与 compiler-dom 一致, 提升时把大段的静态节点字符串化为 createStaticVNode
+  if ctx.hoist_static {
+    ctx.transform_hoist = Some(stringify_static);
+  }
+  ctx.cache_handlers = cache_handlers;
+  ctx.ssr = ssr;
+  ctx.is_dev = is_dev;
+  ctx.prefix_identifiers = codegen.prefix_identifiers || codegen.mode == CodegenMode::Module;
+  ctx.inline = codegen.inline;
+  ctx.is_ts = codegen.is_ts;
+  if let Some(binding_metadata) = &codegen.binding_metadata {
+    ctx.binding_metadata = binding_metadata.clone();
+  }
+
+  let (mut transforms, mut directives) = get_base_transform_preset();
+  if ssr {
+    transforms = get_ssr_transform_preset();
+  }
+  transforms.extend(node_transforms);
+  directives.extend(directive_transforms);
+  ctx.directive_transforms = directives;
+
+  let mut runner = TransformRunner::new(transforms, ctx);
+  runner.transform(ast.clone());
+  let errors = std::mem::take(&mut runner.ctx.errors);
+  let ast = ast.borrow().clone();
+
+  let (code, preamble, map) = if ssr {
+    let mut code_gen = SsrCodeGen::new(&ast, codegen);
+    code_gen.generate();
+    (code_gen.code, code_gen.preamble, None)
+  } else {
+    let mut code_gen = CodeGen::new(&ast, codegen);
+    code_gen.generate();
+    (code_gen.code, code_gen.preamble, code_gen.map)
+  };
+  CodegenResult { code, preamble, ast, map, errors }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::{cell::RefCell, collections::HashMap, rc::Rc};
+
+  use crate::{
+    compile, CompilerOptions, CodegenOptions, CodegenMode, Transform, TransformContext, Node,
+    DirectiveTransform, DirectiveTransformRes, DirectiveProp, ErrorCodes,
+  };
+
+  fn render(code: &str) -> &str {
+    &code[code.find("function ").unwrap()..]
+  }
+
+  /// 统计元素数量
+  struct CountElements {
+    count: Rc<RefCell<usize>>,
+  }
+
+  impl Transform for CountElements {
+    fn pre_transform(&self, node: &mut Node, _ctx: &mut TransformContext) {
+      if let Node::ElementNode(_) = node {
+        *self.count.borrow_mut() += 1;
+      }
+    }
+
+    fn post_transform(&self, _node: &mut Node, _ctx: &mut TransformContext) {
+    }
+  }
+
+  /// `v-track` 只在运行时处理
+  struct TransformTrack;
+
+  impl DirectiveTransform for TransformTrack {
+    fn transform(&self, _dir: &mut DirectiveProp, _node: &mut Node, _ctx: &mut TransformContext) -> DirectiveTransformRes {
+      DirectiveTransformRes { properties: vec![], need_runtime: true }
+    }
+  }
+
+  #[test]
+  fn test_compile() {
+    let result = compile("<div>a {{ b }}</div>", CompilerOptions {
+      codegen: CodegenOptions {
+        mode: CodegenMode::Module,
+        ..Default::default()
+      },
+      ..Default::default()
+    });
+    assert!(result.code.starts_with(&result.preamble));
+    assert_eq!(result.preamble, "import { openBlock as _openBlock, createElementBlock as _createElementBlock, toDisplayString as _toDisplayString } from \"vue\"\n\n");
+    assert_eq!(render(&result.code), "function render(_ctx, _cache) {\n  return (_openBlock(), _createElementBlock(\"div\", null, \"a \" + _toDisplayString(_ctx.b), 1 /* TEXT */))\n}");
+    assert!(matches!(result.ast, Node::Root(_)));
+    assert!(result.map.is_none());
+    assert!(result.errors.is_empty());
+
+    let result = compile("<div><slot name=\"foo\"></slot><template v-if=\"ok\"><b></b></template></div>", Default::default());
+    assert_eq!(render(&result.code), "function render(_ctx, _cache) {\n  return (_openBlock(), _createElementBlock(\"div\", null, [\n    _renderSlot(_ctx.$slots, \"foo\"),\n    (_ctx.ok)\n      ? (_openBlock(), _createElementBlock(\"b\", { key: 0 }))\n      : _createCommentVNode(\"v-if\", true)\n  ]))\n}");
+  }
+
+  #[test]
+  fn test_compile_once_and_memo() {
+    let result = compile("<div v-once>{{ a }}</div>", Default::default());
+    assert_eq!(render(&result.code), "function render(_ctx, _cache) {\n  return _cache[0] || (\n    _setBlockTracking(-1),\n    _cache[0] = _createElementVNode(\"div\", null, _toDisplayString(_ctx.a), 1 /* TEXT */),\n    _setBlockTracking(1),\n    _cache[0]\n  )\n}");
+    let result = compile("<div><p v-memo=\"[a]\" @click=\"b\">{{ a }}</p><span v-if=\"ok\" v-memo=\"[a]\"></span></div>", Default::default());
+    assert_eq!(render(&result.code), "function render(_ctx, _cache) {\n  return (_openBlock(), _createElementBlock(\"div\", null, [\n    _withMemo([_ctx.a], () => (_openBlock(), _createElementBlock(\"p\", { onClick: _ctx.b }, _toDisplayString(_ctx.a), 9 /* TEXT, PROPS */, [\"onClick\"])), _cache, 0),\n    (_ctx.ok)\n      ? _withMemo([_ctx.a], () => (_openBlock(), _createElementBlock(\"span\", { key: 0 })), _cache, 1)\n      : _createCommentVNode(\"v-if\", true)\n  ]))\n}");
+  }
+
+  #[test]
+  fn test_compile_stringify_static() {
+    let template = format!("<div>{}{{{{ msg }}}}</div>", "<span>a</span>".repeat(25));
+    let result = compile(&template, CompilerOptions { hoist_static: true, ..Default::default() });
+    assert!(result.code.contains(&format!(
+      "const _hoisted_1 = /*#__PURE__*/_createStaticVNode(\"{}\", 25)\n",
+      "<span>a</span>".repeat(25),
+    )));
+    assert!(!result.code.contains("_hoisted_2"));
+    assert!(result.code.contains("[\n    _hoisted_1,\n    _createTextVNode(_toDisplayString(_ctx.msg), 1 /* TEXT */)\n  ]"));
+  }
+
+  #[test]
+  fn test_compile_void_tags() {
+    let result = compile("<div><input disabled><br>{{ a }}</div>", Default::default());
+    assert!(result.errors.is_empty());
+    assert_eq!(render(&result.code), "function render(_ctx, _cache) {\n  return (_openBlock(), _createElementBlock(\"div\", null, [\n    _createElementVNode(\"input\", { disabled: \"\" }),\n    _createElementVNode(\"br\"),\n    _createTextVNode(_toDisplayString(_ctx.a), 1 /* TEXT */)\n  ]))\n}");
+  }
+
+  #[test]
+  fn test_compile_with_user_transforms() {
+    let count = Rc::new(RefCell::new(0));
+    let result = compile("<div v-track><span></span></div>", CompilerOptions {
+      node_transforms: vec![Box::new(CountElements { count: count.clone() })],
+      directive_transforms: HashMap::from([
+        ("track".to_string(), Rc::new(TransformTrack) as Rc<dyn DirectiveTransform>),
+      ]),
+      ..Default::default()
+    });
+    assert_eq!(*count.borrow(), 2);
+    assert_eq!(render(&result.code), "function render(_ctx, _cache) {\n  const _directive_track = _resolveDirective(\"track\")\n\n  return _withDirectives((_openBlock(), _createElementBlock(\"div\", null, [\n    _createElementVNode(\"span\")\n  ], 512 /* NEED_PATCH */)), [\n    [_directive_track]\n  ])\n}");
+  }
+
+  #[test]
+  fn test_compile_ssr() {
+    let result = compile("<div>{{ msg }}</div>", CompilerOptions {
+      ssr: true,
+      ..Default::default()
+    });
+    assert_eq!(render(&result.code), "function ssrRender(_ctx, _push, _parent, _attrs) {\n  _push(`<div${_ssrRenderAttrs(_attrs)}>${_ssrInterpolate(_ctx.msg)}</div>`)\n}");
+  }
+
+  #[test]
+  fn test_compile_errors() {
+    let result = compile("<div :[key]></div>", Default::default());
+    assert_eq!(result.errors.len(), 1);
+    assert_eq!(result.errors[0].code, ErrorCodes::XVBindInvalidSameNameArgument);
+
+    let codes = |source: &str| compile(source, Default::default()).errors.iter().map(|e| e.code).collect::<Vec<_>>();
+    assert_eq!(codes("<Comp><template #default>a</template><b></b></Comp>"), vec![ErrorCodes::XVSlotExtraneousDefaultSlotChildren]);
+    assert_eq!(codes("<Comp><template #a>a</template><template #a>b</template></Comp>"), vec![ErrorCodes::XVSlotDuplicateSlotNames]);
+    assert_eq!(codes("<Comp><template #a>a</template><template v-else #b>b</template></Comp>"), vec![ErrorCodes::XVElseNoAdjacentIf]);
+  }
+}
@@ -1,6 +1,8 @@
 use std::collections::HashMap;
 
-#[derive(Eq, PartialEq, Hash)]
+use crate::SourceLocation;
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
 pub enum ErrorCodes {
   AbruptClosingOfEmptyComment,
   CdataInHtmlContent,
@@ -41,6 +43,7 @@ pub enum ErrorCodes {
   XVForMalformedExpression,
   XVForTemplateKeyPlacement,
   XVBindNoExpression,
+  XVBindInvalidSameNameArgument,
   XVOnNoExpression,
   XVSlotUnexpectedDirectiveOnSlotOutlet,
   XVSlotMixedSlotUsage,
@@ -108,6 +111,7 @@ lazy_static! {
       map.insert(ErrorCodes::XVForMalformedExpression, "v-for has invalid expression.");
       map.insert(ErrorCodes::XVForTemplateKeyPlacement, "v-for template key should be placed on <template> tag.");
       map.insert(ErrorCodes::XVBindNoExpression, "v-bind is missing expression.");
+      map.insert(ErrorCodes::XVBindInvalidSameNameArgument, "v-bind with same-name shorthand only allows static argument.");
       map.insert(ErrorCodes::XVOnNoExpression, "v-on is missing expression.");
       map.insert(ErrorCodes::XVSlotUnexpectedDirectiveOnSlotOutlet, "Unexpected custom directive on <slot> outlet.");
       map.insert(ErrorCodes::XVSlotMixedSlotUsage, "Mixed v-slot usage on both the component and nested <template>s. When there are multiple named slots, all slots should use <template> syntax to avoid scope ambiguity.");
@@ -130,3 +134,22 @@ lazy_static! {
       map
   };
 }
+
+/// 编译过程中收集的错误, `message` 为 ERROR_MESSAGES 中的信息加上附加说明
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompilerError {
+  pub code: ErrorCodes,
+  pub message: String,
+  pub loc: Option<SourceLocation>,
+}
+
+impl CompilerError {
+  pub fn new(code: ErrorCodes, loc: Option<SourceLocation>, additional_message: &str) -> Self {
+    let message = ERROR_MESSAGES.get(&code).copied().unwrap_or_default();
+    Self {
+      code,
+      message: format!("{}{}", message, additional_message),
+      loc,
+    }
+  }
+}
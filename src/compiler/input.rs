@@ -20,19 +20,16 @@ impl<'a> Input<'a> {
     if n > self.source.len() {
       panic!("consume out of range");
     }
-    self.column += n;
     self.offset += n;
-    let mut i = 0;
-    self.source.chars().for_each(|c| {
-      if i == n {
-        return;
-      }
+    for c in self.source[..n].chars() {
       if c == '\n' {
         self.line += 1;
+        self.column = 1;
+      } else {
+        self.column += 1;
       }
-      i += 1;
-    });
-    
+    }
+
     self.source = &self.source[n..];
   }
 
@@ -50,8 +47,9 @@ impl<'a> Input<'a> {
       .for_each(|_| {})
   }
 
+  /// 剩余内容不足 n 字节时返回全部剩余内容
   pub fn peek_chars(&self, n: usize) -> &'a str {
-    &self.source[..n]
+    self.source.get(..n).unwrap_or(self.source)
   }
 
   pub fn peek_char_at(&self, n: usize) -> char {
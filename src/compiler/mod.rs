@@ -14,8 +14,14 @@ mod utils;
 pub use utils::*;
 mod code_gen;
 pub use code_gen::*;
+mod ssr_code_gen;
+pub use ssr_code_gen::*;
 mod patch_flags;
 pub use patch_flags::*;
+mod runtime_helpers;
+pub use runtime_helpers::*;
+mod compile;
+pub use compile::*;
 
 
 #[cfg(test)]
@@ -24,16 +30,10 @@ mod tests {
 
   #[test]
   pub fn test_transform() {
-    // let mut parser = Parser::new("<div><span/></div>");
-    // let mut ast = parser.parse();
-    
-    // let mut transform_runner = TransformRunner::new(
-    //   vec![
-    //     Box::new(TransformElement{}),
-    //     Box::new(TransformText{})
-    //   ],
-    //   TransformContext::new("hello.vue".to_string()),
-    // );
-    // transform_runner.travel_node(&mut ast);
+    let result = compile("<div><span/></div>", CompilerOptions::default());
+    assert_eq!(
+      &result.code[result.code.find("function render").unwrap()..],
+      "function render(_ctx, _cache) {\n  return (_openBlock(), _createElementBlock(\"div\", null, [\n    _createElementVNode(\"span\")\n  ]))\n}"
+    );
   }
-}
\ No newline at end of file
+}
@@ -1,3 +1,148 @@
+use std::{collections::HashMap, rc::Rc, str::FromStr};
+
+use crate::{Transform, DirectiveTransform, is_html_void_tag};
+
+/// `<script setup>` 编译后提供的变量来源, 决定模板中标识符的访问方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BindingTypes {
+  /// `data()` 返回的变量
+  Data,
+  Props,
+  /// 解构 props 时被重命名的变量, 原名记录在 `props_aliases` 中
+  PropsAliased,
+  /// `let` 声明, 可能被重新赋值为 ref
+  SetupLet,
+  /// 不会是 ref 的 `const` 声明
+  SetupConst,
+  /// `reactive()` 创建的常量, 不需要 unref
+  SetupReactiveConst,
+  /// 可能是 ref 的 `const` 声明
+  SetupMaybeRef,
+  /// 确定是 ref 的 `const` 声明
+  SetupRef,
+  /// `computed`, `methods` 等选项中声明的变量
+  Options,
+  /// 字面量常量
+  LiteralConst,
+}
+
+impl FromStr for BindingTypes {
+  type Err = ();
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    let binding_type = match s {
+      "data" => BindingTypes::Data,
+      "props" => BindingTypes::Props,
+      "props-aliased" => BindingTypes::PropsAliased,
+      "setup-let" => BindingTypes::SetupLet,
+      "setup-const" => BindingTypes::SetupConst,
+      "setup-reactive-const" => BindingTypes::SetupReactiveConst,
+      "setup-maybe-ref" => BindingTypes::SetupMaybeRef,
+      "setup-ref" => BindingTypes::SetupRef,
+      "options" => BindingTypes::Options,
+      "literal-const" => BindingTypes::LiteralConst,
+      _ => return Err(()),
+    };
+    Ok(binding_type)
+  }
+}
+
+impl BindingTypes {
+  pub fn is_setup(&self) -> bool {
+    matches!(
+      self,
+      BindingTypes::SetupLet |
+      BindingTypes::SetupConst |
+      BindingTypes::SetupReactiveConst |
+      BindingTypes::SetupMaybeRef |
+      BindingTypes::SetupRef
+    )
+  }
+
+  pub fn is_const(&self) -> bool {
+    matches!(self, BindingTypes::SetupConst | BindingTypes::LiteralConst)
+  }
+}
+
+pub type BindingMetadata = HashMap<String, BindingTypes>;
+
+/// 渲染函数的输出形式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CodegenMode {
+  /// `return function render() {}`, helper 从全局变量 `Vue` 中解构
+  #[default]
+  Function,
+  /// `export function render() {}`, helper 从 `vue` 模块中 import
+  Module,
+}
+
+#[derive(Debug, Clone)]
+pub struct CodegenOptions {
+  pub mode: CodegenMode,
+  /// 关闭时函数模式的渲染函数包裹在 `with (_ctx)` 中, 模块模式总是开启
+  pub prefix_identifiers: bool,
+  /// 有 binding metadata 且不是内联模式时, 渲染函数需要接收 `$props`, `$setup` 等参数
+  pub binding_metadata: Option<BindingMetadata>,
+  pub inline: bool,
+  pub is_ts: bool,
+  /// 模块模式下 import helper 的模块名
+  pub runtime_module_name: String,
+  /// 函数模式下解构 helper 的全局变量名
+  pub runtime_global_name: String,
+  /// 是否生成 source map
+  pub source_map: bool,
+  /// 模板所在的文件名, 作为 source map 的 `sources`
+  pub filename: String,
+}
+
+impl Default for CodegenOptions {
+  fn default() -> Self {
+    Self {
+      mode: CodegenMode::Function,
+      prefix_identifiers: true,
+      binding_metadata: None,
+      inline: false,
+      is_ts: false,
+      runtime_module_name: "vue".to_string(),
+      runtime_global_name: "Vue".to_string(),
+      source_map: false,
+      filename: "template.vue.html".to_string(),
+    }
+  }
+}
+
+/// `compile()` 的选项
+pub struct CompilerOptions {
+  pub parse: ParseOptions,
+  pub codegen: CodegenOptions,
+  /// 把静态节点提升到渲染函数之外
+  pub hoist_static: bool,
+  /// 缓存内联的事件处理函数
+  pub cache_handlers: bool,
+  /// 生成服务端渲染函数 `ssrRender`
+  pub ssr: bool,
+  pub is_dev: bool,
+  /// 追加在内置转换之后的节点转换
+  pub node_transforms: Vec<Box<dyn Transform>>,
+  /// 追加的指令转换, 与内置转换同名时覆盖内置转换
+  pub directive_transforms: HashMap<String, Rc<dyn DirectiveTransform>>,
+}
+
+impl Default for CompilerOptions {
+  fn default() -> Self {
+    Self {
+      parse: ParseOptions::default(),
+      codegen: CodegenOptions::default(),
+      hoist_static: false,
+      cache_handlers: false,
+      ssr: false,
+      is_dev: true,
+      node_transforms: vec![],
+      directive_transforms: HashMap::new(),
+    }
+  }
+}
+
 pub struct ParseOptions {
   pub is_void_tag: fn(&str) -> bool,
   pub is_in_pre: fn(&str) -> bool,
@@ -11,7 +156,7 @@ pub struct ParseOptions {
 impl Default for ParseOptions {
   fn default() -> Self {
       Self { 
-        is_void_tag: |_: &str| false,
+        is_void_tag: is_html_void_tag,
         is_in_pre: |_: &str| false,
         is_native_tag: |_: &str| true,
         is_custom_element: |_: &str| false,
@@ -29,7 +174,16 @@ mod test {
   #[test]
   fn test_default() {
     let options = ParseOptions::default();
-    assert_eq!((options.is_void_tag)("br"), false);
+    assert_eq!(options.delimiters, (String::from("{{"), String::from("}}")));
+    assert!(options.comment);
+  }
+
+  #[test]
+  fn test_default_void_tags() {
+    let options = ParseOptions::default();
+    assert!((options.is_void_tag)("br"));
+    assert!((options.is_void_tag)("input"));
+    assert!(!(options.is_void_tag)("div"));
   }
 
   #[test]
@@ -70,7 +70,9 @@ impl<'a> Parser<'a> {
     }
 
     pub fn parse(&mut self) -> Node {
+      let source = self.input.source.to_string();
       let root = RootNode {
+        source,
         children: Rc::new(RefCell::new(self.parse_children())),
         helpers: vec![],
         components: vec![],
@@ -219,15 +221,9 @@ impl<'a> Parser<'a> {
           "slot" => {
             element_type = ElementTagType::SlotOutletNode;
           },
+          // `<template>` 只是包裹子节点, 本身不会被渲染
           "template" => {
-            if attributes.iter().any(|attr| {
-              match attr {
-                Prop::Directive(_) => true,
-                _ => false,
-              }
-            }) {
-              element_type = ElementTagType::TemplateNode;
-            }
+            element_type = ElementTagType::TemplateNode;
           },
           _ => {
             if self.is_component(tag_name, &attributes) {
@@ -257,8 +253,9 @@ impl<'a> Parser<'a> {
     pub fn parse_text(&mut self) -> TextNode {
       let start_pos = self.input.get_current_position();
       let mut content = String::new();
+      let open_delimiter = self.parse_options.delimiters.0.clone();
       while !self.input.is_end() {
-        if self.input.peek_char_at(0) == '<' {
+        if self.input.peek_char_at(0) == '<' || start_with(self.input.source, &open_delimiter) {
           break;
         }
         content.push(self.input.peek_char());
@@ -275,24 +272,28 @@ impl<'a> Parser<'a> {
 
     pub fn parse_interpolation(&mut self) -> InterpolationNode {
       let (open, close) = self.parse_options.delimiters.clone();
-      let start_pos = self.input.get_current_position();
       self.input.consume(open.len());
       let close_index = self.input.source.find(close.as_str());
       if close_index.is_none() {
         emit_error(ErrorCodes::XMissingInterpolationEnd);
       }
       let close_index = close_index.unwrap();
-      let content = self.input.source[..close_index].to_string();
-      self.input.consume(content.len() + close.len());
+      let raw_content = &self.input.source[..close_index];
+      let content = raw_content.trim().to_string();
+      // 表达式的位置只包含去掉首尾空白后的内容, 不包括分隔符
+      let leading_len = raw_content.len() - raw_content.trim_start().len();
+      let trailing_len = raw_content.len() - leading_len - content.len();
+      self.input.consume(leading_len);
+      let start = self.input.get_current_position();
+      self.input.consume(content.len());
+      let end = self.input.get_current_position();
+      self.input.consume(trailing_len + close.len());
       InterpolationNode {
         content: ExpressionNode::SimpleExpressionNode(SimpleExpressionNode {
           content,
           is_static: false,
           constant_type: ConstantTypes::NotConstant,
-          loc: SourceLocation {
-            start: start_pos,
-            end: self.input.get_current_position(),
-          },
+          loc: SourceLocation { start, end },
         }),
       }
     }
@@ -433,13 +434,17 @@ impl<'a> Parser<'a> {
           None => None,
         };
 
-        let modifiers = match matched.get(3) {
+        let mut modifiers: Vec<String> = match matched.get(3) {
           Some(modifiers) => {
             modifiers.as_str().trim_start_matches(".")
               .split(".").map(|m| m.to_string()).collect()
           },
           None => vec![],
         };
+        // `.foo` 是 `:foo.prop` 的简写
+        if is_prop_short_hand {
+          modifiers.push("prop".to_string());
+        }
 
         let exp = match value {
             Some(attribute) => {
@@ -636,8 +641,35 @@ pub fn emit_error(code: ErrorCodes) {
 
 #[cfg(test)]
 mod tests {
+  use crate::{Parser, Node, InterpolationNode, ExpressionNode, Position};
+
   #[test]
   pub fn test_run() {
     assert_eq!(1, 1)
   }
+
+  #[test]
+  fn test_interpolation_loc() {
+    let mut parser = Parser::new("<div>{{  a + b }}</div>");
+    let Node::Root(root) = parser.parse() else { unreachable!() };
+    let children = root.children.borrow();
+    let Node::ElementNode(div) = &*children[0].borrow() else { unreachable!() };
+    let div_children = div.children.borrow();
+    let Node::Interpolation(InterpolationNode { content: ExpressionNode::SimpleExpressionNode(exp) }) = &*div_children[0].borrow() else {
+      unreachable!()
+    };
+    assert_eq!(exp.content, "a + b");
+    assert_eq!(exp.loc.start, Position { offset: 9, line: 1, column: 10 });
+    assert_eq!(exp.loc.end, Position { offset: 14, line: 1, column: 15 });
+  }
+
+  #[test]
+  fn test_text_ends_at_interpolation() {
+    let mut parser = Parser::new("a {{ b }}");
+    let Node::Root(root) = parser.parse() else { unreachable!() };
+    let children = root.children.borrow();
+    assert_eq!(children.len(), 2);
+    assert!(matches!(&*children[0].borrow(), Node::Text(text) if text.content == "a "));
+    assert!(matches!(&*children[1].borrow(), Node::Interpolation(_)));
+  }
 }
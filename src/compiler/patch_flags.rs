@@ -2,7 +2,7 @@ use bitflags::bitflags;
 
 bitflags! {
   #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
-  struct PatchFlags: i32 {
+  pub struct PatchFlags: i32 {
     const TEXT = 1 << 0;
     const CLASS = 1 << 1;
     const STYLE = 1 << 2;
@@ -14,7 +14,29 @@ bitflags! {
     const UNKEYED_FRAGMENT = 1 << 8;
     const NEED_PATCH = 1 << 9;
     const DYNAMIC_SLOTS = 1 << 10;
+    const DEV_ROOT_FRAGMENT = 1 << 11;
     const HOISTED = -1;
     const BAIL = -2;
   }
 }
+
+/// 从 `format_patch_flag` 生成的字符串中读取 patch flag
+pub fn parse_patch_flag(flag: &str) -> Option<PatchFlags> {
+  let bits = flag.split_whitespace().next()?.parse::<i32>().ok()?;
+  Some(PatchFlags::from_bits_retain(bits))
+}
+
+/// 生成代码中的 patch flag, 带上注释方便阅读: `9 /* TEXT, PROPS */`
+pub fn format_patch_flag(flag: PatchFlags) -> String {
+  if flag == PatchFlags::HOISTED {
+    return format!("{} /* HOISTED */", flag.bits());
+  }
+  if flag == PatchFlags::BAIL {
+    return format!("{} /* BAIL */", flag.bits());
+  }
+  let names = flag.iter_names()
+    .map(|(name, _)| name)
+    .collect::<Vec<_>>()
+    .join(", ");
+  format!("{} /* {} */", flag.bits(), names)
+}
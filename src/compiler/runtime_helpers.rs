@@ -0,0 +1,57 @@
+//! 渲染函数用到的运行时 helper, 值为 `vue` 导出的名字, 生成代码中以 `_` 为前缀引用
+
+pub const FRAGMENT: &str = "Fragment";
+pub const TELEPORT: &str = "Teleport";
+pub const SUSPENSE: &str = "Suspense";
+pub const KEEP_ALIVE: &str = "KeepAlive";
+pub const BASE_TRANSITION: &str = "BaseTransition";
+pub const OPEN_BLOCK: &str = "openBlock";
+pub const CREATE_BLOCK: &str = "createBlock";
+pub const CREATE_ELEMENT_BLOCK: &str = "createElementBlock";
+pub const CREATE_VNODE: &str = "createVNode";
+pub const CREATE_ELEMENT_VNODE: &str = "createElementVNode";
+pub const CREATE_COMMENT: &str = "createCommentVNode";
+pub const CREATE_TEXT: &str = "createTextVNode";
+pub const CREATE_STATIC: &str = "createStaticVNode";
+pub const RESOLVE_COMPONENT: &str = "resolveComponent";
+pub const RESOLVE_DYNAMIC_COMPONENT: &str = "resolveDynamicComponent";
+pub const RESOLVE_DIRECTIVE: &str = "resolveDirective";
+pub const WITH_DIRECTIVES: &str = "withDirectives";
+pub const RENDER_LIST: &str = "renderList";
+pub const RENDER_SLOT: &str = "renderSlot";
+pub const CREATE_SLOTS: &str = "createSlots";
+pub const TO_DISPLAY_STRING: &str = "toDisplayString";
+pub const MERGE_PROPS: &str = "mergeProps";
+pub const NORMALIZE_CLASS: &str = "normalizeClass";
+pub const NORMALIZE_STYLE: &str = "normalizeStyle";
+pub const NORMALIZE_PROPS: &str = "normalizeProps";
+pub const GUARD_REACTIVE_PROPS: &str = "guardReactiveProps";
+pub const TO_HANDLERS: &str = "toHandlers";
+pub const CAMELIZE: &str = "camelize";
+pub const CAPITALIZE: &str = "capitalize";
+pub const TO_HANDLER_KEY: &str = "toHandlerKey";
+pub const SET_BLOCK_TRACKING: &str = "setBlockTracking";
+pub const WITH_CTX: &str = "withCtx";
+pub const UNREF: &str = "unref";
+pub const IS_REF: &str = "isRef";
+pub const WITH_MEMO: &str = "withMemo";
+pub const IS_MEMO_SAME: &str = "isMemoSame";
+
+// 以下 helper 由 `vue/server-renderer` 导出, 只在 SSR 渲染函数中使用
+pub const SSR_INTERPOLATE: &str = "ssrInterpolate";
+pub const SSR_RENDER_ATTRS: &str = "ssrRenderAttrs";
+pub const SSR_RENDER_ATTR: &str = "ssrRenderAttr";
+pub const SSR_RENDER_DYNAMIC_ATTR: &str = "ssrRenderDynamicAttr";
+pub const SSR_RENDER_CLASS: &str = "ssrRenderClass";
+pub const SSR_RENDER_STYLE: &str = "ssrRenderStyle";
+pub const SSR_INCLUDE_BOOLEAN_ATTR: &str = "ssrIncludeBooleanAttr";
+pub const SSR_LOOSE_EQUAL: &str = "ssrLooseEqual";
+pub const SSR_LOOSE_CONTAIN: &str = "ssrLooseContain";
+pub const SSR_RENDER_COMPONENT: &str = "ssrRenderComponent";
+pub const SSR_RENDER_SLOT: &str = "ssrRenderSlot";
+pub const SSR_RENDER_LIST: &str = "ssrRenderList";
+
+/// 内置组件作为 vnode 的 tag 时需要从运行时引入
+pub fn is_builtin_symbol_tag(tag: &str) -> bool {
+  matches!(tag, FRAGMENT | TELEPORT | SUSPENSE | KEEP_ALIVE | BASE_TRANSITION)
+}
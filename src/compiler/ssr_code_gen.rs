@@ -0,0 +1,1254 @@
+use std::{cell::RefCell, rc::Rc};
+
+use crate::{
+  Node, RootNode, ElementNodeBase, ElementTagType, ExpressionNode, IfNode, IfBranchNode, ForNode,
+  Prop, DirectiveProp, SourceLocation, CodeGen, CodegenOptions, CodegenMode, TransformRunner,
+  TransformContext, TransformElement, TransformText, TransformVIf, Children, for_loop_params,
+  for_item_children,
+  escape_html, is_html_void_tag, is_boolean_attr, is_simple_identifier, is_core_component,
+  is_builtin_symbol_tag, to_js_string_literal, to_valid_asset_id, deep_clone_children,
+  MERGE_PROPS, RESOLVE_COMPONENT, WITH_CTX, SSR_INTERPOLATE, SSR_RENDER_ATTRS, SSR_RENDER_ATTR,
+  SSR_RENDER_DYNAMIC_ATTR, SSR_RENDER_CLASS, SSR_RENDER_STYLE, SSR_INCLUDE_BOOLEAN_ATTR,
+  SSR_LOOSE_EQUAL, SSR_LOOSE_CONTAIN, SSR_RENDER_COMPONENT, SSR_RENDER_SLOT, SSR_RENDER_LIST,
+};
+
+/// SSR helper 所在的模块
+const SSR_RUNTIME_MODULE_NAME: &str = "vue/server-renderer";
+
+/// 生成服务端渲染函数 `ssrRender(_ctx, _push, _parent, _attrs)`,
+/// 静态内容拼接为模板字符串, 通过 `_push` 输出
+pub struct SsrCodeGen<'a> {
+  pub code: String,
+  pub ast: &'a Node,
+  pub options: CodegenOptions,
+  /// 从 `vue` 引入的 helper
+  pub helpers: Vec<String>,
+  /// 从 `vue/server-renderer` 引入的 helper
+  pub ssr_helpers: Vec<String>,
+  /// `code` 中渲染函数之前的 helper 引入
+  pub preamble: String,
+  components: Vec<String>,
+  /// 还没有 `_push` 出去的模板字符串内容
+  template: String,
+  indent_level: usize,
+}
+
+enum AttrKey {
+  Static(String),
+  Dynamic(String),
+}
+
+enum AttrValue {
+  /// 没有值的静态属性, 如 `<input disabled>`
+  Empty,
+  Static(String),
+  Exp(String),
+}
+
+struct Attr {
+  key: AttrKey,
+  value: AttrValue,
+}
+
+/// `v-bind="obj"` 把属性分成多段, 通过 `mergeProps` 合并
+enum PropsSegment {
+  Attrs(Vec<Attr>),
+  Object(String),
+}
+
+#[derive(Default)]
+struct ElementProps {
+  segments: Vec<PropsSegment>,
+  /// v-show 的条件
+  show: Option<String>,
+  /// v-html, v-text 或 `<textarea v-model>` 生成的元素内容
+  content: Option<String>,
+}
+
+impl ElementProps {
+  fn push(&mut self, key: AttrKey, value: AttrValue) {
+    if !matches!(self.segments.last(), Some(PropsSegment::Attrs(_))) {
+      self.segments.push(PropsSegment::Attrs(vec![]));
+    }
+    let Some(PropsSegment::Attrs(attrs)) = self.segments.last_mut() else {
+      return;
+    };
+    // 同时有静态和绑定的 class/style 时合并为数组, 由运行时 normalize
+    if let AttrKey::Static(name) = &key {
+      if name == "class" || name == "style" {
+        let existing = attrs.iter_mut().find(|a| matches!(&a.key, AttrKey::Static(n) if n == name));
+        if let Some(existing) = existing {
+          existing.value = AttrValue::Exp(format!(
+            "[{}, {}]",
+            attr_value_code(&existing.value),
+            attr_value_code(&value),
+          ));
+          return;
+        }
+      }
+    }
+    attrs.push(Attr { key, value });
+  }
+
+  /// 只有一段静态的属性列表时, 属性可以直接写在模板字符串中
+  fn inline_attrs(&self) -> Option<&[Attr]> {
+    match &self.segments[..] {
+      [] => Some(&[]),
+      [PropsSegment::Attrs(attrs)] => Some(attrs),
+      _ => None,
+    }
+  }
+}
+
+impl<'a> SsrCodeGen<'a> {
+  pub fn new(ast: &'a Node, options: CodegenOptions) -> Self {
+    Self {
+      code: String::new(),
+      ast,
+      options,
+      helpers: vec![],
+      ssr_helpers: vec![],
+      preamble: String::new(),
+      components: vec![],
+      template: String::new(),
+      indent_level: 0,
+    }
+  }
+
+  pub fn generate(&mut self) {
+    let Node::Root(root) = self.ast else {
+      return;
+    };
+    for helper in root.helpers.iter() {
+      self.helper(helper);
+    }
+    for helper in root.ssr_helpers.iter() {
+      self.ssr_helper(helper);
+    }
+    self.components = root.components.clone();
+
+    // helper 在生成过程中才收集完整, 先生成渲染函数, 最后补上前导代码
+    self.gen_render_function(root);
+    let render = std::mem::take(&mut self.code);
+    match self.options.mode {
+      CodegenMode::Module => self.gen_module_preamble(root),
+      CodegenMode::Function => self.gen_function_preamble(),
+    }
+    self.preamble = self.code.clone();
+    self.push(match self.options.mode {
+      CodegenMode::Module => "export ",
+      CodegenMode::Function => "return ",
+    });
+    self.push(&render);
+  }
+
+  /// 记录从 `vue` 引入的 helper, 返回生成代码中的别名
+  pub fn helper(&mut self, name: &str) -> String {
+    if !self.helpers.iter().any(|h| h == name) {
+      self.helpers.push(name.to_string());
+    }
+    format!("_{}", name)
+  }
+
+  /// 记录从 `vue/server-renderer` 引入的 helper, 返回生成代码中的别名
+  pub fn ssr_helper(&mut self, name: &str) -> String {
+    if !self.ssr_helpers.iter().any(|h| h == name) {
+      self.ssr_helpers.push(name.to_string());
+    }
+    format!("_{}", name)
+  }
+
+  /// `import { ssrInterpolate as _ssrInterpolate } from "vue/server-renderer"`
+  fn gen_module_preamble(&mut self, root: &RootNode) {
+    let imports = [
+      (&self.helpers, self.options.runtime_module_name.as_str()),
+      (&self.ssr_helpers, SSR_RUNTIME_MODULE_NAME),
+    ];
+    let mut preamble = String::new();
+    for (helpers, module) in imports {
+      if helpers.is_empty() {
+        continue;
+      }
+      let helpers = helpers
+        .iter()
+        .map(|h| format!("{} as _{}", h, h))
+        .collect::<Vec<_>>()
+        .join(", ");
+      preamble.push_str(&format!("import {{ {} }} from {}\n", helpers, to_js_string_literal(module)));
+    }
+    for import in root.imports.iter() {
+      preamble.push_str(import);
+      preamble.push('\n');
+    }
+    if !preamble.is_empty() {
+      preamble.push('\n');
+    }
+    self.push(&preamble);
+  }
+
+  /// 服务端没有全局的 `Vue`, 函数模式通过 `require` 引入 helper
+  fn gen_function_preamble(&mut self) {
+    let requires = [
+      (&self.helpers, self.options.runtime_module_name.as_str()),
+      (&self.ssr_helpers, SSR_RUNTIME_MODULE_NAME),
+    ];
+    let mut preamble = String::new();
+    for (helpers, module) in requires {
+      if helpers.is_empty() {
+        continue;
+      }
+      let helpers = helpers
+        .iter()
+        .map(|h| format!("{}: _{}", h, h))
+        .collect::<Vec<_>>()
+        .join(", ");
+      preamble.push_str(&format!("const {{ {} }} = require({})\n", helpers, to_js_string_literal(module)));
+    }
+    if !preamble.is_empty() {
+      preamble.push('\n');
+    }
+    self.push(&preamble);
+  }
+
+  fn gen_render_function(&mut self, root: &RootNode) {
+    let mut args = vec!["_ctx", "_push", "_parent", "_attrs"];
+    if self.options.binding_metadata.is_some() && !self.options.inline {
+      args.extend(["$props", "$setup", "$data", "$options"]);
+    }
+    self.push(&format!("function ssrRender({}) {{\n", args.join(", ")));
+    self.indent_level += 1;
+    let body_start = self.code.len();
+
+    self.gen_root(root);
+    self.flush();
+
+    // 插槽的客户端渲染分支中也可能用到组件, 生成完才能确定需要解析哪些组件
+    if !self.components.is_empty() {
+      let resolver = self.helper(RESOLVE_COMPONENT);
+      let mut assets = self.components
+        .iter()
+        .map(|id| format!(
+          "  const {} = {}({})\n",
+          to_valid_asset_id(id, "component"),
+          resolver,
+          to_js_string_literal(id),
+        ))
+        .collect::<String>();
+      assets.push('\n');
+      self.code.insert_str(body_start, &assets);
+    }
+    self.indent_level -= 1;
+    self.push("}");
+  }
+
+  fn gen_root(&mut self, root: &RootNode) {
+    let children = root.children.borrow().clone();
+    // 只有一个根节点时, 透传的 attrs 渲染到这个节点上, 注释不算作根节点
+    let roots = children
+      .iter()
+      .filter(|c| !matches!(&*c.borrow(), Node::Comment(_)))
+      .count();
+    let is_fragment = children.len() > 1;
+    if is_fragment {
+      self.push_string("<!--[-->");
+    }
+    for child in children.iter() {
+      let child = child.borrow();
+      let inherit_attrs = roots == 1 && !matches!(&*child, Node::Comment(_));
+      self.gen_node(&child, inherit_attrs);
+    }
+    if is_fragment {
+      self.push_string("<!--]-->");
+    }
+  }
+
+  fn gen_children(&mut self, children: &Children, as_fragment: bool) {
+    if as_fragment {
+      self.push_string("<!--[-->");
+    }
+    for child in children.borrow().clone().iter() {
+      self.gen_node(&child.borrow(), false);
+    }
+    if as_fragment {
+      self.push_string("<!--]-->");
+    }
+  }
+
+  fn gen_node(&mut self, node: &Node, inherit_attrs: bool) {
+    match node {
+      Node::ElementNode(el) => self.gen_element(el, inherit_attrs),
+      Node::Text(text) => self.push_string(&escape_html(&text.content)),
+      Node::Comment(comment) => self.push_string(&format!("<!--{}-->", comment.content)),
+      Node::Interpolation(interpolation) => {
+        let interpolate = self.ssr_helper(SSR_INTERPOLATE);
+        let exp = self.expression(&interpolation.content);
+        self.push_exp(&format!("{}({})", interpolate, exp));
+      },
+      Node::If(if_node) => self.gen_if(if_node, inherit_attrs),
+      Node::For(for_node) => self.gen_for(for_node),
+      _ => {},
+    }
+  }
+
+  fn gen_element(&mut self, el: &ElementNodeBase, inherit_attrs: bool) {
+    match el.tag_type {
+      ElementTagType::ComponentNode => self.gen_component(el, inherit_attrs),
+      ElementTagType::SlotOutletNode => self.gen_slot_outlet(el),
+      // 没有指令的 `<template>` 只渲染子节点
+      ElementTagType::TemplateNode => self.gen_children(&el.children, false),
+      ElementTagType::PlainElementNode => self.gen_plain_element(el, inherit_attrs),
+    }
+  }
+
+  fn gen_plain_element(&mut self, el: &ElementNodeBase, inherit_attrs: bool) {
+    let props = self.collect_props(el, false);
+    self.push_string(&format!("<{}", el.tag_name));
+    match props.inline_attrs().filter(|_| !inherit_attrs) {
+      Some(attrs) => self.gen_inline_attrs(attrs, props.show.as_deref()),
+      None => {
+        let render_attrs = self.ssr_helper(SSR_RENDER_ATTRS);
+        let args = self.merge_props_args(&props, inherit_attrs);
+        let props = self.merge_props(args).unwrap_or_else(|| "{}".to_string());
+        self.push_exp(&format!("{}({})", render_attrs, props));
+      },
+    }
+    self.push_string(">");
+    if is_html_void_tag(&el.tag_name) {
+      return;
+    }
+    match &props.content {
+      Some(content) => self.push_exp(content),
+      None => self.gen_children(&el.children, false),
+    }
+    self.push_string(&format!("</{}>", el.tag_name));
+  }
+
+  fn gen_inline_attrs(&mut self, attrs: &[Attr], show: Option<&str>) {
+    let mut has_style = false;
+    for attr in attrs.iter() {
+      match (&attr.key, &attr.value) {
+        (AttrKey::Static(name), AttrValue::Exp(exp)) if name == "class" => {
+          let render_class = self.ssr_helper(SSR_RENDER_CLASS);
+          self.push_string(" class=\"");
+          self.push_exp(&format!("{}({})", render_class, exp));
+          self.push_string("\"");
+        },
+        (AttrKey::Static(name), value) if name == "style" && (show.is_some() || matches!(value, AttrValue::Exp(_))) => {
+          has_style = true;
+          self.gen_style_attr(Some(attr_value_code(value)), show);
+        },
+        (AttrKey::Static(name), AttrValue::Empty) => self.push_string(&format!(" {}", name)),
+        (AttrKey::Static(name), AttrValue::Static(value)) => {
+          if name == "style" {
+            has_style = true;
+          }
+          self.push_string(&format!(" {}=\"{}\"", name, escape_html(value)));
+        },
+        (AttrKey::Static(name), AttrValue::Exp(exp)) if is_boolean_attr(name) => {
+          let include_boolean_attr = self.ssr_helper(SSR_INCLUDE_BOOLEAN_ATTR);
+          self.push_exp(&format!(
+            "({}({})) ? \" {}\" : \"\"",
+            include_boolean_attr, exp, name,
+          ));
+        },
+        (AttrKey::Static(name), AttrValue::Exp(exp)) => {
+          let render_attr = self.ssr_helper(SSR_RENDER_ATTR);
+          self.push_exp(&format!("{}({}, {})", render_attr, to_js_string_literal(name), exp));
+        },
+        (AttrKey::Dynamic(key), value) => {
+          let render_dynamic_attr = self.ssr_helper(SSR_RENDER_DYNAMIC_ATTR);
+          self.push_exp(&format!("{}({}, {})", render_dynamic_attr, key, attr_value_code(value)));
+        },
+      }
+    }
+    if !has_style && show.is_some() {
+      self.gen_style_attr(None, show);
+    }
+  }
+
+  /// ` style="${_ssrRenderStyle([_ctx.style, _ctx.ok ? null : { display: "none" }])}"`
+  fn gen_style_attr(&mut self, style: Option<String>, show: Option<&str>) {
+    let render_style = self.ssr_helper(SSR_RENDER_STYLE);
+    let values = style.into_iter().chain(show.map(show_style)).collect::<Vec<_>>();
+    let value = match &values[..] {
+      [value] => value.clone(),
+      values => format!("[{}]", values.join(", ")),
+    };
+    self.push_string(" style=\"");
+    self.push_exp(&format!("{}({})", render_style, value));
+    self.push_string("\"");
+  }
+
+  /// `_mergeProps` 的参数, 根节点最后合并透传的 `_attrs`
+  fn merge_props_args(&mut self, props: &ElementProps, inherit_attrs: bool) -> Vec<String> {
+    let mut args = props.segments
+      .iter()
+      .map(|segment| match segment {
+        PropsSegment::Attrs(attrs) => object_code(attrs),
+        PropsSegment::Object(exp) => exp.clone(),
+      })
+      .collect::<Vec<_>>();
+    if let Some(show) = &props.show {
+      args.push(format!("{{ style: {} }}", show_style(show)));
+    }
+    if inherit_attrs {
+      args.push("_attrs".to_string());
+    }
+    args
+  }
+
+  fn merge_props(&mut self, args: Vec<String>) -> Option<String> {
+    match args.len() {
+      0 => None,
+      1 => args.into_iter().next(),
+      _ => {
+        let merge_props = self.helper(MERGE_PROPS);
+        Some(format!("{}({})", merge_props, args.join(", ")))
+      },
+    }
+  }
+
+  fn collect_props(&mut self, el: &ElementNodeBase, is_component: bool) -> ElementProps {
+    let mut result = ElementProps::default();
+    let props = el.props.borrow();
+    for prop in props.iter() {
+      match prop {
+        Prop::Normal(p) => {
+          // ref 只在客户端有意义, `is` 用于动态组件
+          if p.name == "ref" || p.name == "key" || p.name == "is" {
+            continue;
+          }
+          let value = match &p.value {
+            Some(value) => AttrValue::Static(value.content.clone()),
+            None => AttrValue::Empty,
+          };
+          result.push(AttrKey::Static(p.name.clone()), value);
+        },
+        Prop::Directive(dir) => self.collect_directive(el, &props, dir, is_component, &mut result),
+      }
+    }
+    result
+  }
+
+  fn collect_directive(
+    &mut self,
+    el: &ElementNodeBase,
+    props: &[Prop],
+    dir: &DirectiveProp,
+    is_component: bool,
+    result: &mut ElementProps,
+  ) {
+    let Some(exp) = dir.exp.as_ref().map(|exp| self.expression(exp)) else {
+      return;
+    };
+    match dir.name.as_str() {
+      "bind" => match &dir.arg {
+        None => result.segments.push(PropsSegment::Object(exp)),
+        Some(ExpressionNode::SimpleExpressionNode(arg)) if arg.is_static => {
+          if arg.content != "key" && arg.content != "ref" {
+            result.push(AttrKey::Static(arg.content.clone()), AttrValue::Exp(exp));
+          }
+        },
+        Some(arg) => {
+          let arg = self.expression(arg);
+          result.push(AttrKey::Dynamic(arg), AttrValue::Exp(exp));
+        },
+      },
+      "show" => result.show = Some(exp),
+      "html" => result.content = Some(exp),
+      "text" => {
+        let interpolate = self.ssr_helper(SSR_INTERPOLATE);
+        result.content = Some(format!("{}({})", interpolate, exp));
+      },
+      "model" if is_component => {
+        result.push(AttrKey::Static("modelValue".to_string()), AttrValue::Exp(exp));
+      },
+      "model" => match el.tag_name.as_str() {
+        "input" => {
+          let (key, value) = self.input_model(props, exp);
+          result.push(AttrKey::Static(key.to_string()), AttrValue::Exp(value));
+        },
+        "textarea" => {
+          let interpolate = self.ssr_helper(SSR_INTERPOLATE);
+          result.content = Some(format!("{}({})", interpolate, exp));
+        },
+        // todo `<select v-model>` 需要在 `<option>` 上渲染 selected
+        _ => {},
+      },
+      // v-on, v-slot 等指令不影响服务端渲染的内容, 自定义指令暂不支持 getSSRProps
+      _ => {},
+    }
+  }
+
+  /// `<input v-model>` 根据 type 渲染 value 或 checked
+  fn input_model(&mut self, props: &[Prop], model: String) -> (&'static str, String) {
+    let static_attr = |name: &str| props.iter().find_map(|p| match p {
+      Prop::Normal(p) if p.name == name => Some(p.value.as_ref().map_or(String::new(), |v| v.content.clone())),
+      _ => None,
+    });
+    let value = match static_attr("value") {
+      Some(value) => to_js_string_literal(&value),
+      None => props.iter()
+        .find_map(|p| match p {
+          Prop::Directive(d) if d.name == "bind" && matches!(
+            &d.arg,
+            Some(ExpressionNode::SimpleExpressionNode(arg)) if arg.is_static && arg.content == "value"
+          ) => d.exp.as_ref().map(|exp| self.expression(exp)),
+          _ => None,
+        })
+        .unwrap_or_else(|| "null".to_string()),
+    };
+    match static_attr("type").as_deref() {
+      Some("checkbox") => {
+        let loose_contain = self.ssr_helper(SSR_LOOSE_CONTAIN);
+        ("checked", format!("(Array.isArray({0})) ? {1}({0}, {2}) : {0}", model, loose_contain, value))
+      },
+      Some("radio") => {
+        let loose_equal = self.ssr_helper(SSR_LOOSE_EQUAL);
+        ("checked", format!("{}({}, {})", loose_equal, model, value))
+      },
+      _ => ("value", model),
+    }
+  }
+
+  fn gen_component(&mut self, el: &ElementNodeBase, inherit_attrs: bool) {
+    // KeepAlive 和 Transition 在服务端直接渲染子节点
+    if matches!(el.tag_name.as_str(), "KeepAlive" | "keep-alive" | "Transition" | "transition") {
+      self.gen_children(&el.children, false);
+      return;
+    }
+    let component = if is_core_component(&el.tag_name) && is_builtin_symbol_tag(&el.tag_name) {
+      self.helper(&el.tag_name)
+    } else {
+      if !self.components.iter().any(|c| c == &el.tag_name) {
+        self.components.push(el.tag_name.clone());
+      }
+      to_valid_asset_id(&el.tag_name, "component")
+    };
+    let props = self.collect_props(el, true);
+    let args = self.merge_props_args(&props, inherit_attrs);
+    let props = self.merge_props(args).unwrap_or_else(|| "null".to_string());
+
+    let render_component = self.ssr_helper(SSR_RENDER_COMPONENT);
+    self.flush();
+    self.start_line(&format!("_push({}({}, {}, ", render_component, component, props));
+    self.gen_slots(el);
+    self.push(", _parent))\n");
+  }
+
+  /// `{ default: _withCtx((_, _push, _parent, _scopeId) => { ... }), _: 1 }`
+  fn gen_slots(&mut self, el: &ElementNodeBase) {
+    let mut slots: Vec<(String, String, Children)> = vec![];
+    let mut default_children = vec![];
+    for child in el.children.borrow().iter() {
+      let slot = match &*child.borrow() {
+        Node::ElementNode(template) if template.tag_type == ElementTagType::TemplateNode => {
+          find_slot_dir(&template.props.borrow()).map(|dir| (dir, template.children.clone()))
+        },
+        _ => None,
+      };
+      match slot {
+        Some((dir, children)) => {
+          let name = self.slot_name(&dir);
+          slots.push((name, slot_params(&dir), children));
+        },
+        None => default_children.push(child.clone()),
+      }
+    }
+    let has_default_content = default_children.iter().any(|c| !is_whitespace(&c.borrow()));
+    let default_dir = find_slot_dir(&el.props.borrow());
+    if has_default_content || default_dir.is_some() {
+      let params = default_dir.as_ref().map_or("_".to_string(), slot_params);
+      slots.insert(0, ("default".to_string(), params, Rc::new(RefCell::new(default_children))));
+    }
+    if slots.is_empty() {
+      self.push("null");
+      return;
+    }
+
+    let with_ctx = self.helper(WITH_CTX);
+    self.push("{\n");
+    self.indent_level += 1;
+    for (name, params, children) in slots.iter() {
+      self.line(&format!("{}: {}(({}, _push, _parent, _scopeId) => {{", name, with_ctx, params));
+      self.indent_level += 1;
+      self.line("if (_push) {");
+      self.indent_level += 1;
+      self.gen_children(children, false);
+      self.flush();
+      self.indent_level -= 1;
+      self.line("} else {");
+      self.indent_level += 1;
+      // 插槽也可能在客户端渲染(如作为 vnode 传给其他组件), 需要同时生成 vnode 版本
+      let vnodes = self.gen_vnode_fallback(children);
+      self.line(&format!("return {}", vnodes));
+      self.indent_level -= 1;
+      self.line("}");
+      self.indent_level -= 1;
+      self.line("}),");
+    }
+    // 插槽内容不依赖动态条件, 标记为稳定插槽
+    self.line("_: 1");
+    self.indent_level -= 1;
+    self.start_line("}");
+  }
+
+  fn slot_name(&mut self, dir: &DirectiveProp) -> String {
+    match &dir.arg {
+      None => "default".to_string(),
+      Some(ExpressionNode::SimpleExpressionNode(arg)) if arg.is_static => {
+        if is_simple_identifier(&arg.content) {
+          arg.content.clone()
+        } else {
+          to_js_string_literal(&arg.content)
+        }
+      },
+      Some(arg) => format!("[{}]", self.expression(arg)),
+    }
+  }
+
+  /// 用客户端的 transform 处理拷贝出的子节点, 生成 vnode 数组
+  fn gen_vnode_fallback(&mut self, children: &Children) -> String {
+    let root = Rc::new(RefCell::new(Node::Root(RootNode {
+      source: String::new(),
+      children: deep_clone_children(children),
+      helpers: vec![],
+      components: vec![],
+      directives: vec![],
+      hoists: vec![],
+      imports: vec![],
+      cached: 0,
+      temps: 0,
+      ssr_helpers: vec![],
+      code_gen_node: None,
+      filters: vec![],
+      loc: SourceLocation::default(),
+    })));
+    // 表达式已经在 SSR 的 transform 中处理过, 不能再加一次前缀。
+    // TransformVIf 只为已有的 IfNode 生成分支的 vnode
+    let mut runner = TransformRunner::new(
+      vec![Box::new(TransformVIf), Box::new(TransformText), Box::new(TransformElement)],
+      TransformContext::new(self.options.filename.clone()),
+    );
+    runner.transform(root.clone());
+
+    let root = root.borrow();
+    let Node::Root(root_node) = &*root else {
+      unreachable!();
+    };
+    let mut code_gen = CodeGen::new(&root, self.options.clone());
+    code_gen.indent_level = self.indent_level;
+    code_gen.gen_children_array(&root_node.children);
+    for helper in root_node.helpers.iter().chain(code_gen.helpers.iter()) {
+      self.helper(helper);
+    }
+    for component in root_node.components.iter() {
+      if !self.components.contains(component) {
+        self.components.push(component.clone());
+      }
+    }
+    code_gen.code
+  }
+
+  /// `_ssrRenderSlot(_ctx.$slots, "name", props, fallback, _push, _parent)`
+  fn gen_slot_outlet(&mut self, el: &ElementNodeBase) {
+    let mut name = to_js_string_literal("default");
+    let mut props = ElementProps::default();
+    for prop in el.props.borrow().iter() {
+      match prop {
+        Prop::Normal(p) if p.name == "name" => {
+          name = to_js_string_literal(p.value.as_ref().map_or("", |v| v.content.as_str()));
+        },
+        Prop::Directive(d) if d.name == "bind" && matches!(
+          &d.arg,
+          Some(ExpressionNode::SimpleExpressionNode(arg)) if arg.is_static && arg.content == "name"
+        ) => {
+          if let Some(exp) = &d.exp {
+            name = self.expression(exp);
+          }
+        },
+        Prop::Normal(p) => {
+          let value = p.value.as_ref().map_or(String::new(), |v| v.content.clone());
+          props.push(AttrKey::Static(p.name.clone()), AttrValue::Static(value));
+        },
+        Prop::Directive(d) => self.collect_directive(el, &[], d, true, &mut props),
+      }
+    }
+    let args = self.merge_props_args(&props, false);
+    let props = self.merge_props(args).unwrap_or_else(|| "{}".to_string());
+
+    let render_slot = self.ssr_helper(SSR_RENDER_SLOT);
+    self.flush();
+    self.start_line(&format!("{}(_ctx.$slots, {}, {}, ", render_slot, name, props));
+    let has_fallback = el.children.borrow().iter().any(|c| !is_whitespace(&c.borrow()));
+    if has_fallback {
+      self.push("() => {\n");
+      self.indent_level += 1;
+      self.gen_children(&el.children, false);
+      self.flush();
+      self.indent_level -= 1;
+      self.start_line("}");
+    } else {
+      self.push("null");
+    }
+    self.push(", _push, _parent)\n");
+  }
+
+  fn gen_if(&mut self, if_node: &IfNode, inherit_attrs: bool) {
+    self.flush();
+    for (index, branch) in if_node.branches.iter().enumerate() {
+      match &branch.condition {
+        Some(condition) => {
+          let condition = self.expression(condition);
+          if index == 0 {
+            self.line(&format!("if ({}) {{", condition));
+          } else {
+            self.line(&format!("}} else if ({}) {{", condition));
+          }
+        },
+        None => self.line("} else {"),
+      }
+      self.indent_level += 1;
+      self.gen_if_branch(branch, inherit_attrs);
+      self.flush();
+      self.indent_level -= 1;
+    }
+    // 没有 v-else 时输出注释占位, 与客户端的 `createCommentVNode("v-if", true)` 对应
+    if if_node.branches.last().is_some_and(|b| b.condition.is_some()) {
+      self.line("} else {");
+      self.indent_level += 1;
+      self.push_string("<!--v-if-->");
+      self.flush();
+      self.indent_level -= 1;
+    }
+    self.line("}");
+  }
+
+  fn gen_if_branch(&mut self, branch: &IfBranchNode, inherit_attrs: bool) {
+    let children = branch.children.borrow().clone();
+    match &children[..] {
+      [child] if matches!(&*child.borrow(), Node::ElementNode(_)) => {
+        self.gen_node(&child.borrow(), inherit_attrs);
+      },
+      // 列表自身已经包裹了 fragment 注释, 客户端也直接使用列表的 Fragment
+      [child] if matches!(&*child.borrow(), Node::For(_)) => self.gen_node(&child.borrow(), false),
+      _ => self.gen_children(&branch.children, true),
+    }
+  }
+
+  /// `_ssrRenderList(_ctx.list, (item) => { ... })`, 列表包裹在 fragment 注释中
+  fn gen_for(&mut self, for_node: &ForNode) {
+    self.push_string("<!--[-->");
+    self.flush();
+    let render_list = self.ssr_helper(SSR_RENDER_LIST);
+    let source = self.expression(&for_node.source);
+    self.line(&format!("{}({}, ({}) => {{", render_list, source, for_loop_params(for_node)));
+    self.indent_level += 1;
+    let item_children = for_item_children(for_node);
+    let children = item_children.borrow().clone();
+    let is_single_element = matches!(&children[..], [child] if matches!(&*child.borrow(), Node::ElementNode(_)));
+    self.gen_children(&item_children, !is_single_element);
+    self.flush();
+    self.indent_level -= 1;
+    self.line("})");
+    self.push_string("<!--]-->");
+  }
+
+  fn expression(&mut self, exp: &ExpressionNode) -> String {
+    match exp {
+      ExpressionNode::SimpleExpressionNode(exp) if exp.is_static => to_js_string_literal(&exp.content),
+      ExpressionNode::SimpleExpressionNode(exp) => exp.content.clone(),
+      ExpressionNode::CompoundExpressionNode(exp) => {
+        let mut code_gen = CodeGen::new(self.ast, self.options.clone());
+        code_gen.gen_compound_expression(exp);
+        for helper in code_gen.helpers.iter() {
+          self.helper(helper);
+        }
+        code_gen.code
+      },
+    }
+  }
+
+  /// 追加静态内容, 转义模板字符串中的特殊字符
+  fn push_string(&mut self, content: &str) {
+    self.template.push_str(&escape_template_literal(content));
+  }
+
+  /// 追加模板字符串中的插值
+  fn push_exp(&mut self, exp: &str) {
+    self.template.push_str(&format!("${{{}}}", exp));
+  }
+
+  /// 把累积的模板字符串作为一次 `_push` 输出
+  fn flush(&mut self) {
+    if self.template.is_empty() {
+      return;
+    }
+    let template = std::mem::take(&mut self.template);
+    self.line(&format!("_push(`{}`)", template));
+  }
+
+  fn push(&mut self, code: &str) {
+    self.code.push_str(code);
+  }
+
+  fn start_line(&mut self, code: &str) {
+    self.code.push_str(&"  ".repeat(self.indent_level));
+    self.code.push_str(code);
+  }
+
+  fn line(&mut self, code: &str) {
+    self.start_line(code);
+    self.code.push('\n');
+  }
+}
+
+fn escape_template_literal(content: &str) -> String {
+  content
+    .replace('\\', "\\\\")
+    .replace('`', "\\`")
+    .replace("${", "\\${")
+}
+
+fn attr_value_code(value: &AttrValue) -> String {
+  match value {
+    AttrValue::Empty => to_js_string_literal(""),
+    AttrValue::Static(value) => to_js_string_literal(value),
+    AttrValue::Exp(exp) => exp.clone(),
+  }
+}
+
+/// `{ id: "foo", "data-id": _ctx.id, [_ctx.key]: _ctx.value }`
+fn object_code(attrs: &[Attr]) -> String {
+  let properties = attrs
+    .iter()
+    .map(|attr| {
+      let key = match &attr.key {
+        AttrKey::Static(name) if is_simple_identifier(name) => name.clone(),
+        AttrKey::Static(name) => to_js_string_literal(name),
+        AttrKey::Dynamic(key) => format!("[{}]", key),
+      };
+      format!("{}: {}", key, attr_value_code(&attr.value))
+    })
+    .collect::<Vec<_>>();
+  format!("{{ {} }}", properties.join(", "))
+}
+
+fn show_style(show: &str) -> String {
+  format!("({}) ? null : {{ display: \"none\" }}", show)
+}
+
+fn find_slot_dir(props: &[Prop]) -> Option<DirectiveProp> {
+  props.iter().find_map(|p| match p {
+    Prop::Directive(d) if d.name == "slot" => Some(d.clone()),
+    _ => None,
+  })
+}
+
+fn slot_params(dir: &DirectiveProp) -> String {
+  match &dir.exp {
+    Some(ExpressionNode::SimpleExpressionNode(exp)) if !exp.content.trim().is_empty() => exp.content.clone(),
+    _ => "_".to_string(),
+  }
+}
+
+fn is_whitespace(node: &Node) -> bool {
+  matches!(node, Node::Text(text) if text.content.trim().is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+  use std::{cell::RefCell, rc::Rc};
+
+  use crate::{
+    Parser, TransformRunner, TransformContext, TransformVIf, TransformVFor, TrackSlotScopes,
+    Expression, SsrCodeGen, CodegenOptions, CodegenMode,
+  };
+
+  fn compile_with_options(source: &str, options: CodegenOptions) -> String {
+    let mut parser = Parser::new(source);
+    let ast = Rc::new(RefCell::new(parser.parse()));
+    let mut ctx = TransformContext::new("hello.vue".to_string());
+    ctx.ssr = true;
+    let mut runner = TransformRunner::new(
+      vec![
+        Box::new(TransformVIf),
+        Box::new(TransformVFor),
+        Box::new(TrackSlotScopes),
+        Box::new(Expression {}),
+      ],
+      ctx,
+    );
+    runner.transform(ast.clone());
+    let ast = ast.borrow();
+    let mut code_gen = SsrCodeGen::new(&ast, options);
+    code_gen.generate();
+    code_gen.code
+  }
+
+  fn compile(source: &str) -> String {
+    let code = compile_with_options(source, CodegenOptions::default());
+    code[code.find("function ssrRender").unwrap()..].to_string()
+  }
+
+  #[test]
+  fn test_root_attrs_fallthrough() {
+    assert_eq!(
+      compile("<div class=\"foo\">{{ msg }}</div>"),
+      r#"function ssrRender(_ctx, _push, _parent, _attrs) {
+  _push(`<div${_ssrRenderAttrs(_mergeProps({ class: "foo" }, _attrs))}>${_ssrInterpolate(_ctx.msg)}</div>`)
+}"#
+    );
+  }
+
+  #[test]
+  fn test_dynamic_arg() {
+    assert_eq!(
+      compile("<div><span :[key]=\"value\"></span></div>"),
+      r#"function ssrRender(_ctx, _push, _parent, _attrs) {
+  _push(`<div${_ssrRenderAttrs(_attrs)}><span${_ssrRenderDynamicAttr(_ctx.key, _ctx.value)}></span></div>`)
+}"#
+    );
+  }
+
+  #[test]
+  fn test_inline_attrs() {
+    assert_eq!(
+      compile("<div><span class=\"a\" :class=\"cls\" :id=\"id\" :disabled=\"off\" v-show=\"ok\">`${x}`</span></div>"),
+      r#"function ssrRender(_ctx, _push, _parent, _attrs) {
+  _push(`<div${_ssrRenderAttrs(_attrs)}><span class="${_ssrRenderClass(["a", _ctx.cls])}"${_ssrRenderAttr("id", _ctx.id)}${(_ssrIncludeBooleanAttr(_ctx.off)) ? " disabled" : ""} style="${_ssrRenderStyle((_ctx.ok) ? null : { display: "none" })}">\`\${x}\`</span></div>`)
+}"#
+    );
+  }
+
+  #[test]
+  fn test_v_model() {
+    assert_eq!(
+      compile("<div><input v-model=\"text\"/><input type=\"checkbox\" value=\"a\" v-model=\"list\"/><textarea v-model=\"text\"></textarea></div>"),
+      r#"function ssrRender(_ctx, _push, _parent, _attrs) {
+  _push(`<div${_ssrRenderAttrs(_attrs)}><input${_ssrRenderAttr("value", _ctx.text)}><input type="checkbox" value="a"${(_ssrIncludeBooleanAttr((Array.isArray(_ctx.list)) ? _ssrLooseContain(_ctx.list, "a") : _ctx.list)) ? " checked" : ""}><textarea>${_ssrInterpolate(_ctx.text)}</textarea></div>`)
+}"#
+    );
+  }
+
+  #[test]
+  fn test_v_if_and_v_for() {
+    assert_eq!(
+      compile("<div><p v-if=\"ok\">yes</p><span v-for=\"item in list\">{{ item }}</span></div>"),
+      r#"function ssrRender(_ctx, _push, _parent, _attrs) {
+  _push(`<div${_ssrRenderAttrs(_attrs)}>`)
+  if (_ctx.ok) {
+    _push(`<p>yes</p>`)
+  } else {
+    _push(`<!--v-if-->`)
+  }
+  _push(`<!--[-->`)
+  _ssrRenderList(_ctx.list, (item) => {
+    _push(`<span>${_ssrInterpolate(item)}</span>`)
+  })
+  _push(`<!--]--></div>`)
+}"#
+    );
+  }
+
+  #[test]
+  fn test_component_with_slots() {
+    assert_eq!(
+      compile("<MyComp :foo=\"bar\"><template #item=\"{ text }\">{{ text }}</template></MyComp>"),
+      r#"function ssrRender(_ctx, _push, _parent, _attrs) {
+  const _component_MyComp = _resolveComponent("MyComp")
+
+  _push(_ssrRenderComponent(_component_MyComp, _mergeProps({ foo: _ctx.bar }, _attrs), {
+    item: _withCtx(({ text }, _push, _parent, _scopeId) => {
+      if (_push) {
+        _push(`${_ssrInterpolate(text)}`)
+      } else {
+        return [_toDisplayString(text)]
+      }
+    }),
+    _: 1
+  }, _parent))
+}"#
+    );
+  }
+
+  #[test]
+  fn test_slot_outlet_and_fragment_root() {
+    assert_eq!(
+      compile("<slot name=\"header\" :title=\"title\"><h1>default</h1></slot><p>body</p>"),
+      r#"function ssrRender(_ctx, _push, _parent, _attrs) {
+  _push(`<!--[-->`)
+  _ssrRenderSlot(_ctx.$slots, "header", { title: _ctx.title }, () => {
+    _push(`<h1>default</h1>`)
+  }, _push, _parent)
+  _push(`<p>body</p><!--]-->`)
+}"#
+    );
+  }
+
+  #[test]
+  fn test_preambles() {
+    let code = compile_with_options("<div id=\"app\">{{ msg }}</div>", CodegenOptions::default());
+    assert!(code.starts_with(
+      "const { mergeProps: _mergeProps } = require(\"vue\")\n\
+       const { ssrRenderAttrs: _ssrRenderAttrs, ssrInterpolate: _ssrInterpolate } = require(\"vue/server-renderer\")\n\n\
+       return function ssrRender("
+    ));
+    let code = compile_with_options("<div id=\"app\">{{ msg }}</div>", CodegenOptions {
+      mode: CodegenMode::Module,
+      ..Default::default()
+    });
+    assert!(code.starts_with(
+      "import { mergeProps as _mergeProps } from \"vue\"\n\
+       import { ssrRenderAttrs as _ssrRenderAttrs, ssrInterpolate as _ssrInterpolate } from \"vue/server-renderer\"\n\n\
+       export function ssrRender("
+    ));
+  }
+
+  /// 同一个模板分别编译为客户端渲染函数和 SSR 渲染函数, 把两者都"渲染"为只保留结构的标记
+  /// (标签, 注释和 fragment 锚点, 文本), 水合时两者必须一致。
+  /// 字面量 `true`/`false` 的条件按字面值选择分支, 其他条件取第一个分支;
+  /// 数组字面量的列表按长度展开, 插值统一渲染为 `{{}}`
+  mod hydration {
+    use std::{cell::RefCell, rc::Rc};
+
+    use regex::Regex;
+    use swc_ecma_ast::{
+      ModuleItem, ModuleDecl, Decl, Stmt, Expr, Lit, Callee, BlockStmtOrExpr, BinaryOp, Function,
+    };
+
+    use crate::{
+      Parser, TransformRunner, TransformContext, TransformVIf, TransformVFor, TrackSlotScopes,
+      Expression, TransformElement, TransformText, CodeGen, CodegenOptions, CodegenMode,
+      parse_module_items, escape_html, is_html_void_tag,
+    };
+    use super::compile_with_options;
+
+    const INTERPOLATION: &str = "{{}}";
+
+    fn module_options() -> CodegenOptions {
+      CodegenOptions {
+        mode: CodegenMode::Module,
+        ..Default::default()
+      }
+    }
+
+    fn client_code(source: &str) -> String {
+      let mut parser = Parser::new(source);
+      let ast = Rc::new(RefCell::new(parser.parse()));
+      let mut runner = TransformRunner::new(
+        vec![
+          Box::new(TransformVIf),
+          Box::new(TransformVFor),
+          Box::new(TrackSlotScopes),
+          Box::new(Expression {}),
+          Box::new(TransformText),
+          Box::new(TransformElement),
+        ],
+        TransformContext::new("hello.vue".to_string()),
+      );
+      runner.transform(ast.clone());
+      let ast = ast.borrow();
+      let mut code_gen = CodeGen::new(&ast, module_options());
+      code_gen.generate();
+      code_gen.code
+    }
+
+    fn exported_function(code: &str, name: &str) -> Function {
+      parse_module_items(code)
+        .into_iter()
+        .find_map(|item| match item {
+          ModuleItem::ModuleDecl(ModuleDecl::ExportDecl(export)) => match export.decl {
+            Decl::Fn(f) if &*f.ident.sym == name => Some(*f.function),
+            _ => None,
+          },
+          _ => None,
+        })
+        .unwrap_or_else(|| panic!("`{}` not found in:\n{}", name, code))
+    }
+
+    fn pick(test: &Expr) -> bool {
+      match test {
+        Expr::Lit(Lit::Bool(b)) => b.value,
+        Expr::Paren(p) => pick(&p.expr),
+        _ => true,
+      }
+    }
+
+    fn list_len(source: &Expr) -> usize {
+      match source {
+        Expr::Array(arr) => arr.elems.len(),
+        Expr::Lit(Lit::Num(n)) => n.value as usize,
+        _ => 1,
+      }
+    }
+
+    fn call_parts(expr: &Expr) -> Option<(String, Vec<&Expr>)> {
+      let Expr::Call(call) = expr else {
+        return None;
+      };
+      let Callee::Expr(callee) = &call.callee else {
+        return None;
+      };
+      let Expr::Ident(ident) = &**callee else {
+        return None;
+      };
+      Some((ident.sym.to_string(), call.args.iter().map(|a| &*a.expr).collect()))
+    }
+
+    fn client_vnode(expr: &Expr) -> String {
+      match expr {
+        Expr::Paren(p) => client_vnode(&p.expr),
+        Expr::Seq(seq) => client_vnode(seq.exprs.last().unwrap()),
+        Expr::Cond(cond) => client_vnode(if pick(&cond.test) { &cond.cons } else { &cond.alt }),
+        Expr::Lit(Lit::Str(s)) => escape_html(&s.value),
+        Expr::Lit(Lit::Null(_)) => String::new(),
+        Expr::Bin(bin) if bin.op == BinaryOp::Add => format!("{}{}", client_vnode(&bin.left), client_vnode(&bin.right)),
+        Expr::Array(arr) => arr.elems.iter().flatten().map(|e| client_vnode(&e.expr)).collect(),
+        _ => {
+          let (callee, args) = call_parts(expr).unwrap_or_else(|| panic!("unsupported: {:?}", expr));
+          match callee.as_str() {
+            "_createElementVNode" | "_createElementBlock" | "_createVNode" | "_createBlock" => {
+              let children = args.get(2).map_or(String::new(), |c| client_vnode(c));
+              match args[0] {
+                Expr::Ident(i) if &*i.sym == "_Fragment" => format!("<!--[-->{}<!--]-->", children),
+                Expr::Lit(Lit::Str(tag)) if is_html_void_tag(&tag.value) => format!("<{}>", tag.value),
+                Expr::Lit(Lit::Str(tag)) => format!("<{0}>{1}</{0}>", tag.value, children),
+                tag => panic!("unsupported tag: {:?}", tag),
+              }
+            },
+            "_createCommentVNode" => format!("<!--{}-->", client_vnode(args[0])),
+            "_createTextVNode" => args.first().map_or(" ".to_string(), |t| client_vnode(t)),
+            "_toDisplayString" => INTERPOLATION.to_string(),
+            "_withDirectives" => client_vnode(args[0]),
+            "_renderList" => {
+              let Expr::Arrow(arrow) = args[1] else {
+                panic!("unsupported renderList callback");
+              };
+              let item = match &*arrow.body {
+                BlockStmtOrExpr::Expr(e) => client_vnode(e),
+                BlockStmtOrExpr::BlockStmt(block) => block.stmts
+                  .iter()
+                  .find_map(|s| match s {
+                    Stmt::Return(r) => r.arg.as_ref().map(|a| client_vnode(a)),
+                    _ => None,
+                  })
+                  .unwrap_or_default(),
+              };
+              item.repeat(list_len(args[0]))
+            },
+            callee => panic!("unsupported helper: {}", callee),
+          }
+        },
+      }
+    }
+
+    fn ssr_stmts(stmts: &[Stmt]) -> String {
+      stmts.iter().map(ssr_stmt).collect()
+    }
+
+    fn ssr_stmt(stmt: &Stmt) -> String {
+      match stmt {
+        Stmt::Block(block) => ssr_stmts(&block.stmts),
+        Stmt::If(if_stmt) => match (pick(&if_stmt.test), &if_stmt.alt) {
+          (true, _) => ssr_stmt(&if_stmt.cons),
+          (false, Some(alt)) => ssr_stmt(alt),
+          (false, None) => String::new(),
+        },
+        Stmt::Decl(_) => String::new(),
+        Stmt::Expr(e) => {
+          let (callee, args) = call_parts(&e.expr).unwrap_or_else(|| panic!("unsupported: {:?}", e));
+          match (callee.as_str(), args[0]) {
+            ("_push", Expr::Tpl(tpl)) => {
+              let mut html = String::new();
+              for (index, quasi) in tpl.quasis.iter().enumerate() {
+                html.push_str(quasi.cooked.as_deref().unwrap_or_default());
+                // 属性相关的插值在比较时会和属性一起去掉
+                if let Some(exp) = tpl.exprs.get(index) {
+                  if matches!(call_parts(exp), Some((callee, _)) if callee == "_ssrInterpolate") {
+                    html.push_str(INTERPOLATION);
+                  }
+                }
+              }
+              html
+            },
+            ("_ssrRenderList", source) => {
+              let Expr::Arrow(arrow) = args[1] else {
+                panic!("unsupported ssrRenderList callback");
+              };
+              let BlockStmtOrExpr::BlockStmt(block) = &*arrow.body else {
+                panic!("unsupported ssrRenderList callback");
+              };
+              ssr_stmts(&block.stmts).repeat(list_len(source))
+            },
+            (callee, _) => panic!("unsupported statement: {}", callee),
+          }
+        },
+        stmt => panic!("unsupported statement: {:?}", stmt),
+      }
+    }
+
+    fn client_markup(source: &str) -> String {
+      let render = exported_function(&client_code(source), "render");
+      let body = render.body.unwrap();
+      body.stmts
+        .iter()
+        .find_map(|s| match s {
+          Stmt::Return(r) => r.arg.as_ref().map(|a| client_vnode(a)),
+          _ => None,
+        })
+        .unwrap()
+    }
+
+    fn ssr_markup(source: &str) -> String {
+      let render = exported_function(&compile_with_options(source, module_options()), "ssrRender");
+      let html = ssr_stmts(&render.body.unwrap().stmts);
+      Regex::new(r"<([a-zA-Z][\w-]*)[^>]*>").unwrap().replace_all(&html, "<$1>").to_string()
+    }
+
+    fn assert_hydratable(source: &str, expected: &str) {
+      assert_eq!(ssr_markup(source), expected, "ssr markup of {}", source);
+      assert_eq!(client_markup(source), expected, "client vnode tree of {}", source);
+    }
+
+    #[test]
+    fn test_v_if_placeholder() {
+      assert_hydratable(
+        "<div><p v-if=\"false\">a</p><span class=\"s\">{{ msg }}</span></div>",
+        "<div><!--v-if--><span>{{}}</span></div>",
+      );
+    }
+
+    #[test]
+    fn test_fragments() {
+      assert_hydratable(
+        "<p>a</p><template v-if=\"true\"><b>x</b></template><template v-else><i>1</i><i>2</i></template>",
+        "<!--[--><p>a</p><b>x</b><!--]-->",
+      );
+      assert_hydratable(
+        "<div><template v-if=\"false\"><b>x</b></template><template v-else><i>1</i>{{ a }}</template></div>",
+        "<div><!--[--><i>1</i>{{}}<!--]--></div>",
+      );
+    }
+
+    #[test]
+    fn test_list_in_branch() {
+      assert_hydratable(
+        "<ul><template v-if=\"ok\"><li v-for=\"i in [1, 2]\">{{ i }}</li></template></ul>",
+        "<ul><!--[--><li>{{}}</li><li>{{}}</li><!--]--></ul>",
+      );
+      assert_hydratable(
+        "<div><template v-for=\"i in [1, 2]\"><b>{{ i }}</b>!</template></div>",
+        "<div><!--[--><!--[--><b>{{}}</b>!<!--]--><!--[--><b>{{}}</b>!<!--]--><!--]--></div>",
+      );
+    }
+
+    #[test]
+    fn test_listeners_do_not_change_markup() {
+      assert_hydratable(
+        "<form><input @input=\"onInput\"/><button @click=\"submit\">go</button></form>",
+        "<form><input><button>go</button></form>",
+      );
+    }
+  }
+}
@@ -0,0 +1,492 @@
+use std::{cell::RefCell, rc::Rc};
+
+use crate::{
+  Node,
+  TransformContext,
+  ConstantTypes,
+  ElementTagType,
+  ElementNodeBase,
+  ExpressionNode,
+  CompoundExpressionNodeChild,
+  TextCallNodeContent,
+  PropExpression,
+  JsChildNode,
+  Prop,
+  VnodeCallChildren,
+  ArrayExpression,
+  ElementValue,
+  PatchFlags,
+  SimpleExpressionNode,
+  find_prop,
+  parse_patch_flag,
+};
+
+pub fn hoist_static(root: &Node, ctx: &mut TransformContext) {
+  if let Node::Root(root) = root {
+    let children = root.children.clone();
+    // 单个根元素会被编译成 block, 不能被提升
+    let do_not_hoist_node = is_single_element_root(&children.borrow());
+    walk(&children, None, ctx, do_not_hoist_node);
+  }
+}
+
+pub fn is_single_element_root(children: &[Rc<RefCell<Node>>]) -> bool {
+  if children.len() != 1 {
+    return false;
+  }
+  match &*children[0].borrow() {
+    Node::ElementNode(el) => el.tag_type != ElementTagType::SlotOutletNode,
+    _ => false,
+  }
+}
+
+fn walk(
+  children: &Rc<RefCell<Vec<Rc<RefCell<Node>>>>>,
+  parent: Option<&mut ElementNodeBase>,
+  ctx: &mut TransformContext,
+  do_not_hoist_node: bool,
+) {
+  let nodes = children.borrow().clone();
+  let original_count = nodes.len();
+  let mut hoisted_count = 0;
+
+  for child in nodes.iter() {
+    let mut child = child.borrow_mut();
+
+    if let Node::ElementNode(el) = &mut *child {
+      if el.tag_type == ElementTagType::PlainElementNode {
+        let constant_type = if do_not_hoist_node {
+          ConstantTypes::NotConstant
+        } else {
+          get_element_constant_type(el)
+        };
+
+        if constant_type >= ConstantTypes::CanHoist {
+          if hoist_vnode_call(el, ctx).is_some() {
+            hoisted_count += 1;
+            continue;
+          }
+        } else if constant_type == ConstantTypes::NotConstant {
+          // 节点本身不能提升, 但是静态的 props 依然可以提升
+          hoist_props(el, ctx);
+        }
+      }
+
+      let is_component = el.tag_type == ElementTagType::ComponentNode;
+      if is_component {
+        ctx.scopes.v_slot += 1;
+      }
+      let el_children = el.children.clone();
+      walk(&el_children, Some(el), ctx, false);
+      if is_component {
+        ctx.scopes.v_slot -= 1;
+      }
+      continue;
+    }
+
+    // 静态文本的 `createTextVNode(...)` 也可以提升
+    if get_constant_type(&child) >= ConstantTypes::CanHoist {
+      if let Node::TextCall(text_call) = &mut *child {
+        let exp = ctx.hoist(JsChildNode::CallExpression(text_call.code_gen.clone()));
+        text_call.hoisted = Some(exp);
+        hoisted_count += 1;
+        continue;
+      }
+    }
+
+    if let Node::If(if_node) = &mut *child {
+      for branch in if_node.branches.iter() {
+        let do_not_hoist = branch.children.borrow().len() == 1;
+        walk(&branch.children, None, ctx, do_not_hoist);
+      }
+    }
+
+    if let Node::For(for_node) = &mut *child {
+      // 单个子节点会作为每次循环的 block
+      let do_not_hoist = for_node.children.borrow().len() == 1;
+      ctx.scopes.v_for += 1;
+      walk(&for_node.children, None, ctx, do_not_hoist);
+      ctx.scopes.v_for -= 1;
+    }
+  }
+
+  if hoisted_count > 0 {
+    if let Some(transform_hoist) = ctx.transform_hoist {
+      transform_hoist(&mut children.borrow_mut(), ctx);
+    }
+  }
+
+  // 子节点全部被提升时, 子节点数组本身也可以提升
+  if hoisted_count == 0 || hoisted_count != original_count {
+    return;
+  }
+  if let Some(parent) = parent {
+    if parent.tag_type != ElementTagType::PlainElementNode {
+      return;
+    }
+    if let Some(vnode_call) = parent.code_gen.as_mut().and_then(|c| c.vnode_call.as_mut()) {
+      if let Some(VnodeCallChildren::TemplateChildNodes(_)) = vnode_call.children {
+        let elements = children.borrow().iter()
+          .filter_map(|child| get_hoisted_node(&child.borrow()).cloned())
+          .map(|exp| ElementValue::ExpressionNode(
+            JsChildNode::ExpressionNode(ExpressionNode::SimpleExpressionNode(exp))))
+          .collect();
+        let exp = ctx.hoist(JsChildNode::ArrayExpression(ArrayExpression { elements }));
+        vnode_call.children = Some(VnodeCallChildren::SimpleExpressionNode(exp));
+      }
+    }
+  }
+}
+
+/// 已提升的元素或文本对应的 `_hoisted_N`
+pub fn get_hoisted_node(node: &Node) -> Option<&SimpleExpressionNode> {
+  match node {
+    Node::ElementNode(el) => el.code_gen.as_ref()?.simple_expression_node.as_ref(),
+    Node::TextCall(text_call) => text_call.hoisted.as_ref(),
+    _ => None,
+  }
+}
+
+/// `_hoisted_N` 在 `TransformContext::hoists` 中的下标
+pub fn get_hoisted_index(exp: &SimpleExpressionNode) -> Option<usize> {
+  if exp.constant_type != ConstantTypes::CanHoist {
+    return None;
+  }
+  let index = exp.content.strip_prefix("_hoisted_")?.parse::<usize>().ok()?;
+  index.checked_sub(1)
+}
+
+fn hoist_vnode_call(
+  el: &mut ElementNodeBase,
+  ctx: &mut TransformContext
+) -> Option<SimpleExpressionNode> {
+  let code_gen = el.code_gen.as_mut()?;
+  let mut vnode_call = code_gen.vnode_call.take()?;
+  vnode_call.patch_flag = Some(format!("{} /* HOISTED */", PatchFlags::HOISTED.bits()));
+  // 只有不带指令的 svg/foreignObject 会走到这里, 提升后不再需要 block
+  vnode_call.is_block = false;
+  let exp = ctx.hoist(JsChildNode::VnodeCall(vnode_call));
+  code_gen.simple_expression_node = Some(exp.clone());
+  Some(exp)
+}
+
+fn hoist_props(el: &mut ElementNodeBase, ctx: &mut TransformContext) {
+  if get_generated_props_constant_type(el) < ConstantTypes::CanHoist {
+    return;
+  }
+  let vnode_call = match el.code_gen.as_mut().and_then(|c| c.vnode_call.as_mut()) {
+    Some(vnode_call) => vnode_call,
+    None => return,
+  };
+  // 与 compiler-core 一致, 只有没有 patch flag 或者只有 NEED_PATCH/TEXT 时提升 props
+  let flag = vnode_call.patch_flag.as_deref().and_then(parse_patch_flag);
+  if !matches!(flag, None | Some(PatchFlags::NEED_PATCH) | Some(PatchFlags::TEXT)) {
+    return;
+  }
+  if let Some(PropExpression::ObjectExpression(obj)) = vnode_call.props.take() {
+    let exp = ctx.hoist(JsChildNode::ObjectExpression(obj));
+    vnode_call.props = Some(PropExpression::SimpleExpression(
+      ExpressionNode::SimpleExpressionNode(exp)));
+  }
+}
+
+pub fn get_constant_type(node: &Node) -> ConstantTypes {
+  match node {
+    Node::ElementNode(el) => get_element_constant_type(el),
+    Node::Text(_) | Node::Comment(_) => ConstantTypes::CanStringify,
+    Node::Interpolation(n) => get_expression_constant_type(&n.content),
+    Node::TextCall(n) => match &n.content {
+      TextCallNodeContent::TextNode(_) => ConstantTypes::CanStringify,
+      TextCallNodeContent::InterpolationNode(n) => get_expression_constant_type(&n.content),
+      TextCallNodeContent::CompoundExpressionNode(n) => {
+        get_expression_constant_type(&ExpressionNode::CompoundExpressionNode(n.clone()))
+      },
+    },
+    Node::SimpleExpression(n) => n.constant_type,
+    Node::CompoundExpression(n) => {
+      get_expression_constant_type(&ExpressionNode::CompoundExpressionNode(n.clone()))
+    },
+    _ => ConstantTypes::NotConstant,
+  }
+}
+
+pub fn get_expression_constant_type(exp: &ExpressionNode) -> ConstantTypes {
+  match exp {
+    ExpressionNode::SimpleExpressionNode(n) => n.constant_type,
+    ExpressionNode::CompoundExpressionNode(n) => {
+      let mut return_type = ConstantTypes::CanStringify;
+      for child in n.children.iter() {
+        let child_type = match child {
+          CompoundExpressionNodeChild::SimpleExpressionNode(n) => n.constant_type,
+          CompoundExpressionNodeChild::InterpolationNode(n) => {
+            get_expression_constant_type(&n.content)
+          },
+          CompoundExpressionNodeChild::TextNode(_) |
+          CompoundExpressionNodeChild::RawText(_) => continue,
+        };
+        if child_type == ConstantTypes::NotConstant {
+          return ConstantTypes::NotConstant;
+        }
+        return_type = return_type.min(child_type);
+      }
+      return_type
+    },
+  }
+}
+
+pub fn get_element_constant_type(el: &ElementNodeBase) -> ConstantTypes {
+  if el.tag_type != ElementTagType::PlainElementNode {
+    return ConstantTypes::NotConstant;
+  }
+
+  let vnode_call = match el.code_gen.as_ref().and_then(|c| c.vnode_call.as_ref()) {
+    Some(vnode_call) => vnode_call,
+    None => return ConstantTypes::NotConstant,
+  };
+
+  let is_svg = el.tag_name == "svg" || el.tag_name == "foreignObject";
+  if vnode_call.is_block && !is_svg {
+    return ConstantTypes::NotConstant;
+  }
+  if vnode_call.patch_flag.is_some() {
+    return ConstantTypes::NotConstant;
+  }
+  // 模板 ref 需要在每次渲染时重新设置
+  if find_prop(el.clone(), "ref").is_some() {
+    return ConstantTypes::NotConstant;
+  }
+
+  let mut return_type = ConstantTypes::CanStringify;
+
+  let props_type = get_generated_props_constant_type(el);
+  if props_type == ConstantTypes::NotConstant {
+    return ConstantTypes::NotConstant;
+  }
+  return_type = return_type.min(props_type);
+
+  for child in el.children.borrow().iter() {
+    let child_type = get_constant_type(&child.borrow());
+    if child_type == ConstantTypes::NotConstant {
+      return ConstantTypes::NotConstant;
+    }
+    return_type = return_type.min(child_type);
+  }
+
+  for p in el.props.borrow().iter() {
+    if let Prop::Directive(d) = p {
+      // v-on, v-once 以及自定义指令等都需要运行时处理
+      if d.name != "bind" || is_svg {
+        return ConstantTypes::NotConstant;
+      }
+      let arg_type = d.arg.as_ref()
+        .map(get_expression_constant_type)
+        .unwrap_or(ConstantTypes::NotConstant);
+      let exp_type = d.exp.as_ref()
+        .map(get_expression_constant_type)
+        .unwrap_or(ConstantTypes::NotConstant);
+      let dir_type = arg_type.min(exp_type);
+      if dir_type == ConstantTypes::NotConstant {
+        return ConstantTypes::NotConstant;
+      }
+      return_type = return_type.min(dir_type);
+    }
+  }
+
+  return_type
+}
+
+pub fn get_generated_props_constant_type(el: &ElementNodeBase) -> ConstantTypes {
+  let vnode_call = match el.code_gen.as_ref().and_then(|c| c.vnode_call.as_ref()) {
+    Some(vnode_call) => vnode_call,
+    None => return ConstantTypes::NotConstant,
+  };
+
+  let mut return_type = ConstantTypes::CanStringify;
+  match &vnode_call.props {
+    None => {},
+    Some(PropExpression::ObjectExpression(obj)) => {
+      for property in obj.properties.iter() {
+        let key_type = get_expression_constant_type(&property.key);
+        if key_type == ConstantTypes::NotConstant {
+          return key_type;
+        }
+        return_type = return_type.min(key_type);
+
+        let value_type = match &*property.value {
+          JsChildNode::ExpressionNode(exp @ ExpressionNode::SimpleExpressionNode(_)) => {
+            get_expression_constant_type(exp)
+          },
+          _ => ConstantTypes::NotConstant,
+        };
+        if value_type == ConstantTypes::NotConstant {
+          return value_type;
+        }
+        return_type = return_type.min(value_type);
+      }
+    },
+    Some(_) => return ConstantTypes::NotConstant,
+  }
+  return_type
+}
+
+#[cfg(test)]
+mod tests {
+  use std::{cell::RefCell, rc::Rc};
+
+  use crate::{
+    Parser, TransformRunner, TransformContext, TransformElement, TransformText, Node,
+    JsChildNode, PropExpression, VnodeCallChildren, PatchFlags, format_patch_flag, hoist_static,
+  };
+
+  fn transform(source: &str) -> Rc<RefCell<Node>> {
+    let mut parser = Parser::new(source);
+    let ast = Rc::new(RefCell::new(parser.parse()));
+    let mut ctx = TransformContext::new("hello.vue".to_string());
+    ctx.hoist_static = true;
+    let mut runner = TransformRunner::new(vec![Box::new(TransformText), Box::new(TransformElement)], ctx);
+    runner.transform(ast.clone());
+    ast
+  }
+
+  fn root_children(ast: &Rc<RefCell<Node>>) -> Vec<Rc<RefCell<Node>>> {
+    ast.borrow().children().unwrap().borrow().clone()
+  }
+
+  fn first_child(node: &Rc<RefCell<Node>>) -> Rc<RefCell<Node>> {
+    node.borrow().children().unwrap().borrow()[0].clone()
+  }
+
+  fn hoists(ast: &Rc<RefCell<Node>>) -> Vec<Option<JsChildNode>> {
+    match &*ast.borrow() {
+      Node::Root(root) => root.hoists.clone(),
+      _ => unreachable!(),
+    }
+  }
+
+  #[test]
+  fn test_hoist_static_element() {
+    let ast = transform("<div><span>hello</span></div>");
+    let hoists = hoists(&ast);
+    assert_eq!(hoists.len(), 2);
+    match &hoists[0] {
+      Some(JsChildNode::VnodeCall(vnode_call)) => {
+        assert_eq!(vnode_call.tag, "span");
+        assert_eq!(vnode_call.patch_flag, Some("-1 /* HOISTED */".to_string()));
+      },
+      _ => panic!("expected hoisted vnode call"),
+    }
+    assert!(matches!(hoists[1], Some(JsChildNode::ArrayExpression(_))));
+
+    let root = root_children(&ast)[0].clone();
+    match &*first_child(&root).borrow() {
+      Node::ElementNode(el) => {
+        let code_gen = el.code_gen.as_ref().unwrap();
+        assert!(code_gen.vnode_call.is_none());
+        assert_eq!(code_gen.simple_expression_node.as_ref().unwrap().content, "_hoisted_1");
+      },
+      _ => panic!("expected element"),
+    };
+    match &*root.borrow() {
+      Node::ElementNode(el) => {
+        let vnode_call = el.code_gen.as_ref().unwrap().vnode_call.as_ref().unwrap();
+        match vnode_call.children.as_ref().unwrap() {
+          VnodeCallChildren::SimpleExpressionNode(exp) => assert_eq!(exp.content, "_hoisted_2"),
+          _ => panic!("expected hoisted children"),
+        }
+      },
+      _ => panic!("expected element"),
+    };
+  }
+
+  #[test]
+  fn test_do_not_hoist_single_root() {
+    let ast = transform("<div id=\"foo\"></div>");
+    let hoists = hoists(&ast);
+    assert_eq!(hoists.len(), 1);
+    assert!(matches!(hoists[0], Some(JsChildNode::ObjectExpression(_))));
+  }
+
+  #[test]
+  fn test_do_not_hoist_dynamic_element() {
+    let ast = transform("<div><span :id=\"foo\"></span><p @click=\"foo\"></p></div>");
+    assert_eq!(hoists(&ast).len(), 0);
+  }
+
+  #[test]
+  fn test_hoist_static_props() {
+    let ast = transform("<div><p id=\"foo\">{{ bar }}</p></div>");
+    let hoists = hoists(&ast);
+    assert_eq!(hoists.len(), 1);
+    assert!(matches!(hoists[0], Some(JsChildNode::ObjectExpression(_))));
+
+    let root = root_children(&ast)[0].clone();
+    match &*first_child(&root).borrow() {
+      Node::ElementNode(el) => {
+        let vnode_call = el.code_gen.as_ref().unwrap().vnode_call.as_ref().unwrap();
+        assert!(matches!(vnode_call.props, Some(PropExpression::SimpleExpression(_))));
+      },
+      _ => panic!("expected element"),
+    };
+  }
+
+  #[test]
+  fn test_hoist_props_only_with_allowed_patch_flags() {
+    let hoisted_props = |flag: PatchFlags| {
+      let mut parser = Parser::new("<div><p id=\"foo\"></p></div>");
+      let ast = Rc::new(RefCell::new(parser.parse()));
+      let ctx = TransformContext::new("hello.vue".to_string());
+      let mut runner = TransformRunner::new(vec![Box::new(TransformElement)], ctx);
+      runner.transform(ast.clone());
+      let root = root_children(&ast)[0].clone();
+      if let Node::ElementNode(el) = &mut *first_child(&root).borrow_mut() {
+        let vnode_call = el.code_gen.as_mut().unwrap().vnode_call.as_mut().unwrap();
+        vnode_call.patch_flag = Some(format_patch_flag(flag));
+      }
+      hoist_static(&ast.borrow(), &mut runner.ctx);
+      runner.ctx.hoists.len()
+    };
+    assert_eq!(hoisted_props(PatchFlags::NEED_PATCH), 1);
+    assert_eq!(hoisted_props(PatchFlags::TEXT), 1);
+    assert_eq!(hoisted_props(PatchFlags::PROPS), 0);
+    assert_eq!(hoisted_props(PatchFlags::HYDRATE_EVENTS), 0);
+  }
+
+  #[test]
+  fn test_hoist_text_call() {
+    let ast = transform("<div><span :id=\"foo\"></span>hello</div>");
+    let hoists = hoists(&ast);
+    assert_eq!(hoists.len(), 1);
+    match &hoists[0] {
+      Some(JsChildNode::CallExpression(call)) => assert_eq!(call.callee, "createTextVNode"),
+      _ => panic!("expected hoisted text call"),
+    }
+    let root = root_children(&ast)[0].clone();
+    let text = root.borrow().children().unwrap().borrow()[1].clone();
+    match &*text.borrow() {
+      Node::TextCall(text_call) => assert_eq!(text_call.hoisted.as_ref().unwrap().content, "_hoisted_1"),
+      _ => panic!("expected text call"),
+    };
+  }
+
+  #[test]
+  fn test_do_not_hoist_ref_and_component() {
+    let ast = transform("<div><span ref=\"el\"></span><Comp></Comp><p v-once></p></div>");
+    // 带 ref 的元素本身不能提升, 只有静态的 props 对象被提升
+    let hoists = hoists(&ast);
+    assert_eq!(hoists.len(), 1);
+    assert!(matches!(&hoists[0], Some(JsChildNode::ObjectExpression(_))));
+  }
+
+  #[test]
+  fn test_hoist_svg() {
+    let ast = transform("<div><svg><path d=\"M0\"></path></svg></div>");
+    let hoists = hoists(&ast);
+    match &hoists[0] {
+      Some(JsChildNode::VnodeCall(vnode_call)) => {
+        assert_eq!(vnode_call.tag, "svg");
+        assert!(!vnode_call.is_block);
+      },
+      _ => panic!("expected hoisted vnode call"),
+    }
+  }
+}
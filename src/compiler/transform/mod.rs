@@ -15,11 +15,33 @@ pub use swc_utils::*;
 mod transform_v_bind;
 pub use transform_v_bind::*;
 mod transform_v_model;
+pub use transform_v_model::*;
+mod transform_v_on;
+pub use transform_v_on::*;
 mod transform_v_if;
 pub use transform_v_if::*;
 mod transform_v_for;
 pub use transform_v_for::*;
-use crate::{Node, Property, DirectiveProp};
+mod transform_v_once;
+pub use transform_v_once::*;
+mod transform_v_memo;
+pub use transform_v_memo::*;
+mod hoist_static;
+pub use hoist_static::*;
+mod stringify_static;
+pub use stringify_static::*;
+use crate::{
+  Node, RootNode, CompilerError, ElementTagType, VnodeCall, VnodeCallChildren, PatchFlags,
+  format_patch_flag, FRAGMENT,
+  Property,
+  DirectiveProp,
+  JsChildNode,
+  SimpleExpressionNode,
+  ConstantTypes,
+  CacheExpression,
+  BindingMetadata,
+  Children,
+};
 
 
 pub trait Transform {
@@ -59,16 +81,81 @@ impl<'a> TransformRunner<'a> {
   // }
 
   pub fn travel_node(&mut self, node: Rc<RefCell<Node>>) {
+    self.ctx.current_node = Some(node);
+    let siblings = self.ctx.siblings.clone();
+    let exit_fns_start = self.ctx.exit_fns.len();
+    let pending_start = self.ctx.pending_nodes.len();
     for transform in self.transforms.iter_mut() {
+      // 前面的 transform 可能已经替换或移除了当前节点
+      let Some(node) = self.ctx.current_node.clone() else {
+        break;
+      };
       node.borrow_mut().accept_pre_transform(transform, &mut self.ctx);
     }
-    for child in node.borrow_mut().get_children().into_inner().iter_mut() {
-      self.ctx.save_parent(node.clone());
-      self.travel_node(child.clone());
+    let current = self.ctx.current_node.clone();
+    let index = self.ctx.child_index;
+    // 不在树中的节点(如合并到 IfNode 中的 v-else 分支)在当前位置遍历
+    let pending_nodes = self.ctx.pending_nodes.split_off(pending_start);
+    for pending_node in pending_nodes {
+      self.ctx.siblings = None;
+      self.travel_node(pending_node);
     }
+    let Some(node) = current else {
+      self.ctx.exit_fns.truncate(exit_fns_start);
+      return;
+    };
+    self.ctx.siblings = siblings.clone();
+
+    let children = match &*node.borrow() {
+      // v-if 在 pre_transform 中把元素替换为 IfNode, 此时只有第一个分支
+      Node::If(if_node) => if_node.branches.iter().map(|b| b.children.clone()).collect(),
+      n => n.children().into_iter().collect::<Vec<_>>(),
+    };
+    self.ctx.ancestors.push(node.clone());
+    for children in children {
+      let nodes = children.borrow().clone();
+      for child in nodes.iter() {
+        // 前面的兄弟节点可能已经被移除, 需要重新计算下标
+        self.ctx.child_index = match children.borrow().iter().position(|c| Rc::ptr_eq(c, child)) {
+          Some(index) => index,
+          None => continue,
+        };
+        self.ctx.siblings = Some(children.clone());
+        self.travel_node(child.clone());
+      }
+    }
+    self.ctx.ancestors.pop();
+
+    // 子节点的遍历会修改这些状态, 退出当前节点前恢复
+    self.ctx.current_node = Some(node.clone());
+    self.ctx.siblings = siblings;
+    self.ctx.child_index = index;
     for transform in self.transforms.iter_mut() {
       node.borrow_mut().accept_post_transform(transform, &mut self.ctx);
     }
+    // 与注册顺序相反执行, 先注册的回调最后执行
+    let exit_fns = self.ctx.exit_fns.split_off(exit_fns_start);
+    for exit_fn in exit_fns.into_iter().rev() {
+      exit_fn(&mut node.borrow_mut(), &mut self.ctx);
+    }
+  }
+
+  pub fn transform(&mut self, root: Rc<RefCell<Node>>) {
+    if let Node::Root(root) = &*root.borrow() {
+      remove_root_whitespace(root);
+    }
+    self.travel_node(root.clone());
+    if self.ctx.hoist_static {
+      hoist_static(&root.borrow(), &mut self.ctx);
+    }
+    if let Node::Root(root) = &mut *root.borrow_mut() {
+      root.code_gen_node = create_root_codegen(root, &self.ctx);
+      root.hoists = self.ctx.hoists.clone();
+      root.cached = self.ctx.cached;
+      root.helpers = self.ctx.helpers.clone();
+      root.components = self.ctx.components.clone();
+      root.directives = self.ctx.directives.clone();
+    }
   }
 
   pub fn save_parent_node(&mut self, node: Rc<RefCell<&'a mut Node>>) {
@@ -76,17 +163,117 @@ impl<'a> TransformRunner<'a> {
   }
 }
 
+/// 模板首尾以及根节点之间换行产生的空白文本不渲染, 避免单个根元素被包裹为 Fragment
+fn remove_root_whitespace(root: &RootNode) {
+  let len = root.children.borrow().len();
+  let mut index = 0;
+  root.children.borrow_mut().retain(|child| {
+    index += 1;
+    match &*child.borrow() {
+      Node::Text(text) if text.content.trim().is_empty() => {
+        !(index == 1 || index == len || text.content.contains('\n'))
+      },
+      _ => true,
+    }
+  });
+}
+
+/// 渲染函数的返回值: 单个根元素直接作为 block, 多个根节点包裹在 Fragment block 中,
+/// 文本、v-if、v-for 等单个根节点直接生成, 没有根节点时返回 null
+fn create_root_codegen(root: &RootNode, ctx: &TransformContext) -> Option<Box<Node>> {
+  let children = root.children.borrow().clone();
+  match &children[..] {
+    [] => None,
+    [child] => {
+      let child = child.borrow();
+      let vnode = match &*child {
+        Node::ElementNode(el) if el.tag_type != ElementTagType::SlotOutletNode => {
+          el.code_gen.as_ref().and_then(|c| c.vnode_call.clone())
+        },
+        _ => None,
+      };
+      match vnode {
+        Some(mut vnode) => {
+          vnode.is_block = true;
+          Some(Box::new(Node::VnodeCall(vnode)))
+        },
+        None => Some(Box::new(child.clone())),
+      }
+    },
+    _ => {
+      let mut patch_flag = PatchFlags::STABLE_FRAGMENT;
+      // 开发环境会渲染注释, 除去注释只有一个根节点时, 运行时仍然要把属性透传给这个节点
+      let non_comment_count = children
+        .iter()
+        .filter(|c| !matches!(&*c.borrow(), Node::Comment(_)))
+        .count();
+      if ctx.is_dev && non_comment_count == 1 {
+        patch_flag |= PatchFlags::DEV_ROOT_FRAGMENT;
+      }
+      Some(Box::new(Node::VnodeCall(VnodeCall {
+        tag: FRAGMENT.to_string(),
+        children: Some(VnodeCallChildren::TemplateChildNodes(root.children.clone())),
+        patch_flag: Some(format_patch_flag(patch_flag)),
+        is_block: true,
+        ..Default::default()
+      })))
+    },
+  }
+}
+
+pub type HoistTransform = fn(&mut Vec<Rc<RefCell<Node>>>, &mut TransformContext);
+
+/// 在当前节点的子节点全部转换完成后执行, 参数为当前节点(可能已被替换)
+pub type ExitFn = Box<dyn FnOnce(&mut Node, &mut TransformContext)>;
+
+#[derive(Debug, Default)]
+pub struct Scopes {
+  pub v_for: usize,
+  pub v_slot: usize,
+  pub v_pre: usize,
+  pub v_once: usize,
+}
+
 pub struct TransformContext<'a> {
   pub file_name: String,
   pub hoist_static: bool,
+  pub hoists: Vec<Option<JsChildNode>>,
+  pub transform_hoist: Option<HoistTransform>,
+  pub scopes: Scopes,
+  pub cache_handlers: bool,
+  pub cached: usize,
+  pub identifiers: HashMap<String, usize>,
+  pub helpers: Vec<String>,
+  pub binding_metadata: BindingMetadata,
+  pub props_aliases: HashMap<String, String>,
+  /// 渲染函数内联在 setup() 中, 可以直接访问 setup 中的变量
+  pub inline: bool,
+  /// 是否给模板中的标识符加上 `_ctx.` 等前缀
+  pub prefix_identifiers: bool,
+  /// 为服务端渲染转换, v-model 等指令没有对应的客户端 transform
+  pub ssr: bool,
+  /// 开发环境的输出, 生产环境不需要的辅助信息(如 DEV_ROOT_FRAGMENT)在关闭时省略
+  pub is_dev: bool,
   pub components: Vec<String>,
+  pub directives: Vec<String>,
   pub in_v_once: bool,
   pub is_ts: bool,
+  /// 当前节点在 `siblings` 中的下标
   pub child_index: usize,
-  pub current_node: Option<&'a mut Node>,
+  /// 正在转换的节点, 被移除后为 None
+  pub current_node: Option<Rc<RefCell<Node>>>,
+  /// 当前节点所在的子节点列表, 根节点没有
+  pub siblings: Option<Children>,
+  /// 从根节点到父节点的路径
+  pub ancestors: Vec<Rc<RefCell<Node>>>,
+  pub exit_fns: Vec<ExitFn>,
+  /// 等待在当前节点之后遍历的节点, 见 `traverse_node`
+  pub pending_nodes: Vec<Rc<RefCell<Node>>>,
   pub parent_node_ref: Option<Rc<RefCell<&'a mut Node>>>,
-  pub parent: Option<Rc<RefCell<Node>>>,
-  pub directive_transform: Option<HashMap<String, Box<dyn DirectiveTransform>>>,
+  /// 按指令名注册的编译时转换, 没有注册的自定义指令在运行时通过 withDirectives 处理
+  pub directive_transforms: HashMap<String, Rc<dyn DirectiveTransform>>,
+  /// 转换中遇到的错误, 不中断转换, 由调用方决定如何报告
+  pub errors: Vec<CompilerError>,
 }
 
 impl<'a> TransformContext<'a> {
@@ -94,23 +281,149 @@ impl<'a> TransformContext<'a> {
     Self {
       file_name,
       components: vec![],
+      directives: vec![],
       hoist_static: false,
+      hoists: vec![],
+      transform_hoist: None,
+      scopes: Default::default(),
+      cache_handlers: false,
+      cached: 0,
+      identifiers: HashMap::new(),
+      helpers: vec![],
+      binding_metadata: HashMap::new(),
+      props_aliases: HashMap::new(),
+      inline: false,
+      prefix_identifiers: true,
+      ssr: false,
+      is_dev: true,
       in_v_once: false,
       is_ts: false,
       child_index: 0,
       current_node: None,
+      siblings: None,
+      ancestors: vec![],
+      exit_fns: vec![],
+      pending_nodes: vec![],
       parent_node_ref: None,
-      parent: None,
-      directive_transform: None,
+      directive_transforms: HashMap::from([
+        ("bind".to_string(), Rc::new(TransformBind) as Rc<dyn DirectiveTransform>),
+        ("on".to_string(), Rc::new(TransformVOn)),
+      ]),
+      errors: vec![],
     }
   }
 
+  /// 注册指令的编译时转换, 同名的转换会被覆盖
+  pub fn add_directive_transform(&mut self, name: &str, transform: impl DirectiveTransform + 'static) {
+    self.directive_transforms.insert(name.to_string(), Rc::new(transform));
+  }
+
+  pub fn on_error(&mut self, error: CompilerError) {
+    self.errors.push(error);
+  }
+
   pub fn save_parent_node_ref(&mut self, node: Rc<RefCell<&'a mut Node>>) {
     self.parent_node_ref = Some(node);
   }
 
-  pub fn save_parent(&mut self, node: Rc<RefCell<Node>>) {
-    self.parent = Some(node);
+  pub fn current_index(&self) -> usize {
+    self.child_index
+  }
+
+  pub fn parent(&self) -> Option<Rc<RefCell<Node>>> {
+    self.ancestors.last().cloned()
+  }
+
+  pub fn ancestors(&self) -> &[Rc<RefCell<Node>>] {
+    &self.ancestors
+  }
+
+  /// 用新节点替换当前节点, 后续的 transform 和子节点遍历都作用在新节点上。
+  /// 调用方持有的 `&mut Node` 仍然指向旧节点, 替换后不应再修改它
+  pub fn replace_node(&mut self, node: Node) {
+    let node = Rc::new(RefCell::new(node));
+    if let Some(siblings) = &self.siblings {
+      siblings.borrow_mut()[self.child_index] = node.clone();
+    }
+    self.current_node = Some(node);
+  }
+
+  /// 移除兄弟节点, 没有指定时移除当前节点。当前节点被移除后不再执行剩余的 transform
+  pub fn remove_node(&mut self, node: Option<&Rc<RefCell<Node>>>) {
+    let Some(siblings) = self.siblings.clone() else {
+      return;
+    };
+    let index = match node {
+      Some(node) => siblings.borrow().iter().position(|c| Rc::ptr_eq(c, node)),
+      None => Some(self.child_index),
+    };
+    let Some(index) = index else {
+      return;
+    };
+    siblings.borrow_mut().remove(index);
+    if index == self.child_index {
+      self.current_node = None;
+    } else if index < self.child_index {
+      self.child_index -= 1;
+    }
+  }
+
+  /// 遍历不在树中的节点, 在当前节点的 pre_transform 完成后执行
+  pub fn traverse_node(&mut self, node: Node) {
+    self.pending_nodes.push(Rc::new(RefCell::new(node)));
+  }
+
+  /// 注册在当前节点的子节点转换完成后执行的回调。
+  /// 在 post_transform 之后执行, 对应 compiler-core 中排在前面的 transform 的退出函数,
+  /// 可以看到 TransformElement 生成的 vnode
+  pub fn on_exit(&mut self, exit_fn: ExitFn) {
+    self.exit_fns.push(exit_fn);
+  }
+
+  /// 记录用到的运行时 helper, 返回生成代码中的别名
+  pub fn helper(&mut self, name: &str) -> String {
+    if !self.helpers.iter().any(|h| h == name) {
+      self.helpers.push(name.to_string());
+    }
+    format!("_{}", name)
+  }
+
+  pub fn add_identifiers(&mut self, names: &[String]) {
+    for name in names.iter() {
+      *self.identifiers.entry(name.clone()).or_insert(0) += 1;
+    }
+  }
+
+  pub fn remove_identifiers(&mut self, names: &[String]) {
+    for name in names.iter() {
+      if let Some(count) = self.identifiers.get_mut(name) {
+        *count -= 1;
+        if *count == 0 {
+          self.identifiers.remove(name);
+        }
+      }
+    }
+  }
+
+  pub fn cache(&mut self, exp: JsChildNode, is_vnode: bool) -> CacheExpression {
+    let index = self.cached;
+    self.cached += 1;
+    CacheExpression {
+      index,
+      value: Box::new(exp),
+      is_vnode,
+      newline: false,
+    }
+  }
+
+  pub fn hoist(&mut self, exp: JsChildNode) -> SimpleExpressionNode {
+    self.hoists.push(Some(exp));
+    SimpleExpressionNode {
+      content: format!("_hoisted_{}", self.hoists.len()),
+      is_static: false,
+      constant_type: ConstantTypes::CanHoist,
+      loc: Default::default(),
+    }
   }
 }
 
@@ -126,3 +439,64 @@ pub struct DirectiveTransformRes {
   pub properties: Vec<Property>,
   pub need_runtime: bool,
 }
+
+#[cfg(test)]
+mod tests {
+  use std::{cell::RefCell, rc::Rc};
+
+  use crate::{Parser, Node, Transform, TransformRunner, TransformContext};
+
+  /// 移除注释, 把 `<foo>` 替换为 `<bar>`, 并记录遍历顺序
+  struct TraceTransform {
+    log: Rc<RefCell<Vec<String>>>,
+  }
+
+  impl Transform for TraceTransform {
+    fn pre_transform(&self, node: &mut Node, ctx: &mut TransformContext) {
+      let el = match node {
+        Node::Comment(_) => return ctx.remove_node(None),
+        Node::ElementNode(el) => el,
+        _ => return,
+      };
+      self.log.borrow_mut().push(format!("enter {} {} {}", el.tag_name, ctx.current_index(), ctx.ancestors().len()));
+      if el.tag_name == "foo" {
+        let mut bar = el.clone();
+        bar.tag_name = "bar".to_string();
+        ctx.replace_node(Node::ElementNode(bar));
+      }
+      let log = self.log.clone();
+      ctx.on_exit(Box::new(move |node, _| {
+        if let Node::ElementNode(el) = node {
+          log.borrow_mut().push(format!("exit {}", el.tag_name));
+        }
+      }));
+    }
+
+    fn post_transform(&self, node: &mut Node, _ctx: &mut TransformContext) {
+      if let Node::ElementNode(el) = node {
+        self.log.borrow_mut().push(format!("leave {}", el.tag_name));
+      }
+    }
+  }
+
+  #[test]
+  fn test_replace_and_remove_node() {
+    let mut parser = Parser::new("<div><!-- c --><foo><span></span></foo><b></b></div>");
+    let ast = Rc::new(RefCell::new(parser.parse()));
+    let log = Rc::new(RefCell::new(vec![]));
+    let mut runner = TransformRunner::new(
+      vec![Box::new(TraceTransform { log: log.clone() })],
+      TransformContext::new("hello.vue".to_string()),
+    );
+    runner.transform(ast.clone());
+    assert_eq!(log.borrow().join(", "), "enter div 0 1, enter foo 0 2, enter span 0 3, leave span, exit span, leave bar, exit bar, enter b 1 2, leave b, exit b, leave div, exit div");
+
+    let root = ast.borrow();
+    let div = root.children().unwrap().borrow()[0].clone();
+    let tags = div.borrow().children().unwrap().borrow().iter().map(|c| match &*c.borrow() {
+      Node::ElementNode(el) => el.tag_name.clone(),
+      _ => String::new(),
+    }).collect::<Vec<_>>();
+    assert_eq!(tags, vec!["bar", "b"]);
+  }
+}
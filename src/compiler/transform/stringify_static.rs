@@ -0,0 +1,405 @@
+use std::{cell::RefCell, rc::Rc};
+
+use crate::{
+  Node,
+  CREATE_STATIC,
+  TransformContext,
+  ElementNodeBase,
+  ElementTagType,
+  ConstantTypes,
+  ExpressionNode,
+  Prop,
+  JsChildNode,
+  CallExpression,
+  CallExpressionArgument,
+  TextCallNodeContent,
+  get_hoisted_index,
+  get_hoisted_node,
+  is_html_void_tag,
+  escape_html,
+  to_js_string_literal,
+};
+
+/// 连续静态节点数达到该值时字符串化
+const NODE_COUNT: usize = 20;
+/// 连续带属性的静态元素数达到该值时字符串化
+const ELEMENT_WITH_BINDING_COUNT: usize = 5;
+
+/// 这些标签的 innerHTML 会被浏览器重新解析, 不能字符串化
+const NON_STRINGIFIABLE_TAGS: [&str; 9] = [
+  "caption", "thead", "tr", "th", "tbody", "td", "tfoot", "colgroup", "col",
+];
+
+const KNOWN_HTML_ATTRS: [&str; 134] = [
+  "accept", "accept-charset", "accesskey", "action", "align", "allow", "alt",
+  "async", "autocapitalize", "autocomplete", "autofocus", "autoplay",
+  "background", "bgcolor", "border", "buffered", "capture", "challenge",
+  "charset", "checked", "cite", "class", "code", "codebase", "color", "cols",
+  "colspan", "content", "contenteditable", "contextmenu", "controls", "coords",
+  "crossorigin", "csp", "data", "datetime", "decoding", "default", "defer",
+  "dir", "dirname", "disabled", "download", "draggable", "dropzone", "enctype",
+  "enterkeyhint", "for", "form", "formaction", "formenctype", "formmethod",
+  "formnovalidate", "formtarget", "headers", "height", "hidden", "high", "href",
+  "hreflang", "http-equiv", "icon", "id", "importance", "inert", "integrity",
+  "ismap", "itemprop", "keytype", "kind", "label", "lang", "language",
+  "loading", "list", "loop", "low", "manifest", "max", "maxlength", "minlength",
+  "media", "min", "multiple", "muted", "name", "novalidate", "open", "optimum",
+  "pattern", "ping", "placeholder", "poster", "preload", "radiogroup",
+  "readonly", "referrerpolicy", "rel", "required", "reversed", "rows",
+  "rowspan", "sandbox", "scope", "scoped", "selected", "shape", "size",
+  "sizes", "slot", "span", "spellcheck", "src", "srcdoc", "srclang", "srcset",
+  "start", "step", "style", "summary", "tabindex", "target", "title",
+  "translate", "type", "usemap", "value", "width", "wrap", "role", "nonce",
+  "part", "inputmode", "xmlns",
+];
+
+const KNOWN_SVG_ATTRS: [&str; 64] = [
+  "xmlns:xlink", "viewBox", "d", "fill", "fill-opacity", "fill-rule",
+  "clip-rule", "clip-path", "stroke", "stroke-width", "stroke-linecap",
+  "stroke-linejoin", "stroke-miterlimit", "stroke-dasharray",
+  "stroke-dashoffset", "stroke-opacity", "cx", "cy", "r", "rx", "ry", "x", "y",
+  "x1", "x2", "y1", "y2", "dx", "dy", "points", "transform", "opacity", "mask",
+  "filter", "preserveAspectRatio", "version", "xlink:href", "gradientUnits",
+  "gradientTransform", "offset", "stop-color", "stop-opacity", "text-anchor",
+  "font-family", "font-size", "font-weight", "dominant-baseline", "focusable",
+  "markerWidth", "markerHeight", "refX", "refY", "orient", "patternUnits",
+  "pathLength", "visibility", "display", "color-interpolation-filters",
+  "fx", "fy", "spreadMethod", "textLength", "vector-effect", "shape-rendering",
+];
+
+pub fn is_stringifiable_attr(name: &str) -> bool {
+  KNOWN_HTML_ATTRS.contains(&name) ||
+  KNOWN_SVG_ATTRS.contains(&name) ||
+  name.starts_with("data-") ||
+  name.starts_with("aria-")
+}
+
+/// 把连续的大段静态节点替换成一个 `createStaticVNode("<html...>", count)` 提升,
+/// 作为 `TransformContext::transform_hoist` 使用.
+pub fn stringify_static(children: &mut Vec<Rc<RefCell<Node>>>, ctx: &mut TransformContext) {
+  // 插槽内容由子组件渲染, 不能字符串化
+  if ctx.scopes.v_slot > 0 {
+    return;
+  }
+
+  let mut node_count = 0;
+  let mut element_count = 0;
+  let mut chunk: Vec<Rc<RefCell<Node>>> = vec![];
+
+  let mut i = 0;
+  while i < children.len() {
+    let child = children[i].clone();
+    let result = {
+      let node = child.borrow();
+      if chunk.is_empty() && get_node_hoisted_index(&node).is_none() {
+        None
+      } else {
+        analyze_node(&node)
+      }
+    };
+    if let Some((nc, ec)) = result {
+      node_count += nc;
+      element_count += ec;
+      chunk.push(child);
+      i += 1;
+      continue;
+    }
+
+    i -= stringify_chunk(children, i, &chunk, node_count, element_count, ctx);
+    node_count = 0;
+    element_count = 0;
+    chunk.clear();
+    i += 1;
+  }
+  stringify_chunk(children, i, &chunk, node_count, element_count, ctx);
+}
+
+/// 返回从 children 中删除的节点数
+fn stringify_chunk(
+  children: &mut Vec<Rc<RefCell<Node>>>,
+  current_index: usize,
+  chunk: &[Rc<RefCell<Node>>],
+  node_count: usize,
+  element_count: usize,
+  ctx: &mut TransformContext,
+) -> usize {
+  if node_count < NODE_COUNT && element_count < ELEMENT_WITH_BINDING_COUNT {
+    return 0;
+  }
+
+  let html = chunk.iter()
+    .map(|node| stringify_node(&node.borrow()))
+    .collect::<String>();
+  let static_call = JsChildNode::CallExpression(CallExpression {
+    callee: CREATE_STATIC.to_string(),
+    arguments: vec![
+      CallExpressionArgument::RawText(to_js_string_literal(&html)),
+      CallExpressionArgument::RawText(chunk.len().to_string()),
+    ],
+  });
+
+  // 第一个节点沿用原来的提升变量, 其余节点的提升被清空
+  let first = get_node_hoisted_index(&chunk[0].borrow()).unwrap();
+  ctx.hoists[first] = Some(static_call);
+  for node in chunk.iter().skip(1) {
+    if let Some(index) = get_node_hoisted_index(&node.borrow()) {
+      ctx.hoists[index] = None;
+    }
+  }
+
+  let delete_count = chunk.len() - 1;
+  let start = current_index - chunk.len() + 1;
+  children.drain(start..start + delete_count);
+  delete_count
+}
+
+fn get_node_hoisted_index(node: &Node) -> Option<usize> {
+  get_hoisted_index(get_hoisted_node(node)?)
+}
+
+/// 返回 (节点数, 带属性的元素数), 不能字符串化时返回 None
+fn analyze_node(node: &Node) -> Option<(usize, usize)> {
+  match node {
+    Node::Text(text) => {
+      Some((if text.content.trim().is_empty() { 0 } else { 1 }, 0))
+    },
+    Node::TextCall(n) => match n.content {
+      TextCallNodeContent::TextNode(_) => Some((1, 0)),
+      _ => None,
+    },
+    Node::Comment(_) => Some((1, 0)),
+    Node::ElementNode(el) => {
+      el.code_gen.as_ref()?.simple_expression_node.as_ref()?;
+      let mut counts = (0, 0);
+      if analyze_element(el, &mut counts) {
+        Some(counts)
+      } else {
+        None
+      }
+    },
+    _ => None,
+  }
+}
+
+fn analyze_element(el: &ElementNodeBase, counts: &mut (usize, usize)) -> bool {
+  if el.tag_type != ElementTagType::PlainElementNode ||
+    NON_STRINGIFIABLE_TAGS.contains(&el.tag_name.as_str()) {
+    return false;
+  }
+
+  counts.0 += 1;
+  let props = el.props.borrow();
+  if !props.is_empty() {
+    counts.1 += 1;
+  }
+
+  for p in props.iter() {
+    match p {
+      Prop::Normal(p) => {
+        if !is_stringifiable_attr(&p.name) {
+          return false;
+        }
+      },
+      Prop::Directive(d) => {
+        if d.name != "bind" {
+          return false;
+        }
+        match &d.arg {
+          Some(ExpressionNode::SimpleExpressionNode(arg)) if arg.is_static => {
+            if !is_stringifiable_attr(&arg.content) {
+              return false;
+            }
+          },
+          _ => return false,
+        }
+        match &d.exp {
+          Some(ExpressionNode::SimpleExpressionNode(exp)) => {
+            if exp.constant_type < ConstantTypes::CanStringify ||
+              evaluate_constant(&exp.content).is_none() {
+              return false;
+            }
+          },
+          _ => return false,
+        }
+      }
+    }
+  }
+
+  for child in el.children.borrow().iter() {
+    match &*child.borrow() {
+      Node::ElementNode(child) => {
+        if !analyze_element(child, counts) {
+          return false;
+        }
+      },
+      Node::Text(_) | Node::Comment(_) => {
+        counts.0 += 1;
+      },
+      _ => return false,
+    }
+  }
+  true
+}
+
+/// 只计算字面量, 复杂表达式不做字符串化
+pub fn evaluate_constant(exp: &str) -> Option<String> {
+  let exp = exp.trim();
+  match exp {
+    "true" | "false" => return Some(exp.to_string()),
+    "null" | "undefined" => return Some("".to_string()),
+    _ => {},
+  }
+  if exp.parse::<f64>().is_ok() {
+    return Some(exp.to_string());
+  }
+  let quoted = exp.len() >= 2 && (
+    (exp.starts_with('\'') && exp.ends_with('\'')) ||
+    (exp.starts_with('"') && exp.ends_with('"')) ||
+    (exp.starts_with('`') && exp.ends_with('`'))
+  );
+  let inner = &exp[1..exp.len() - 1];
+  if quoted && !inner.contains(['\\', '\'', '"', '`', '$']) {
+    return Some(inner.to_string());
+  }
+  None
+}
+
+pub fn stringify_node(node: &Node) -> String {
+  match node {
+    Node::ElementNode(el) => stringify_element(el),
+    // 解析器保留了原始的 html 文本(包括实体), 不需要再次转义
+    Node::Text(text) => text.content.clone(),
+    Node::Comment(comment) => format!("<!--{}-->", comment.content),
+    Node::TextCall(n) => match &n.content {
+      TextCallNodeContent::TextNode(text) => text.content.clone(),
+      _ => String::new(),
+    },
+    _ => String::new(),
+  }
+}
+
+fn stringify_element(el: &ElementNodeBase) -> String {
+  let mut res = format!("<{}", el.tag_name);
+  for p in el.props.borrow().iter() {
+    match p {
+      Prop::Normal(p) => {
+        res.push(' ');
+        res.push_str(&p.name);
+        if let Some(value) = &p.value {
+          res.push_str(&format!("=\"{}\"", value.content.replace('"', "&quot;")));
+        }
+      },
+      Prop::Directive(d) => {
+        let arg = match &d.arg {
+          Some(ExpressionNode::SimpleExpressionNode(arg)) => &arg.content,
+          _ => continue,
+        };
+        let value = match &d.exp {
+          Some(ExpressionNode::SimpleExpressionNode(exp)) => evaluate_constant(&exp.content),
+          _ => None,
+        };
+        if let Some(value) = value {
+          res.push_str(&format!(" {}=\"{}\"", arg, escape_html(&value)));
+        }
+      }
+    }
+  }
+  res.push('>');
+  for child in el.children.borrow().iter() {
+    res.push_str(&stringify_node(&child.borrow()));
+  }
+  if !is_html_void_tag(&el.tag_name) {
+    res.push_str(&format!("</{}>", el.tag_name));
+  }
+  res
+}
+
+#[cfg(test)]
+mod tests {
+  use std::{cell::RefCell, rc::Rc};
+
+  use crate::{
+    Parser, TransformRunner, TransformContext, TransformElement, Node,
+    JsChildNode, CallExpressionArgument, Expression, stringify_static, CREATE_STATIC,
+  };
+
+  fn transform(source: &str) -> Rc<RefCell<Node>> {
+    let mut parser = Parser::new(source);
+    let ast = Rc::new(RefCell::new(parser.parse()));
+    let mut ctx = TransformContext::new("hello.vue".to_string());
+    ctx.hoist_static = true;
+    ctx.transform_hoist = Some(stringify_static);
+    let mut runner = TransformRunner::new(
+      vec![Box::new(Expression {}), Box::new(TransformElement)],
+      ctx,
+    );
+    runner.transform(ast.clone());
+    ast
+  }
+
+  fn hoists(ast: &Rc<RefCell<Node>>) -> Vec<Option<JsChildNode>> {
+    match &*ast.borrow() {
+      Node::Root(root) => root.hoists.clone(),
+      _ => unreachable!(),
+    }
+  }
+
+  #[test]
+  fn test_stringify_many_nodes() {
+    let source = format!("<div>{}</div>", "<span>foo</span>".repeat(20));
+    let ast = transform(&source);
+    let hoists = hoists(&ast);
+    match &hoists[0] {
+      Some(JsChildNode::CallExpression(call)) => {
+        assert_eq!(call.callee, CREATE_STATIC);
+        assert_eq!(
+          call.arguments[0],
+          CallExpressionArgument::RawText(format!("\"{}\"", "<span>foo</span>".repeat(20)))
+        );
+        assert_eq!(call.arguments[1], CallExpressionArgument::RawText("20".to_string()));
+      },
+      _ => panic!("expected createStaticVNode call"),
+    }
+    assert!(hoists[1..20].iter().all(|h| h.is_none()));
+
+    let root = ast.borrow().children().unwrap().borrow()[0].clone();
+    assert_eq!(root.borrow().children().unwrap().borrow().len(), 1);
+  }
+
+  #[test]
+  fn test_stringify_elements_with_attrs() {
+    let source = format!("<div>{}</div>", "<p class=\"foo\" :title=\"'bar'\">a</p>".repeat(5));
+    let ast = transform(&source);
+    match &hoists(&ast)[0] {
+      Some(JsChildNode::CallExpression(call)) => {
+        assert_eq!(
+          call.arguments[0],
+          CallExpressionArgument::RawText(
+            format!("\"{}\"", "<p class=\\\"foo\\\" title=\\\"bar\\\">a</p>".repeat(5)))
+        );
+      },
+      _ => panic!("expected createStaticVNode call"),
+    }
+  }
+
+  #[test]
+  fn test_do_not_stringify_small_chunk() {
+    let source = format!("<div>{}</div>", "<span>foo</span>".repeat(4));
+    let ast = transform(&source);
+    assert!(hoists(&ast).iter().all(|h| matches!(h, Some(JsChildNode::VnodeCall(_)) | Some(JsChildNode::ArrayExpression(_)))));
+  }
+
+  #[test]
+  fn test_do_not_stringify_unknown_attrs() {
+    let source = format!("<div>{}</div>", "<span foo=\"bar\">foo</span>".repeat(20));
+    let ast = transform(&source);
+    assert!(hoists(&ast).iter().all(|h| !matches!(h, Some(JsChildNode::CallExpression(_)))));
+  }
+
+  #[test]
+  fn test_do_not_stringify_table_rows() {
+    let source = format!("<table>{}</table>", "<tr>foo</tr>".repeat(20));
+    let ast = transform(&source);
+    assert!(hoists(&ast).iter().all(|h| !matches!(h, Some(JsChildNode::CallExpression(_)))));
+  }
+}
@@ -1,45 +1,91 @@
 use swc_common::{
   self,
-  errors::{ColorConfig, Handler},
+  util::take::Take,
   sync::Lrc,
-  FileName, SourceMap
+  BytePos, FileName, SourceMap, Span, Spanned, DUMMY_SP,
+};
+use std::{collections::HashSet, rc::Rc};
+
+use swc_ecma_ast::{
+  Module, Ident, Expr, Prop, PropName, Function, MemberExpr, MemberProp, ArrowExpr, CatchClause,
+  BlockStmt, Stmt, Decl, Pat, ObjectPatProp, ModuleItem, ExprStmt, UpdateExpr, PatOrExpr,
+  BindingIdent, CallExpr, NewExpr, TaggedTpl, SuperProp, SuperPropExpr, PrivateName,
+  LabeledStmt, BreakStmt, ContinueStmt, FnExpr, ClassExpr, FnDecl, ClassDecl, VarDeclarator,
+  ForStmt, ForInStmt, ForOfStmt, ForHead, VarDeclOrExpr,
 };
-use swc_ecma_ast::{Module, Ident, Expr, AssignExpr, ObjectLit, PropOrSpread, Prop, PropName, Function, MemberExpr, MemberProp};
 use swc_ecma_parser::{lexer::Lexer, Capturing, Parser, StringInput, Syntax};
 use swc_ecma_visit::{VisitMut, VisitMutWith};
-use swc_ecma_codegen::{text_writer::JsWriter, Emitter};
+
+use crate::{can_rewrite, IdentifierPrefixer};
 
 
 pub struct ProcessIdentifiers<'a> {
   pub source: &'a mut String,
   pub cm: Option<Lrc<SourceMap>>,
-  pub module: Option<Module>
+  pub module: Option<Module>,
+  /// 表达式源码在 SourceMap 中的起始位置, 用于把 span 换算为 `source` 中的偏移
+  pub base: u32,
+  /// 对 `source` 的修改, 生成时按位置拼接到原始源码中, 其余部分保持原样
+  pub edits: Vec<Edit>,
+  /// 按 TypeScript 解析, 解析后去掉类型相关的语法
+  pub is_ts: bool,
+  /// 按语句解析, 只有 v-on 的处理函数允许写多条语句
+  pub as_statements: bool,
+}
+
+/// 表达式的语法错误, `offset` 是错误在表达式源码中的字节偏移
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExpressionError {
+  pub message: String,
+  pub offset: usize,
 }
 
 impl<'a> ProcessIdentifiers<'a> {
-  pub fn new(source: &'a mut String) -> Self {
+  pub fn new(source: &'a mut String, is_ts: bool) -> Self {
     let cm: Lrc<SourceMap> = Default::default();
     Self {
       source,
       cm: Some(cm),
-      module: None
+      module: None,
+      base: 0,
+      edits: vec![],
+      is_ts,
+      as_statements: false,
     }
   }
-  pub fn parse(&mut self) {
+  /// 解析表达式, 默认只允许单个表达式, `as_statements` 时 (v-on 的多条语句) 允许语句;
+  /// 语法错误不会 panic, 而是返回 SWC 的错误信息和它在 `source` 中的偏移
+  pub fn parse(&mut self) -> Result<(), ExpressionError> {
     if self.cm.is_none() {
-      return;
+      return Ok(());
     }
     let cm = self.cm.clone().unwrap();
-    let handler = Handler::with_tty_emitter(
-      ColorConfig::Auto, true, false, Some(cm.clone()));
 
-    let fm = cm.new_source_file(
-        FileName::Custom("test.js".into()),
-        self.source.clone(),
-    );
+    // 表达式包在括号中解析, 这样 `a; b` 这类语句会被当作错误
+    let (source, prefix_len) = if self.as_statements {
+      (self.source.clone(), 0)
+    } else {
+      (format!("({})", self.source), 1)
+    };
+    let fm = cm.new_source_file(FileName::Anon, source);
+    self.base = fm.start_pos.0 + prefix_len as u32;
+    let to_offset = |pos: BytePos| {
+      ((pos.0 - fm.start_pos.0) as usize)
+        .saturating_sub(prefix_len)
+        .min(self.source.len())
+    };
+    let to_error = |e: swc_ecma_parser::error::Error| ExpressionError {
+      message: e.kind().msg().to_string(),
+      offset: to_offset(e.span().lo),
+    };
 
+    let syntax = if self.is_ts {
+      Syntax::Typescript(Default::default())
+    } else {
+      Syntax::Es(Default::default())
+    };
     let lexer = Lexer::new(
-        Syntax::Es(Default::default()),
+        syntax,
         Default::default(),
         StringInput::from(&*fm),
         None,
@@ -49,174 +95,564 @@ impl<'a> ProcessIdentifiers<'a> {
 
     let mut parser = Parser::new_from(capturing);
 
-    for e in parser.take_errors() {
-        e.into_diagnostic(&handler).emit();
+    let mut module = if self.as_statements {
+      parser.parse_module().map_err(to_error)?
+    } else {
+      let mut expr = parser.parse_expr().map_err(to_error)?;
+      // 括号之后还有内容, 例如 `a); b(`
+      if expr.span().hi != fm.end_pos {
+        return Err(ExpressionError {
+          message: "Unexpected token".to_string(),
+          offset: to_offset(expr.span().hi),
+        });
+      }
+      if let Expr::Paren(paren) = &mut *expr {
+        expr = paren.expr.take();
+      }
+      Module {
+        span: DUMMY_SP,
+        body: vec![ModuleItem::Stmt(Stmt::Expr(ExprStmt { span: DUMMY_SP, expr }))],
+        shebang: None,
+      }
+    };
+    if let Some(e) = parser.take_errors().into_iter().next() {
+      return Err(to_error(e));
+    }
+    // 先去掉类型, 类型中的名字不会被当作标识符改写, 生成的渲染函数也是纯 js
+    if self.is_ts {
+      let mut strip = StripTypes::new(self.base);
+      module.visit_mut_with(&mut strip);
+      self.edits.extend(strip.edits);
     }
-
-    let module = parser
-        .parse_module()
-        .map_err(|e| e.into_diagnostic(&handler).emit())
-        .expect("Failed to parse module.");
     self.module = Some(module);
+    Ok(())
   }
 
-  pub fn rewrite_identifiers(&mut self) {
+  pub fn rewrite_identifiers(&mut self, known_ids: HashSet<String>, prefixer: Rc<IdentifierPrefixer>) {
     if self.cm.is_none() {
       return;
     }
 
     if let Some(m) = &mut self.module {
-      let mut rewriter = RewriteAstNode;
+      let mut rewriter = RewriteIdentifier::new(known_ids, prefixer, self.source, self.base);
       m.visit_mut_with(&mut rewriter);
+      self.edits.extend(rewriter.edits);
     }
   }
 
-  pub fn generate(&self) -> String {
-    if self.cm.is_none() {
-      return "".to_string();
+  /// 表达式中引用的标识符, 不包括对象的 key 和成员表达式的属性名
+  pub fn collect_identifiers(&mut self) -> Vec<String> {
+    let mut collector = RewriteIdentifier::collector();
+    if let Some(m) = &mut self.module {
+      m.visit_mut_with(&mut collector);
     }
+    collector.references
+  }
 
-    let cm = self.cm.clone().unwrap();
+  /// 把改写拼接到原始源码中, 没有改写的部分(空白、换行、括号等)保持原样
+  pub fn generate(&self) -> String {
+    splice(self.source, 0, self.edits.clone())
+  }
+}
 
-    let mut code = String::new();
-    if let Some(m) = &self.module {
-      let mut buf = vec![];
+/// 源码中 `start..end` 字节范围替换为新的内容
+pub type Edit = (usize, usize, String);
+
+/// 按位置应用修改, `offset` 是 `source` 在整个表达式中的起始偏移;
+/// 去掉类型时嵌套的删除(`x! as Foo`)可能重叠, 合并为一次删除
+fn splice(source: &str, offset: usize, mut edits: Vec<Edit>) -> String {
+  edits.sort_by_key(|(start, end, _)| (*start, *end));
+  let mut code = String::new();
+  let mut cursor = offset;
+  for (start, end, content) in edits {
+    if start < cursor {
+      if content.is_empty() {
+        cursor = cursor.max(end);
+      }
+      continue;
+    }
+    code.push_str(&source[cursor - offset..start - offset]);
+    code.push_str(&content);
+    cursor = end;
+  }
+  code.push_str(&source[cursor - offset..]);
+  code
+}
 
-      {
-          let mut emitter = Emitter {
-              cfg: Default::default(),
-              cm: cm.clone(),
-              comments: None,
-              wr: JsWriter::new(cm, "\n", &mut buf, None),
-          };
+/// 改写表达式中引用的标识符为 `_ctx.xxx` 等形式, 跳过作用域内(v-for/v-slot 别名,
+/// 函数参数, catch 参数以及块级声明)的标识符, 对象的 key 和成员表达式的属性名不是引用;
+/// 没有 prefixer 时只收集引用的标识符
+pub struct RewriteIdentifier {
+  pub scopes: Vec<HashSet<String>>,
+  pub prefixer: Option<Rc<IdentifierPrefixer>>,
+  /// 访问过的标识符引用, 包括作用域内的变量
+  pub references: Vec<String>,
+  /// 改写记录为对原始源码的修改
+  pub edits: Vec<Edit>,
+  source: String,
+  base: u32,
+  /// 当前访问的标识符是赋值或自增自减的目标
+  is_lval: bool,
+}
 
-          emitter.emit_module(&m).unwrap();
-      }
+impl RewriteIdentifier {
+  pub fn new(known_ids: HashSet<String>, prefixer: Rc<IdentifierPrefixer>, source: &str, base: u32) -> Self {
+    Self {
+      scopes: vec![known_ids],
+      prefixer: Some(prefixer),
+      references: vec![],
+      edits: vec![],
+      source: source.to_string(),
+      base,
+      is_lval: false,
+    }
+  }
 
-      code = String::from_utf8_lossy(&buf).to_string()
+  pub fn collector() -> Self {
+    Self {
+      scopes: vec![],
+      prefixer: None,
+      references: vec![],
+      edits: vec![],
+      source: String::new(),
+      base: 0,
+      is_lval: false,
     }
+  }
 
-    code
+  fn offset(&self, pos: BytePos) -> usize {
+    (pos.0 - self.base) as usize
   }
-}
 
-pub struct RewriteAstNode;
+  fn slice(&self, span: Span) -> &str {
+    &self.source[self.offset(span.lo)..self.offset(span.hi)]
+  }
 
-impl VisitMut for RewriteAstNode {
-  fn visit_mut_assign_expr(&mut self, n: &mut AssignExpr) { 
-    let mut visitor: RewriteIdentifier<fn(&Ident) -> bool> = RewriteIdentifier { 
-      is_need_rewrite: None
-    }; 
+  /// 需要改写的赋值目标标识符
+  fn lval_target(&self, n: &Expr) -> Option<String> {
+    self.prefixer.as_ref()?;
+    let id = match n {
+      Expr::Assign(assign) => match &assign.left {
+        PatOrExpr::Pat(pat) => match &**pat {
+          Pat::Ident(i) => &i.id,
+          _ => return None,
+        },
+        PatOrExpr::Expr(expr) => match &**expr {
+          Expr::Ident(i) => i,
+          _ => return None,
+        },
+      },
+      Expr::Update(update) => match &*update.arg {
+        Expr::Ident(i) => i,
+        _ => return None,
+      },
+      _ => return None,
+    };
+    if self.is_local(&id.sym) || !can_rewrite(id) {
+      return None;
+    }
+    Some(id.sym.to_string())
+  }
 
-    n.visit_mut_with(&mut visitor);
+  /// 内联模式下 `let` 变量可能在运行时被赋值为 ref:
+  /// `x = y` -> `isRef(x) ? x.value = y : x = y`
+  fn rewrite_let_lval(&mut self, n: &mut Expr, prefixer: &IdentifierPrefixer, name: &str) {
+    let test = prefixer.is_ref_check(name);
+    self.references.push(name.to_string());
+    let (span, cons, alt) = match n {
+      Expr::Assign(assign) => {
+        // 右侧的改写只作用于右侧的源码, 然后拼接到两个分支中
+        let edits_start = self.edits.len();
+        assign.right.visit_mut_with(self);
+        let right_edits = self.edits.split_off(edits_start);
+        let right_span = assign.right.span();
+        let right = splice(self.slice(right_span), self.offset(right_span.lo), right_edits);
+        let op = self.source[self.offset(assign.left.span().hi)..self.offset(right_span.lo)].trim();
+        (assign.span, format!("{}.value {} {}", name, op, right), format!("{} {} {}", name, op, right))
+      },
+      Expr::Update(UpdateExpr { span, op, prefix, .. }) => {
+        let update = |target: &str| if *prefix { format!("{}{}", op, target) } else { format!("{}{}", target, op) };
+        (*span, update(&format!("{}.value", name)), update(name))
+      },
+      _ => return,
+    };
+    self.edits.push((self.offset(span.lo), self.offset(span.hi), format!("{} ? {} : {}", test, cons, alt)));
   }
 
-  fn visit_mut_function(&mut self, n: &mut Function) { 
-    let mut param_names = CollectIdentifiersName::new();
-    n.params.visit_mut_children_with(&mut param_names);
+  fn is_local(&self, name: &str) -> bool {
+    self.scopes.iter().any(|scope| scope.contains(name))
+  }
 
-    let mut visitor: RewriteIdentifier<_> = RewriteIdentifier { 
-      is_need_rewrite: Some(|i: &Ident| {
-        let params = &param_names;
-        !params.names.contains(&i.sym.to_string())
-      })
-    };
+  fn with_scope(&mut self, names: Vec<String>, f: impl FnOnce(&mut Self)) {
+    self.scopes.push(names.into_iter().collect());
+    f(self);
+    self.scopes.pop();
+  }
+}
 
-    n.body.visit_mut_children_with(&mut visitor);
+impl VisitMut for RewriteIdentifier {
+  fn visit_mut_module(&mut self, n: &mut Module) {
+    // v-on 中的多条语句可以声明变量
+    let mut names = vec![];
+    for item in n.body.iter() {
+      if let ModuleItem::Stmt(Stmt::Decl(decl)) = item {
+        collect_decl_bindings(decl, &mut names);
+      }
+    }
+    self.with_scope(names, |v| n.visit_mut_children_with(v));
   }
 
-  fn visit_mut_member_expr(&mut self, n: &mut MemberExpr) { 
-    let mut rewrite_visitor: RewriteIdentifier<fn(&Ident) -> bool> = RewriteIdentifier {
-      is_need_rewrite: None
+  fn visit_mut_expr(&mut self, n: &mut Expr) {
+    let Some(name) = self.lval_target(n) else {
+      n.visit_mut_children_with(self);
+      return;
     };
-
-    match &mut *n.obj {
-      Expr::Ident(i) => {
-        i.visit_mut_with(&mut rewrite_visitor);
+    let prefixer = self.prefixer.clone().unwrap();
+    if prefixer.is_inline_let(&name) {
+      self.rewrite_let_lval(n, &prefixer, &name);
+      return;
+    }
+    match n {
+      Expr::Assign(assign) => {
+        self.is_lval = true;
+        assign.left.visit_mut_with(self);
+        self.is_lval = false;
+        assign.right.visit_mut_with(self);
       },
-      Expr::Member(m) => {
-        self.visit_mut_member_expr(m);
+      Expr::Update(update) => {
+        self.is_lval = true;
+        update.arg.visit_mut_with(self);
+        self.is_lval = false;
       },
-      _ => {}
+      _ => n.visit_mut_children_with(self),
+    }
+  }
+
+  fn visit_mut_ident(&mut self, n: &mut Ident) {
+    self.references.push(n.sym.to_string());
+    let Some(prefixer) = &self.prefixer else {
+      return;
+    };
+    if self.is_local(&n.sym) || !can_rewrite(n) {
+      return;
+    }
+    let content = prefixer.rewrite(&n.sym, self.is_lval);
+    self.edits.push((self.offset(n.span.lo), self.offset(n.span.hi), content));
+  }
+
+  fn visit_mut_member_expr(&mut self, n: &mut MemberExpr) {
+    n.obj.visit_mut_with(self);
+    if let MemberProp::Computed(c) = &mut n.prop {
+      c.visit_mut_with(self);
+    }
+  }
+
+  fn visit_mut_super_prop_expr(&mut self, n: &mut SuperPropExpr) {
+    if let SuperProp::Computed(c) = &mut n.prop {
+      c.visit_mut_with(self);
+    }
+  }
+
+  fn visit_mut_prop_name(&mut self, n: &mut PropName) {
+    if let PropName::Computed(c) = n {
+      c.visit_mut_with(self);
+    }
+  }
+
+  fn visit_mut_prop(&mut self, n: &mut Prop) {
+    // `{ foo }` -> `{ foo: _ctx.foo }`
+    if let Prop::Shorthand(i) = n {
+      let edits_start = self.edits.len();
+      i.visit_mut_with(self);
+      if let Some((_, _, content)) = self.edits.get_mut(edits_start) {
+        *content = format!("{}: {}", i.sym, content);
+      }
+      return;
+    }
+    n.visit_mut_children_with(self);
+  }
+
+  fn visit_mut_private_name(&mut self, _n: &mut PrivateName) {}
+
+  fn visit_mut_labeled_stmt(&mut self, n: &mut LabeledStmt) {
+    n.body.visit_mut_with(self);
+  }
+
+  fn visit_mut_break_stmt(&mut self, _n: &mut BreakStmt) {}
+
+  fn visit_mut_continue_stmt(&mut self, _n: &mut ContinueStmt) {}
+
+  fn visit_mut_function(&mut self, n: &mut Function) {
+    let mut names = vec![];
+    for param in n.params.iter() {
+      collect_pat_bindings(&param.pat, &mut names);
     }
+    self.with_scope(names, |v| {
+      n.params.visit_mut_with(v);
+      n.body.visit_mut_with(v);
+    });
+  }
+
+  fn visit_mut_arrow_expr(&mut self, n: &mut ArrowExpr) {
+    let mut names = vec![];
+    for param in n.params.iter() {
+      collect_pat_bindings(param, &mut names);
+    }
+    self.with_scope(names, |v| {
+      n.params.visit_mut_with(v);
+      n.body.visit_mut_with(v);
+    });
+  }
+
+  fn visit_mut_fn_expr(&mut self, n: &mut FnExpr) {
+    let names = n.ident.iter().map(|i| i.sym.to_string()).collect();
+    self.with_scope(names, |v| n.function.visit_mut_with(v));
+  }
+
+  fn visit_mut_class_expr(&mut self, n: &mut ClassExpr) {
+    let names = n.ident.iter().map(|i| i.sym.to_string()).collect();
+    self.with_scope(names, |v| n.class.visit_mut_with(v));
+  }
+
+  fn visit_mut_fn_decl(&mut self, n: &mut FnDecl) {
+    n.function.visit_mut_with(self);
+  }
+
+  fn visit_mut_class_decl(&mut self, n: &mut ClassDecl) {
+    n.class.visit_mut_with(self);
+  }
+
+  fn visit_mut_var_declarator(&mut self, n: &mut VarDeclarator) {
+    // 声明的名字已经在作用域中, 只需要处理解构的默认值和初始值
+    n.name.visit_mut_with(self);
+    n.init.visit_mut_with(self);
+  }
+
+  fn visit_mut_catch_clause(&mut self, n: &mut CatchClause) {
+    let mut names = vec![];
+    if let Some(param) = &n.param {
+      collect_pat_bindings(param, &mut names);
+    }
+    self.with_scope(names, |v| n.body.visit_mut_with(v));
+  }
+
+  fn visit_mut_block_stmt(&mut self, n: &mut BlockStmt) {
+    let mut names = vec![];
+    for stmt in n.stmts.iter() {
+      if let Stmt::Decl(decl) = stmt {
+        collect_decl_bindings(decl, &mut names);
+      }
+    }
+    self.with_scope(names, |v| n.stmts.visit_mut_with(v));
+  }
+
+  fn visit_mut_for_stmt(&mut self, n: &mut ForStmt) {
+    let mut names = vec![];
+    if let Some(VarDeclOrExpr::VarDecl(var)) = &n.init {
+      collect_decl_bindings(&Decl::Var(var.clone()), &mut names);
+    }
+    self.with_scope(names, |v| n.visit_mut_children_with(v));
+  }
+
+  fn visit_mut_for_in_stmt(&mut self, n: &mut ForInStmt) {
+    let names = for_head_bindings(&n.left);
+    self.with_scope(names, |v| n.visit_mut_children_with(v));
+  }
+
+  fn visit_mut_for_of_stmt(&mut self, n: &mut ForOfStmt) {
+    let names = for_head_bindings(&n.left);
+    self.with_scope(names, |v| n.visit_mut_children_with(v));
+  }
+}
+
+fn for_head_bindings(head: &ForHead) -> Vec<String> {
+  let mut names = vec![];
+  if let ForHead::VarDecl(var) = head {
+    for d in var.decls.iter() {
+      collect_pat_bindings(&d.name, &mut names);
+    }
+  }
+  names
+}
 
-    match &mut n.prop {
-      MemberProp::Computed(c) => {
-        match &mut *c.expr {
-          Expr::Ident(i) => {
-            i.visit_mut_with(&mut rewrite_visitor);
-          },
-          _ => {}
+/// 收集解构模式中声明的变量名, 对象模式中的 key 不是绑定
+pub fn collect_pat_bindings(pat: &Pat, names: &mut Vec<String>) {
+  match pat {
+    Pat::Ident(i) => names.push(i.id.sym.to_string()),
+    Pat::Array(arr) => {
+      for elem in arr.elems.iter().flatten() {
+        collect_pat_bindings(elem, names);
+      }
+    },
+    Pat::Rest(rest) => collect_pat_bindings(&rest.arg, names),
+    Pat::Object(obj) => {
+      for prop in obj.props.iter() {
+        match prop {
+          ObjectPatProp::KeyValue(kv) => collect_pat_bindings(&kv.value, names),
+          ObjectPatProp::Assign(assign) => names.push(assign.key.sym.to_string()),
+          ObjectPatProp::Rest(rest) => collect_pat_bindings(&rest.arg, names),
         }
-      },
-      _ => {}
-    }
-  }
-
-  fn visit_mut_object_lit(&mut self, n: &mut ObjectLit) {
-    for p in n.props.iter_mut() {
-      if let PropOrSpread::Prop(prop) = p {
-        match prop.as_mut() {
-          Prop::Shorthand(i) => {
-            *prop = Box::new(Prop::KeyValue(swc_ecma_ast::KeyValueProp {
-              key: PropName::Ident(i.clone()),
-              value: Box::new(Expr::Ident(Ident::new(format!("_ctx.{}", i.sym.to_string()).into(), i.span.clone())))
-            }))
-          },
-          Prop::KeyValue(kv) => {
-            match &mut *kv.value {
-              Expr::Ident(i) => {
-                let mut rewrite_visitor: RewriteIdentifier<fn(&Ident) -> bool> = RewriteIdentifier {
-                  is_need_rewrite: None
-                };
-                i.visit_mut_with(&mut rewrite_visitor);
-              },
-              Expr::Fn(fn_exp) => {
-                self.visit_mut_function(&mut fn_exp.function);
-              },
-              _ => {}
-            }
-          },
-          Prop::Method(m) => {
-            self.visit_mut_function(&mut m.function);
+      }
+    },
+    Pat::Assign(assign) => collect_pat_bindings(&assign.left, names),
+    Pat::Invalid(_) | Pat::Expr(_) => {},
+  }
+}
+
+pub fn collect_decl_bindings(decl: &Decl, names: &mut Vec<String>) {
+  match decl {
+    Decl::Var(var) => {
+      for d in var.decls.iter() {
+        collect_pat_bindings(&d.name, names);
+      }
+    },
+    Decl::Fn(f) => names.push(f.ident.sym.to_string()),
+    Decl::Class(c) => names.push(c.ident.sym.to_string()),
+    _ => {},
+  }
+}
+
+/// 解析 v-slot 的参数或 v-for 的别名, 返回其中声明的变量名
+pub fn collect_param_bindings(params: &str, is_ts: bool) -> Vec<String> {
+  let mut source = format!("({}) => {{}}", params);
+  let mut process = ProcessIdentifiers::new(&mut source, is_ts);
+  if process.parse().is_err() {
+    return vec![];
+  }
+  let mut names = vec![];
+  if let Some(module) = &process.module {
+    for item in module.body.iter() {
+      if let ModuleItem::Stmt(Stmt::Expr(ExprStmt { expr, .. })) = item {
+        if let Expr::Arrow(arrow) = &**expr {
+          for param in arrow.params.iter() {
+            collect_pat_bindings(param, &mut names);
           }
-          _ => {}
         }
       }
     }
   }
+  names
+}
+
+/// 去掉 TypeScript 中只有类型的语法: `as`, `satisfies`, 非空断言, 类型参数和类型注解;
+/// 类型从 AST 中去掉, 同时记录为对原始源码的删除
+pub struct StripTypes {
+  pub edits: Vec<Edit>,
+  base: u32,
 }
 
-pub struct RewriteIdentifier<F: FnMut(&Ident) -> bool>{
-  pub is_need_rewrite: Option<F>
+impl StripTypes {
+  pub fn new(base: u32) -> Self {
+    Self { edits: vec![], base }
+  }
+
+  fn delete(&mut self, lo: BytePos, hi: BytePos) {
+    self.edits.push(((lo.0 - self.base) as usize, (hi.0 - self.base) as usize, String::new()));
+  }
+
+  fn delete_span(&mut self, span: Option<Span>) {
+    if let Some(span) = span {
+      self.delete(span.lo, span.hi);
+    }
+  }
 }
 
-impl<F: FnMut(&Ident) -> bool> VisitMut for RewriteIdentifier<F> {
-  fn visit_mut_ident(&mut self, n: &mut Ident) { 
-    if let Some(f) = &mut self.is_need_rewrite {
-      if f(n) {
-        n.sym = format!("_ctx.{}", n.sym.to_string()).into();
+impl VisitMut for StripTypes {
+  fn visit_mut_expr(&mut self, n: &mut Expr) {
+    n.visit_mut_children_with(self);
+    // 嵌套的类型表达式在访问子节点时已经去掉
+    while is_ts_expr(n) {
+      let span = n.span();
+      let inner = match n {
+        Expr::TsAs(e) => e.expr.take(),
+        Expr::TsSatisfies(e) => e.expr.take(),
+        Expr::TsNonNull(e) => e.expr.take(),
+        Expr::TsTypeAssertion(e) => e.expr.take(),
+        Expr::TsConstAssertion(e) => e.expr.take(),
+        Expr::TsInstantiation(e) => e.expr.take(),
+        _ => return,
+      };
+      // `<Foo>bar` 的类型在表达式之前, 其余在表达式之后
+      if inner.span().lo > span.lo {
+        self.delete(span.lo, inner.span().lo);
+      } else {
+        self.delete(inner.span().hi, span.hi);
       }
-      return;
+      *n = *inner;
     }
-    n.sym = format!("_ctx.{}", n.sym.to_string()).into();
+  }
+
+  fn visit_mut_binding_ident(&mut self, n: &mut BindingIdent) {
+    self.delete_span(n.type_ann.take().map(|t| t.span));
+  }
+
+  fn visit_mut_pat(&mut self, n: &mut Pat) {
+    n.visit_mut_children_with(self);
+    let type_ann = match n {
+      Pat::Array(arr) => arr.type_ann.take(),
+      Pat::Object(obj) => obj.type_ann.take(),
+      Pat::Rest(rest) => rest.type_ann.take(),
+      _ => None,
+    };
+    self.delete_span(type_ann.map(|t| t.span));
+  }
+
+  fn visit_mut_arrow_expr(&mut self, n: &mut ArrowExpr) {
+    n.visit_mut_children_with(self);
+    self.delete_span(n.type_params.take().map(|t| t.span));
+    self.delete_span(n.return_type.take().map(|t| t.span));
+  }
+
+  fn visit_mut_function(&mut self, n: &mut Function) {
+    n.visit_mut_children_with(self);
+    self.delete_span(n.type_params.take().map(|t| t.span));
+    self.delete_span(n.return_type.take().map(|t| t.span));
+  }
+
+  fn visit_mut_call_expr(&mut self, n: &mut CallExpr) {
+    n.visit_mut_children_with(self);
+    self.delete_span(n.type_args.take().map(|t| t.span));
+  }
+
+  fn visit_mut_new_expr(&mut self, n: &mut NewExpr) {
+    n.visit_mut_children_with(self);
+    self.delete_span(n.type_args.take().map(|t| t.span));
+  }
+
+  fn visit_mut_tagged_tpl(&mut self, n: &mut TaggedTpl) {
+    n.visit_mut_children_with(self);
+    self.delete_span(n.type_params.take().map(|t| t.span));
   }
 }
 
-pub struct CollectIdentifiersName {
-  pub names: Vec<String>
+fn is_ts_expr(n: &Expr) -> bool {
+  matches!(
+    n,
+    Expr::TsAs(_) | Expr::TsSatisfies(_) | Expr::TsNonNull(_) | Expr::TsTypeAssertion(_) |
+    Expr::TsConstAssertion(_) | Expr::TsInstantiation(_)
+  )
 }
 
-impl CollectIdentifiersName {
-  pub fn new() -> Self {
-    Self {
-      names: vec![]
-    }
-  }
+/// 解析 `import` 等模块语句, 解析失败时返回空, 仅供测试使用
+#[cfg(test)]
+pub fn parse_module_items(source: &str) -> Vec<ModuleItem> {
+  parse_module(source, false).map(|m| m.body).unwrap_or_default()
 }
 
-impl VisitMut for CollectIdentifiersName {
-  fn visit_mut_ident(&mut self, n: &mut Ident) { 
-    self.names.push(n.sym.to_string());
+#[cfg(test)]
+fn parse_module(source: &str, is_ts: bool) -> Option<Module> {
+  let cm: Lrc<SourceMap> = Default::default();
+  let fm = cm.new_source_file(FileName::Anon, source.to_string());
+  let syntax = if is_ts {
+    Syntax::Typescript(Default::default())
+  } else {
+    Syntax::Es(Default::default())
+  };
+  let lexer = Lexer::new(syntax, Default::default(), StringInput::from(&*fm), None);
+  let mut parser = Parser::new_from(lexer);
+  let module = parser.parse_module().ok()?;
+  if !parser.take_errors().is_empty() {
+    return None;
   }
+  Some(module)
 }
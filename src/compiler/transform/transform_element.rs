@@ -20,11 +20,17 @@ use crate::{
   CodeGenType,
   DirectiveProp,
   CallExpression,
-  CallExpressionArgument, is_build_in_directive,
+  CallExpressionArgument, is_build_in_directive, VnodeCallChildren, TO_HANDLERS, MERGE_PROPS,
+  PatchFlags, format_patch_flag, get_constant_type, to_valid_asset_id, is_core_component,
+  get_expression_constant_type, to_js_string_literal, DynamicProps,
+  NORMALIZE_PROPS, GUARD_REACTIVE_PROPS, is_on, NORMALIZE_CLASS, NORMALIZE_STYLE,
+  ArrayExpression, ElementValue, parse_string_style, stringify_style, BindingTypes, build_slots,
+  CompilerError, ErrorCodes, create_text_calls,
 };
 
 pub struct TransformElement;
 
+#[derive(Default)]
 pub struct PatchFlagStatus {
   pub patch_flag: i32,
   pub has_ref: bool,
@@ -48,29 +54,79 @@ impl Transform for TransformElement {
   fn pre_transform(&self, _node: &mut Node, _ctx: &mut TransformContext) {
   }
 
-  fn post_transform(&self, node: &mut Node, _ctx: &mut TransformContext) {
+  fn post_transform(&self, node: &mut Node, ctx: &mut TransformContext) {
+    let props = match &*node {
+      Node::ElementNode(n) => match n.tag_type {
+        ElementTagType::SlotOutletNode | ElementTagType::TemplateNode => return,
+        _ => n.props.borrow().clone(),
+      },
+      _ => return,
+    };
+    let BuildPropResult { props, directive, patch_flag, dynamic_prop_names, .. } = build_props(node, props, ctx);
     match node {
       Node::ElementNode(n) => {
-        println!("pre_transform: {:?}", n);
-        match n.tag_type {
-          ElementTagType::SlotOutletNode | ElementTagType::TemplateNode => {
-            return;
-          },
-          _ => {}
+        let mut patch_flag = PatchFlags::from_bits_truncate(patch_flag);
+        let is_component = n.tag_type == ElementTagType::ComponentNode;
+        // 运行时指令可能需要访问子节点的 vnode, 单个文本子节点也不能直接作为 children 字符串
+        let has_single_text = matches!(&n.children.borrow()[..], [child] if matches!(
+          &*child.borrow(),
+          Node::Text(_) | Node::Interpolation(_) | Node::CompoundExpression(_)
+        ));
+        if !directive.is_empty() && !is_component && has_single_text {
+          create_text_calls(&n.children, ctx);
         }
+        let directives = (!directive.is_empty()).then(|| ArrayExpression {
+          elements: directive
+            .iter()
+            .map(|dir| ElementValue::ExpressionNode(JsChildNode::ArrayExpression(build_directive_args(dir, ctx))))
+            .collect(),
+        });
+        // Teleport 和 KeepAlive 的子节点直接作为数组传给运行时, 其他组件的子节点编译为插槽
+        let should_build_as_slots = is_component &&
+          !matches!(n.tag_name.as_str(), "Teleport" | "teleport" | "KeepAlive" | "keep-alive");
+        let vnode_children = if n.children.borrow().is_empty() {
+          None
+        } else if should_build_as_slots {
+          let (slots, has_dynamic_slots) = build_slots(n, ctx);
+          if has_dynamic_slots {
+            patch_flag |= PatchFlags::DYNAMIC_SLOTS;
+          }
+          Some(slots)
+        } else {
+          // 只有一个动态文本子节点时, 只需要更新文本
+          if let [child] = &n.children.borrow()[..] {
+            let child = child.borrow();
+            let is_dynamic_text = matches!(&*child, Node::Interpolation(_) | Node::CompoundExpression(_));
+            if is_dynamic_text && get_constant_type(&child) == ConstantTypes::NotConstant {
+              patch_flag |= PatchFlags::TEXT;
+            }
+          }
+          Some(VnodeCallChildren::TemplateChildNodes(n.children.clone()))
+        };
 
-        let ElementNodeBase { tag_name, tag_type, props, children,.. } = n;
-
-        let props = build_props(props.clone());
-        let props = props.props;
+        let ElementNodeBase { tag_name, tag_type, .. } = n;
+        // svg 和 foreignObject 总是作为 block, 以便收集其中的动态节点
+        let is_block = tag_name == "svg" || tag_name == "foreignObject";
+        let dynamic_props = patch_flag.contains(PatchFlags::PROPS).then(|| {
+          let names = dynamic_prop_names
+            .iter()
+            .map(|name| to_js_string_literal(name))
+            .collect::<Vec<_>>();
+          DynamicProps::RawText(format!("[{}]", names.join(", ")))
+        });
 
         let code_gen = ElementNodeCodeGen {
           gen_type: get_code_gen_type(tag_type),
           vnode_call: Some(VnodeCall {
-            tag: tag_name.clone(),
+            tag: resolve_component_type(tag_name, is_component, ctx),
             props,
-            children: children.clone(),
-            is_component: false,
+            patch_flag: (!patch_flag.is_empty()).then(|| format_patch_flag(patch_flag)),
+            dynamic_props,
+            children: vnode_children,
+            directives,
+            is_component,
+            is_block,
+            loc: n.loc,
             ..Default::default()
           }),
           ..Default::default()
@@ -82,6 +138,158 @@ impl Transform for TransformElement {
   }
 }
 
+/// 规范化 class 和 style: 动态的值用 `normalizeClass`/`normalizeStyle` 包裹,
+/// key 是动态的时候无法在编译时确定, 整个对象用 `normalizeProps` 包裹
+fn normalize_props(props: PropExpression, has_style_binding: bool) -> PropExpression {
+  match props {
+    PropExpression::ObjectExpression(mut obj) => {
+      // 动态的事件名 `@[event]` 不会是 class 或 style
+      let has_dynamic_key = obj.properties.iter().any(|p| {
+        matches!(&p.key, ExpressionNode::SimpleExpressionNode(key) if !key.is_static)
+      });
+      if has_dynamic_key {
+        return PropExpression::CallExpression(CallExpression {
+          callee: NORMALIZE_PROPS.to_string(),
+          arguments: vec![CallExpressionArgument::PropExpression(PropExpression::ObjectExpression(obj))],
+        });
+      }
+      for property in obj.properties.iter_mut() {
+        let Some(name) = get_static_exp(&property.key).map(|key| key.content.clone()) else {
+          continue;
+        };
+        let is_static_value = matches!(
+          &*property.value,
+          JsChildNode::ExpressionNode(ExpressionNode::SimpleExpressionNode(exp)) if exp.is_static
+        );
+        let is_array_value = match &*property.value {
+          JsChildNode::ArrayExpression(_) => true,
+          JsChildNode::ExpressionNode(ExpressionNode::SimpleExpressionNode(exp)) => {
+            exp.content.trim_start().starts_with('[')
+          },
+          _ => false,
+        };
+        let callee = if name == "class" && !is_static_value {
+          NORMALIZE_CLASS
+        } else if name == "style" && (has_style_binding || is_array_value) {
+          NORMALIZE_STYLE
+        } else {
+          continue;
+        };
+        let value = property.value.clone();
+        *property.value = JsChildNode::CallExpression(CallExpression {
+          callee: callee.to_string(),
+          arguments: vec![CallExpressionArgument::JsChildNode(value)],
+        });
+      }
+      PropExpression::ObjectExpression(obj)
+    },
+    PropExpression::CallExpression(_) => props,
+    // 单独的 `v-bind="obj"`, 可能是响应式对象
+    _ => PropExpression::CallExpression(CallExpression {
+      callee: NORMALIZE_PROPS.to_string(),
+      arguments: vec![CallExpressionArgument::PropExpression(
+        PropExpression::CallExpression(CallExpression {
+          callee: GUARD_REACTIVE_PROPS.to_string(),
+          arguments: vec![CallExpressionArgument::PropExpression(props)],
+        })
+      )],
+    }),
+  }
+}
+
+/// 合并同名的属性: class/style 和事件监听合并为数组, 运行时再规范化或依次调用;
+/// 动态的 key 无法在编译时比较, 总是保留
+fn dedupe_properties(properties: Vec<Property>) -> Vec<Property> {
+  let mut deduped: Vec<Property> = vec![];
+  for property in properties {
+    let Some(name) = get_static_exp(&property.key).map(|key| key.content.clone()) else {
+      deduped.push(property);
+      continue;
+    };
+    let existing = deduped.iter_mut().find(|p| {
+      get_static_exp(&p.key).is_some_and(|key| key.content == name)
+    });
+    match existing {
+      Some(existing) if name == "class" || name == "style" || is_on(&name) => {
+        merge_as_array(existing, property);
+      },
+      // 其他重复的属性在解析时就应该报错, 保留第一个
+      Some(_) => {},
+      None => deduped.push(property),
+    }
+  }
+  deduped
+}
+
+fn merge_as_array(existing: &mut Property, incoming: Property) {
+  let incoming = ElementValue::ExpressionNode(*incoming.value);
+  match &mut *existing.value {
+    JsChildNode::ArrayExpression(arr) => arr.elements.push(incoming),
+    value => {
+      let first = ElementValue::ExpressionNode(value.clone());
+      *value = JsChildNode::ArrayExpression(ArrayExpression { elements: vec![first, incoming] });
+    },
+  }
+}
+
+/// 组件需要在渲染函数开头通过 `resolveComponent` 解析, 内置组件直接从运行时引入
+pub fn resolve_component_type(tag: &str, is_component: bool, ctx: &mut TransformContext) -> String {
+  if !is_component || is_core_component(tag) {
+    return tag.to_string();
+  }
+  if !ctx.components.iter().any(|c| c == tag) {
+    ctx.components.push(tag.to_string());
+  }
+  to_valid_asset_id(tag, "component")
+}
+
+/// 自定义指令需要在渲染函数开头通过 `resolveDirective` 解析
+fn resolve_directive_type(name: &str, ctx: &mut TransformContext) -> String {
+  if !ctx.directives.iter().any(|d| d == name) {
+    ctx.directives.push(name.to_string());
+  }
+  to_valid_asset_id(name, "directive")
+}
+
+/// `withDirectives` 的参数 `[dir, exp, arg, modifiers]`, 中间缺少的参数用 `void 0` 占位
+pub fn build_directive_args(dir: &DirectiveProp, ctx: &mut TransformContext) -> ArrayExpression {
+  let void = || ElementValue::RawText("void 0".to_string());
+  let mut elements = vec![ElementValue::RawText(resolve_directive_type(&dir.name, ctx))];
+  if let Some(exp) = &dir.exp {
+    elements.push(ElementValue::ExpressionNode(JsChildNode::ExpressionNode(exp.clone())));
+  }
+  if let Some(arg) = &dir.arg {
+    if dir.exp.is_none() {
+      elements.push(void());
+    }
+    elements.push(ElementValue::ExpressionNode(JsChildNode::ExpressionNode(arg.clone())));
+  }
+  if !dir.modifiers.is_empty() {
+    if dir.arg.is_none() {
+      if dir.exp.is_none() {
+        elements.push(void());
+      }
+      elements.push(void());
+    }
+    let properties = dir.modifiers
+      .iter()
+      .map(|modifier| Property {
+        key: static_key(modifier),
+        value: Box::new(JsChildNode::ExpressionNode(ExpressionNode::SimpleExpressionNode(
+          SimpleExpressionNode {
+            content: "true".to_string(),
+            is_static: false,
+            constant_type: ConstantTypes::CanStringify,
+            loc: dir.loc,
+          }
+        ))),
+      })
+      .collect();
+    elements.push(ElementValue::ExpressionNode(JsChildNode::ObjectExpression(ObjectExpression { properties })));
+  }
+  ArrayExpression { elements }
+}
+
 pub fn get_code_gen_type(tag_type: &ElementTagType) -> CodeGenType {
   match tag_type {
     ElementTagType::PlainElementNode => CodeGenType::PlainElementNodeCodeGen,
@@ -91,27 +299,63 @@ pub fn get_code_gen_type(tag_type: &ElementTagType) -> CodeGenType {
   }
 }
 
-pub fn build_props(props: Vec<Prop>) -> BuildPropResult {
+pub fn build_props(node: &mut Node, props: Vec<Prop>, ctx: &mut TransformContext) -> BuildPropResult {
+  let is_component = matches!(
+    &*node,
+    Node::ElementNode(el) if el.tag_type == ElementTagType::ComponentNode
+  );
   let mut properties: Vec<Property> = vec![];
   let mut merge_props: Vec<PropExpression> = vec![];
   let mut runtime_directives: Vec<DirectiveProp> = vec![];
+  let mut flags = PatchFlagStatus::default();
 
   for prop in props.into_iter() {
     match prop {
       Prop::Normal(p) => {
         let NormalProp { name, value, loc } = p;
-        if name == "ref" {
+        if name == "is" {
           continue;
         }
-        if name == "is" {
+        if name == "ref" {
+          flags.has_ref = true;
+          if ctx.scopes.v_for > 0 {
+            properties.push(create_ref_for_property());
+          }
+          let value = value.unwrap_or(TextPropValue { content: "".to_string(), loc });
+          // 内联模式下没有 setupState, 不能通过字符串设置 ref, 需要直接传入 setup 中的 ref
+          let is_setup_ref = ctx.inline && matches!(
+            ctx.binding_metadata.get(&value.content),
+            Some(BindingTypes::SetupLet | BindingTypes::SetupRef | BindingTypes::SetupMaybeRef)
+          );
+          if is_setup_ref {
+            properties.push(create_property("ref_key".to_string(), value.clone(), loc));
+            properties.push(Property {
+              key: static_key("ref"),
+              value: Box::new(JsChildNode::ExpressionNode(ExpressionNode::SimpleExpressionNode(
+                SimpleExpressionNode {
+                  content: value.content,
+                  is_static: false,
+                  constant_type: ConstantTypes::NotConstant,
+                  loc: value.loc,
+                }
+              ))),
+            });
+          } else {
+            properties.push(create_property(name, value, loc));
+          }
           continue;
         }
-        let property = create_property(name, value.unwrap(), loc);
-        println!("property: {:?}", &property);
+        // `<div style>`, `<input disabled>` 没有值, 当作空字符串
+        let value = value.unwrap_or(TextPropValue { content: "".to_string(), loc });
+        let property = if name == "style" {
+          create_static_style_property(value, loc)
+        } else {
+          create_property(name, value, loc)
+        };
         properties.push(property);
       },
-      Prop::Directive(d) => {
-        let DirectiveProp { name, arg, exp, loc, .. } = d.clone();
+      Prop::Directive(mut d) => {
+        let DirectiveProp { name, arg, exp, .. } = d.clone();
         let is_bind = name == "bind";
         let is_v_on = name == "on";
 
@@ -125,6 +369,7 @@ pub fn build_props(props: Vec<Prop>) -> BuildPropResult {
 
         if arg.is_none() && (is_bind || is_v_on) {
           if exp.is_some() {
+            flags.has_dynamic_keys = true;
             if is_bind {
               push_merge_props(&mut merge_props, &mut properties, None);
               let exp = PropExpression::SimpleExpression(exp.unwrap());
@@ -133,7 +378,7 @@ pub fn build_props(props: Vec<Prop>) -> BuildPropResult {
               // v-on="obj" -> toHandlers(obj)
               let exp = exp_to_prop_exp_args(exp.unwrap());
               let prop = PropExpression::CallExpression(CallExpression { 
-                callee: "TO_HANDLERS".to_string(), 
+                callee: TO_HANDLERS.to_string(),
                 arguments: vec![exp]
               });
               push_merge_props(
@@ -142,7 +387,30 @@ pub fn build_props(props: Vec<Prop>) -> BuildPropResult {
                 Some(prop));
             } 
           } else {
-            // todo 提交一个错误, 不能没有表达式
+            let code = if is_bind { ErrorCodes::XVBindNoExpression } else { ErrorCodes::XVOnNoExpression };
+            ctx.on_error(CompilerError::new(code, Some(d.loc), ""));
+          }
+          continue;
+        }
+
+        // v-for 中的 ref 需要收集为数组
+        let is_ref = matches!(&arg, Some(ExpressionNode::SimpleExpressionNode(arg)) if arg.is_static && arg.content == "ref");
+        if is_bind && is_ref && ctx.scopes.v_for > 0 {
+          properties.push(create_ref_for_property());
+        }
+
+        let directive_transform = ctx.directive_transforms.get(&name).cloned();
+        if let Some(directive_transform) = directive_transform {
+          let res = directive_transform.transform(&mut d, node, ctx);
+          for property in res.properties {
+            if !ctx.ssr {
+              analyze_patch_flag(&mut flags, &property, is_component);
+            }
+            properties.push(property);
+          }
+          // 编译时转换之外还需要运行时指令, 例如 `<input v-model>` 的 vModelText
+          if res.need_runtime {
+            runtime_directives.push(d);
           }
           continue;
         }
@@ -157,9 +425,10 @@ pub fn build_props(props: Vec<Prop>) -> BuildPropResult {
   let mut prop_exp: Option<PropExpression> = None;
 
   if merge_props.len() > 0 {
+    push_merge_props(&mut merge_props, &mut properties, None);
     if merge_props.len() > 1 {
       prop_exp = Some(PropExpression::CallExpression(CallExpression { 
-        callee: "MERGE_PROPS".to_string(), 
+        callee: MERGE_PROPS.to_string(), 
         arguments: prop_exps_to_call_exp_args(merge_props)
       }));
     } else {
@@ -167,56 +436,84 @@ pub fn build_props(props: Vec<Prop>) -> BuildPropResult {
     }
   } else if properties.len() > 0 {
     prop_exp = Some(PropExpression::ObjectExpression(ObjectExpression { 
-      properties 
+      properties: dedupe_properties(properties)
     }));
   }
+  let prop_exp = prop_exp.map(|props| normalize_props(props, flags.has_style_binding));
+
+  let mut patch_flag = PatchFlags::empty();
+  if flags.has_dynamic_keys {
+    patch_flag |= PatchFlags::FULL_PROPS;
+  } else {
+    if flags.has_class_binding && !is_component {
+      patch_flag |= PatchFlags::CLASS;
+    }
+    if flags.has_style_binding && !is_component {
+      patch_flag |= PatchFlags::STYLE;
+    }
+    if !flags.dynamic_prop_names.is_empty() {
+      patch_flag |= PatchFlags::PROPS;
+    }
+    if flags.has_hydration_event_binding {
+      patch_flag |= PatchFlags::HYDRATE_EVENTS;
+    }
+  }
+  let needs_patch = flags.has_ref || flags.has_vnode_hook || !runtime_directives.is_empty();
+  if (patch_flag.is_empty() || patch_flag == PatchFlags::HYDRATE_EVENTS) && needs_patch {
+    patch_flag |= PatchFlags::NEED_PATCH;
+  }
 
   BuildPropResult { 
     props: prop_exp, 
     directive: runtime_directives, 
-    patch_flag: 0, 
-    dynamic_prop_names: vec![], 
+    patch_flag: patch_flag.bits(), 
+    dynamic_prop_names: flags.dynamic_prop_names, 
     should_block: false
   }
 }
 
-pub fn analyze_patch_flag(property: &Property, is_component: bool) -> PatchFlagStatus {
-  let mut flags = PatchFlagStatus {
-    patch_flag: 0,
-    has_ref: false,
-    has_class_binding: false,
-    has_style_binding: false,
-    has_hydration_event_binding: false,
-    has_dynamic_keys: false,
-    has_vnode_hook: false,
-    dynamic_prop_names: vec![],
-  };
+pub fn analyze_patch_flag(flags: &mut PatchFlagStatus, property: &Property, is_component: bool) {
+  let Property { key, value } = property;
 
-  let Property { key, .. } = property;
-
-  if let Some(exp) = get_static_exp(key) {
-    let name = &exp.content;
+  let Some(exp) = get_static_exp(key) else {
+    flags.has_dynamic_keys = true;
+    return;
+  };
+  let name = &exp.content;
+  let is_vnode_hook = name.starts_with("onVnode");
+  // 水合时只有 onClick 会默认绑定, 其他事件需要标记后才会在水合时绑定
+  if is_on(name) && !is_component && name.to_lowercase() != "onclick"
+    && name != "onUpdate:modelValue" && !is_vnode_hook {
+    flags.has_hydration_event_binding = true;
+  }
+  if is_vnode_hook {
+    flags.has_vnode_hook = true;
+  }
 
-    if name == "ref" {
-      flags.has_ref = true;
-    } else if name == "class" {
-      flags.has_class_binding = true;
-    } else if name == "style" {
-      flags.has_style_binding = true;
-    } else if name != "key" && !flags.dynamic_prop_names.contains(name) {
-      flags.dynamic_prop_names.push(name.clone());
-    }
+  // 缓存的或者常量的值不会变化
+  let is_static_value = match &**value {
+    JsChildNode::CacheExpression(_) => true,
+    JsChildNode::ExpressionNode(exp) => get_expression_constant_type(exp) > ConstantTypes::NotConstant,
+    _ => false,
+  };
+  if is_static_value {
+    return;
+  }
 
-    if is_component && 
-      (name == "class" && name == "style") && 
-      !flags.dynamic_prop_names.contains(name) {
-        flags.dynamic_prop_names.push(name.clone());
-    }
-  } else {
-    flags.has_dynamic_keys = true;
+  if name == "ref" {
+    flags.has_ref = true;
+  } else if name == "class" {
+    flags.has_class_binding = true;
+  } else if name == "style" {
+    flags.has_style_binding = true;
+  } else if name != "key" && !flags.dynamic_prop_names.contains(name) {
+    flags.dynamic_prop_names.push(name.clone());
   }
 
-  flags
+  // 组件的 class 和 style 作为普通 prop 传递
+  if is_component && (name == "class" || name == "style") && !flags.dynamic_prop_names.contains(name) {
+    flags.dynamic_prop_names.push(name.clone());
+  }
 }
 
 pub fn get_static_exp(exp: &ExpressionNode) -> Option<&SimpleExpressionNode> {
@@ -244,7 +541,7 @@ pub fn push_merge_props(
   properties: &mut Vec<Property>,
   arg: Option<PropExpression>) {
   if properties.len() > 0 {
-    let object_expression = ObjectExpression { properties: properties.clone() };
+    let object_expression = ObjectExpression { properties: dedupe_properties(properties.clone()) };
     let prop_expression = PropExpression::ObjectExpression(object_expression);
     merge_props.push(prop_expression);
     properties.clear();
@@ -258,6 +555,46 @@ pub fn exp_to_prop_exp_args(exp: ExpressionNode) -> CallExpressionArgument {
   CallExpressionArgument::JsChildNode(Box::new(JsChildNode::ExpressionNode(exp)))
 }
 
+fn static_key(name: &str) -> ExpressionNode {
+  ExpressionNode::SimpleExpressionNode(SimpleExpressionNode {
+    content: name.to_string(),
+    is_static: true,
+    constant_type: ConstantTypes::CanStringify,
+    loc: Default::default(),
+  })
+}
+
+/// `ref_for: true`
+fn create_ref_for_property() -> Property {
+  Property {
+    key: static_key("ref_for"),
+    value: Box::new(JsChildNode::ExpressionNode(ExpressionNode::SimpleExpressionNode(
+      SimpleExpressionNode {
+        content: "true".to_string(),
+        is_static: false,
+        constant_type: ConstantTypes::CanStringify,
+        loc: Default::default(),
+      }
+    ))),
+  }
+}
+
+/// 静态的 `style="color: red"` 在编译时解析为对象 `{"color":"red"}`, 以便和动态的 style 合并
+fn create_static_style_property(value: TextPropValue, loc: SourceLocation) -> Property {
+  let style = stringify_style(&parse_string_style(&value.content));
+  Property {
+    key: static_key("style"),
+    value: Box::new(JsChildNode::ExpressionNode(ExpressionNode::SimpleExpressionNode(
+      SimpleExpressionNode {
+        content: style,
+        is_static: false,
+        constant_type: ConstantTypes::CanStringify,
+        loc,
+      }
+    ))),
+  }
+}
+
 pub fn create_property(
   name: String, 
   value: TextPropValue, 
@@ -266,7 +603,7 @@ pub fn create_property(
     ExpressionNode::SimpleExpressionNode(SimpleExpressionNode { 
       content: value.content, 
       is_static: true, 
-      constant_type: ConstantTypes::NotConstant, 
+      constant_type: ConstantTypes::CanStringify, 
       loc,
     });
   let js_child = Box::new(JsChildNode::ExpressionNode(value));
@@ -275,7 +612,7 @@ pub fn create_property(
     ExpressionNode::SimpleExpressionNode(SimpleExpressionNode { 
       content: name, 
       is_static: true, 
-      constant_type: ConstantTypes::NotConstant, 
+      constant_type: ConstantTypes::CanStringify, 
       loc: Default::default(),
     });
 
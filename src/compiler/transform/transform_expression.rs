@@ -1,8 +1,20 @@
+use std::{cell::RefCell, collections::HashMap, rc::Rc};
+
+use regex::Regex;
 use swc_ecma_ast::Ident;
 
 use crate::{
   Transform, Node, TransformContext, SimpleExpressionNode, is_global_white_list, 
-  ExpressionNode, CompoundExpressionNodeChild, Prop, is_simple_identifier, ProcessIdentifiers};
+  ExpressionNode, CompoundExpressionNodeChild, Prop, is_simple_identifier, ProcessIdentifiers,
+  ConstantTypes, is_literal_white_list, BindingMetadata, BindingTypes, to_js_string_literal,
+  UNREF, IS_REF, CompilerError, ErrorCodes, SourceLocation, advance_position,
+  resolve_same_name_shorthand};
+
+lazy_static! {
+  /// 函数调用和成员访问, 这类表达式每次求值的结果可能不同
+  static ref BAIL_CONSTANT_REG: Regex = Regex::new(r"\w\s*\(|\.[^\d]").unwrap();
+}
+
 pub struct Expression {}
 
 impl Transform for Expression {
@@ -10,13 +22,13 @@ impl Transform for Expression {
     if let Node::Interpolation(interpolation) = node {
       match &mut interpolation.content {
         ExpressionNode::SimpleExpressionNode(exp) => {
-          process_expression(exp, ctx, false);
+          process_expression(exp, ctx, false, false);
         },
         ExpressionNode::CompoundExpressionNode(exp) => {
           for child in &mut exp.children {
             match child {
               CompoundExpressionNodeChild::SimpleExpressionNode(exp) => {
-                process_expression(exp, ctx, false);
+                process_expression(exp, ctx, false, false);
               },
               _ => {}
             }
@@ -26,11 +38,23 @@ impl Transform for Expression {
     }
 
     if let Node::ElementNode(el) = node {
-      for prop in &mut el.props {
+      for prop in el.props.borrow_mut().iter_mut() {
         if let Prop::Directive(dir) = prop {
+          resolve_same_name_shorthand(dir);
+          // 动态参数 `:[key]` / `@[event]` 也是表达式
+          if let Some(ExpressionNode::SimpleExpressionNode(arg)) = &mut dir.arg {
+            if !arg.is_static {
+              process_expression(arg, ctx, false, false);
+            }
+          }
           if let Some(ExpressionNode::SimpleExpressionNode(exp)) = &mut dir.exp {
-            if dir.name != "on" {
-              process_expression(exp, ctx, dir.name == "slot");
+            // 带参数的 v-on, v-for 和 v-model 的表达式由各自的 transform 处理,
+            // `v-on="obj"` 没有对应的 transform, 在这里处理;
+            // SSR 不经过 TransformVModel, v-model 的表达式在这里处理
+            let is_model = dir.name == "model" && !ctx.ssr;
+            let is_v_on_with_arg = dir.name == "on" && dir.arg.is_some();
+            if !is_v_on_with_arg && dir.name != "for" && !is_model {
+              process_expression(exp, ctx, dir.name == "slot", false);
             }
           }
         }
@@ -43,28 +67,107 @@ impl Transform for Expression {
   }
 }
 
+/// `as_statements` 为 true 时允许多条语句, 只用于 v-on 的内联处理函数
 pub fn process_expression(
   exp: &mut SimpleExpressionNode,
-  _ctx: &mut TransformContext,
-  _as_param: bool  
+  ctx: &mut TransformContext,
+  as_param: bool,
+  as_statements: bool,
 ) {
-  if exp.content.is_empty() {
+  // 作为参数的表达式(v-slot 的参数)声明的是新的变量, 不需要改写;
+  // 不添加前缀时标识符在运行时通过 `with (_ctx)` 查找
+  if exp.content.is_empty() || as_param || !ctx.prefix_identifiers {
     return;
   }
 
+  let prefixer = Rc::new(IdentifierPrefixer::new(ctx));
   let raw_str = &mut exp.content;
-  if is_simple_identifier(&raw_str) {
+  if is_simple_identifier(raw_str) {
+    let is_scope_var_reference = ctx.identifiers.contains_key(raw_str.as_str());
+    let is_literal = is_literal_white_list(raw_str);
+    let is_allowed_global = is_global_white_list(raw_str);
+    let binding_type = prefixer.binding_type(raw_str);
+    if !is_scope_var_reference && !is_literal && (!is_allowed_global || binding_type.is_some()) {
+      exp.constant_type = match binding_type {
+        Some(binding_type) if binding_type.is_const() => ConstantTypes::CanSkipPatch,
+        _ => ConstantTypes::NotConstant,
+      };
+      *raw_str = prefixer.rewrite(raw_str, false);
+      prefixer.flush_helpers(ctx);
+    } else if is_scope_var_reference {
+      exp.constant_type = ConstantTypes::NotConstant;
+    } else if is_literal {
+      exp.constant_type = ConstantTypes::CanStringify;
+    } else {
+      exp.constant_type = ConstantTypes::CanHoist;
+    }
     return;
   }
 
-  let mut rewrite_idents = ProcessIdentifiers::new(raw_str);
-  rewrite_idents.parse();
-  rewrite_idents.rewrite_identifiers();
+  let source = raw_str.clone();
+  let mut rewrite_idents = ProcessIdentifiers::new(raw_str, ctx.is_ts);
+  rewrite_idents.as_statements = as_statements;
+  if let Err(e) = rewrite_idents.parse() {
+    // 保留原始表达式, 报告错误后继续转换
+    let start = advance_position(exp.loc.start, &source, e.offset);
+    ctx.on_error(CompilerError::new(
+      ErrorCodes::XInvalidExpression,
+      Some(SourceLocation { start, end: exp.loc.end }),
+      &e.message,
+    ));
+    exp.constant_type = ConstantTypes::NotConstant;
+    return;
+  }
+  // 只包含字面量和全局变量的表达式不需要改写;
+  // 函数调用和成员访问(`Date.now()`, `Math.random()`)每次求值结果可能不同, 不能当作常量
+  let identifiers = rewrite_idents.collect_identifiers();
+  if identifiers.iter().all(|name| is_global_white_list(name)) {
+    let bail_constant = BAIL_CONSTANT_REG.is_match(&source);
+    exp.constant_type = if bail_constant {
+      ConstantTypes::NotConstant
+    } else {
+      ConstantTypes::CanStringify
+    };
+    return;
+  }
+  exp.constant_type = ConstantTypes::NotConstant;
+  rewrite_idents.rewrite_identifiers(ctx.identifiers.keys().cloned().collect(), prefixer.clone());
   let code = rewrite_idents.generate();
-
+  prefixer.flush_helpers(ctx);
   *raw_str = code;
 }
 
+/// 表达式是否引用了 v-for/v-slot 等作用域内的变量
+pub fn has_scope_ref(exp: &ExpressionNode, ctx: &TransformContext) -> bool {
+  if ctx.identifiers.is_empty() {
+    return false;
+  }
+  match exp {
+    ExpressionNode::SimpleExpressionNode(exp) => {
+      if is_simple_identifier(&exp.content) {
+        return ctx.identifiers.contains_key(&exp.content);
+      }
+      let mut source = exp.content.clone();
+      let mut idents = ProcessIdentifiers::new(&mut source, ctx.is_ts);
+      if idents.parse().is_err() {
+        return false;
+      }
+      idents.collect_identifiers()
+        .iter()
+        .any(|name| ctx.identifiers.contains_key(name))
+    },
+    ExpressionNode::CompoundExpressionNode(exp) => {
+      exp.children.iter().any(|child| match child {
+        CompoundExpressionNodeChild::SimpleExpressionNode(n) => {
+          has_scope_ref(&ExpressionNode::SimpleExpressionNode(n.clone()), ctx)
+        },
+        CompoundExpressionNodeChild::InterpolationNode(n) => has_scope_ref(&n.content, ctx),
+        _ => false,
+      })
+    },
+  }
+}
+
 pub fn can_rewrite(id: &Ident) -> bool {
   let name = id.sym.to_string();
   if is_global_white_list(&name) {
@@ -76,6 +179,332 @@ pub fn can_rewrite(id: &Ident) -> bool {
   true
 }
 
-pub fn rewrite_identifier(id: &mut String) {
-  *id = String::from("ctx.")
-}
\ No newline at end of file
+/// 根据变量来源决定模板中标识符的访问方式
+pub struct IdentifierPrefixer {
+  pub binding_metadata: BindingMetadata,
+  pub props_aliases: HashMap<String, String>,
+  pub inline: bool,
+  pub is_ts: bool,
+  /// 改写过程中用到的 helper, 改写结束后写回 TransformContext
+  pub helpers: RefCell<Vec<String>>,
+}
+
+impl IdentifierPrefixer {
+  pub fn new(ctx: &TransformContext) -> Self {
+    Self {
+      binding_metadata: ctx.binding_metadata.clone(),
+      props_aliases: ctx.props_aliases.clone(),
+      inline: ctx.inline,
+      is_ts: ctx.is_ts,
+      helpers: RefCell::new(vec![]),
+    }
+  }
+
+  pub fn binding_type(&self, name: &str) -> Option<BindingTypes> {
+    self.binding_metadata.get(name).copied()
+  }
+
+  fn helper(&self, name: &str) -> String {
+    let mut helpers = self.helpers.borrow_mut();
+    if !helpers.iter().any(|h| h == name) {
+      helpers.push(name.to_string());
+    }
+    format!("_{}", name)
+  }
+
+  pub fn flush_helpers(&self, ctx: &mut TransformContext) {
+    for name in self.helpers.borrow_mut().drain(..) {
+      ctx.helper(&name);
+    }
+  }
+
+  /// 改写一个标识符, `is_lval` 表示它是赋值或自增自减的目标
+  pub fn rewrite(&self, raw: &str, is_lval: bool) -> String {
+    let binding_type = self.binding_type(raw);
+    if self.inline {
+      match binding_type {
+        Some(BindingTypes::SetupConst) |
+        Some(BindingTypes::LiteralConst) |
+        Some(BindingTypes::SetupReactiveConst) => raw.to_string(),
+        Some(BindingTypes::SetupRef) => format!("{}.value", raw),
+        Some(BindingTypes::SetupMaybeRef) if is_lval => format!("{}.value", raw),
+        Some(BindingTypes::SetupLet) if is_lval => raw.to_string(),
+        Some(BindingTypes::SetupMaybeRef) |
+        Some(BindingTypes::SetupLet) => format!("{}({})", self.helper(UNREF), raw),
+        Some(BindingTypes::Props) => gen_props_access_exp(raw),
+        Some(BindingTypes::PropsAliased) => gen_props_access_exp(self.props_alias(raw)),
+        Some(BindingTypes::Data) | Some(BindingTypes::Options) | None => format!("_ctx.{}", raw),
+      }
+    } else {
+      match binding_type {
+        Some(t) if t.is_setup() || t == BindingTypes::LiteralConst => format!("$setup.{}", raw),
+        Some(BindingTypes::PropsAliased) => format!("$props['{}']", self.props_alias(raw)),
+        Some(BindingTypes::Props) => format!("$props.{}", raw),
+        Some(BindingTypes::Data) => format!("$data.{}", raw),
+        Some(BindingTypes::Options) => format!("$options.{}", raw),
+        _ => format!("_ctx.{}", raw),
+      }
+    }
+  }
+
+  /// 内联模式下对 `let` 变量赋值时需要在运行时判断是否为 ref
+  pub fn is_ref_check(&self, raw: &str) -> String {
+    let ts_ignore = if self.is_ts { " //@ts-ignore\n" } else { "" };
+    format!("{}({}){}", self.helper(IS_REF), raw, ts_ignore)
+  }
+
+  pub fn is_inline_let(&self, raw: &str) -> bool {
+    self.inline && self.binding_type(raw) == Some(BindingTypes::SetupLet)
+  }
+
+  fn props_alias<'a>(&'a self, raw: &'a str) -> &'a str {
+    self.props_aliases.get(raw).map(|s| s.as_str()).unwrap_or(raw)
+  }
+}
+
+fn gen_props_access_exp(name: &str) -> String {
+  if is_simple_identifier(name) {
+    format!("__props.{}", name)
+  } else {
+    format!("__props[{}]", to_js_string_literal(name))
+  }
+}
+#[cfg(test)]
+mod tests {
+  use crate::{
+    SimpleExpressionNode, TransformContext, ConstantTypes, BindingTypes, process_expression,
+    ErrorCodes, Position, SourceLocation, ProcessIdentifiers, Parser, TransformRunner, Expression,
+  };
+  use std::{cell::RefCell, rc::Rc};
+
+  fn process(content: &str, identifiers: &[&str]) -> String {
+    let mut ctx = TransformContext::new("hello.vue".to_string());
+    process_with(content, identifiers, &mut ctx)
+  }
+
+  fn process_with(content: &str, identifiers: &[&str], ctx: &mut TransformContext) -> String {
+    ctx.add_identifiers(&identifiers.iter().map(|s| s.to_string()).collect::<Vec<_>>());
+    let mut exp = SimpleExpressionNode {
+      content: content.to_string(),
+      is_static: false,
+      constant_type: ConstantTypes::NotConstant,
+      loc: Default::default(),
+    };
+    process_expression(&mut exp, ctx, false, false);
+    exp.content
+  }
+
+  fn binding_ctx(inline: bool) -> TransformContext<'static> {
+    let mut ctx = TransformContext::new("hello.vue".to_string());
+    ctx.inline = inline;
+    for (name, binding_type) in [
+      ("c", "setup-const"),
+      ("r", "setup-ref"),
+      ("m", "setup-maybe-ref"),
+      ("l", "setup-let"),
+      ("s", "setup-reactive-const"),
+      ("p", "props"),
+      ("a", "props-aliased"),
+      ("d", "data"),
+      ("o", "options"),
+      ("n", "literal-const"),
+    ] {
+      ctx.binding_metadata.insert(name.to_string(), binding_type.parse::<BindingTypes>().unwrap());
+    }
+    ctx.props_aliases.insert("a".to_string(), "foo-bar".to_string());
+    ctx
+  }
+
+  #[test]
+  fn test_prefix_simple_identifier() {
+    assert_eq!(process("foo", &[]), "_ctx.foo");
+    assert_eq!(process("foo", &["foo"]), "foo");
+    assert_eq!(process("Math", &[]), "Math");
+    assert_eq!(process("true", &[]), "true");
+  }
+
+  #[test]
+  fn test_arrow_params_not_prefixed() {
+    assert_eq!(process("(a, { b }) => a + b + c", &[]), "(a, { b }) => a + b + _ctx.c");
+  }
+
+  #[test]
+  fn test_block_scope_not_prefixed() {
+    assert_eq!(
+      process("() => { let a = 1; try { foo(a) } catch (e) { bar(e) } }", &[]),
+      "() => { let a = 1; try { _ctx.foo(a) } catch (e) { _ctx.bar(e) } }"
+    );
+  }
+
+  #[test]
+  fn test_binding_metadata_non_inline() {
+    let mut ctx = binding_ctx(false);
+    assert_eq!(process_with("c", &[], &mut ctx), "$setup.c");
+    assert_eq!(process_with("n", &[], &mut ctx), "$setup.n");
+    assert_eq!(process_with("p", &[], &mut ctx), "$props.p");
+    assert_eq!(process_with("a", &[], &mut ctx), "$props['foo-bar']");
+    assert_eq!(process_with("d", &[], &mut ctx), "$data.d");
+    assert_eq!(process_with("o", &[], &mut ctx), "$options.o");
+    assert_eq!(process_with("r + x", &[], &mut ctx), "$setup.r + _ctx.x");
+  }
+
+  #[test]
+  fn test_binding_metadata_inline() {
+    let mut ctx = binding_ctx(true);
+    assert_eq!(process_with("c", &[], &mut ctx), "c");
+    assert_eq!(process_with("s", &[], &mut ctx), "s");
+    assert_eq!(process_with("r", &[], &mut ctx), "r.value");
+    assert_eq!(process_with("m", &[], &mut ctx), "_unref(m)");
+    assert_eq!(process_with("l", &[], &mut ctx), "_unref(l)");
+    assert_eq!(process_with("p", &[], &mut ctx), "__props.p");
+    assert_eq!(process_with("a", &[], &mut ctx), "__props[\"foo-bar\"]");
+    assert_eq!(process_with("d", &[], &mut ctx), "_ctx.d");
+    assert_eq!(ctx.helpers, vec!["unref"]);
+  }
+
+  #[test]
+  fn test_binding_metadata_inline_assignment() {
+    let mut ctx = binding_ctx(true);
+    assert_eq!(process_with("m = 1", &[], &mut ctx), "m.value = 1");
+    assert_eq!(process_with("r++", &[], &mut ctx), "r.value++");
+    assert_eq!(process_with("l = m", &[], &mut ctx), "_isRef(l) ? l.value = _unref(m) : l = _unref(m)");
+    assert_eq!(process_with("l++", &[], &mut ctx), "_isRef(l) ? l.value++ : l++");
+    assert_eq!(ctx.helpers, vec!["isRef", "unref"]);
+  }
+
+  #[test]
+  fn test_const_binding_can_skip_patch() {
+    let mut ctx = binding_ctx(true);
+    let mut exp = SimpleExpressionNode {
+      content: "c".to_string(),
+      is_static: false,
+      constant_type: ConstantTypes::NotConstant,
+      loc: Default::default(),
+    };
+    process_expression(&mut exp, &mut ctx, false, false);
+    assert_eq!(exp.constant_type, ConstantTypes::CanSkipPatch);
+  }
+
+  #[test]
+  fn test_ts_expressions() {
+    let mut ctx = TransformContext::new("hello.vue".to_string());
+    ctx.is_ts = true;
+    assert_eq!(process_with("(foo as Bar).baz", &[], &mut ctx), "(_ctx.foo).baz");
+    assert_eq!(process_with("foo!.bar + x!", &[], &mut ctx), "_ctx.foo.bar + _ctx.x");
+    assert_eq!(process_with("list satisfies Item[]", &[], &mut ctx), "_ctx.list");
+    assert_eq!(process_with("get<Item>(id)", &[], &mut ctx), "_ctx.get(_ctx.id)");
+    assert_eq!(process_with("(e: Event, n: number): void => handle(e, n)", &[], &mut ctx), "(e, n) => _ctx.handle(e, n)");
+    assert_eq!(process_with("<Foo>bar", &[], &mut ctx), "_ctx.bar");
+    assert_eq!(process_with("x! as Foo", &[], &mut ctx), "_ctx.x");
+  }
+
+  #[test]
+  fn test_ts_slot_params() {
+    assert_eq!(
+      crate::collect_param_bindings("{ item }: { item: Item }, index: number", true),
+      vec!["item", "index"]
+    );
+  }
+
+  #[test]
+  fn test_invalid_expression() {
+    let mut ctx = TransformContext::new("hello.vue".to_string());
+    let mut exp = SimpleExpressionNode {
+      content: "a +\n b c".to_string(),
+      is_static: false,
+      constant_type: ConstantTypes::CanStringify,
+      loc: SourceLocation {
+        start: Position { offset: 10, line: 1, column: 11 },
+        end: Position { offset: 19, line: 2, column: 6 },
+      },
+    };
+    process_expression(&mut exp, &mut ctx, false, false);
+    assert_eq!(exp.content, "a +\n b c");
+    assert_eq!(exp.constant_type, ConstantTypes::NotConstant);
+    assert_eq!(ctx.errors.len(), 1);
+    let error = &ctx.errors[0];
+    assert_eq!(error.code, ErrorCodes::XInvalidExpression);
+    assert!(error.message.starts_with("Error parsing JavaScript expression: "));
+    assert!(error.message.len() > "Error parsing JavaScript expression: ".len());
+    assert_eq!(error.loc.unwrap().start, Position { offset: 17, line: 2, column: 4 });
+  }
+
+  #[test]
+  fn test_invalid_interpolation_location() {
+    let mut parser = Parser::new("<div>{{ a b }}</div>");
+    let ast = Rc::new(RefCell::new(parser.parse()));
+    let mut runner = TransformRunner::new(
+      vec![Box::new(Expression {})],
+      TransformContext::new("hello.vue".to_string()),
+    );
+    runner.transform(ast);
+    assert_eq!(runner.ctx.errors.len(), 1);
+    let loc = runner.ctx.errors[0].loc.unwrap();
+    assert_eq!(loc.start, Position { offset: 10, line: 1, column: 11 });
+    assert_eq!(loc.end, Position { offset: 11, line: 1, column: 12 });
+  }
+
+  #[test]
+  fn test_statements_not_allowed() {
+    let mut ctx = TransformContext::new("hello.vue".to_string());
+    assert_eq!(process_with("foo(); bar()", &[], &mut ctx), "foo(); bar()");
+    assert_eq!(process_with("a); (b", &[], &mut ctx), "a); (b");
+    assert_eq!(ctx.errors.len(), 2);
+  }
+
+  #[test]
+  fn test_object_literal_expression() {
+    assert_eq!(process("{ a: b }", &[]), "{ a: _ctx.b }");
+  }
+
+  #[test]
+  fn test_v_on_statements() {
+    let mut ctx = TransformContext::new("hello.vue".to_string());
+    let mut exp = SimpleExpressionNode {
+      content: "foo(); bar = 1".to_string(),
+      is_static: false,
+      constant_type: ConstantTypes::NotConstant,
+      loc: Default::default(),
+    };
+    process_expression(&mut exp, &mut ctx, false, true);
+    assert_eq!(exp.content, "_ctx.foo(); _ctx.bar = 1");
+    assert!(ctx.errors.is_empty());
+  }
+
+  #[test]
+  fn test_free_identifiers() {
+    assert_eq!(process("foo(bar)", &[]), "_ctx.foo(_ctx.bar)");
+    assert_eq!(process("foo(\n  bar, /* c */ 'baz'\n)", &[]), "_ctx.foo(\n  _ctx.bar, /* c */ 'baz'\n)");
+    assert_eq!(process("foo(baz + 1, { key: kuz })", &[]), "_ctx.foo(_ctx.baz + 1, { key: _ctx.kuz })");
+    assert_eq!(process("ok ? foo : bar", &[]), "_ctx.ok ? _ctx.foo : _ctx.bar");
+    assert_eq!(process("`${foo}-${bar.baz}`", &[]), "`${_ctx.foo}-${_ctx.bar.baz}`");
+    assert_eq!(process("foo?.bar?.[baz]", &[]), "_ctx.foo?.bar?.[_ctx.baz]");
+    assert_eq!(process("new Foo(bar)", &[]), "new _ctx.Foo(_ctx.bar)");
+    assert_eq!(process("typeof foo === 'string'", &[]), "typeof _ctx.foo === 'string'");
+    assert_eq!(process("foo[bar].baz", &[]), "_ctx.foo[_ctx.bar].baz");
+    assert_eq!(process("!foo && -bar", &[]), "!_ctx.foo && -_ctx.bar");
+  }
+
+  #[test]
+  fn test_object_keys_not_prefixed() {
+    assert_eq!(process("{ foo: bar, [baz]: 1, qux }", &[]), "{ foo: _ctx.bar, [_ctx.baz]: 1, qux: _ctx.qux }");
+    assert_eq!(process("{ foo }", &["foo"]), "{ foo }");
+    assert_eq!(process("foo = { bar: 1 }", &[]), "_ctx.foo = { bar: 1 }");
+  }
+
+  #[test]
+  fn test_function_scopes() {
+    assert_eq!(process("(a = b) => a + c", &[]), "(a = _ctx.b) => a + _ctx.c");
+    assert_eq!(process("function foo(x) { return foo(x) + y }", &[]), "function foo(x) { return foo(x) + _ctx.y }");
+    assert_eq!(process("({ a: { b } }) => b + a", &[]), "({ a: { b } }) => b + _ctx.a");
+    assert_eq!(process("() => { for (let i = 0; i < n; i++) foo(i) }", &[]), "() => { for (let i = 0; i < _ctx.n; i++) _ctx.foo(i) }");
+  }
+
+  #[test]
+  fn test_collect_references() {
+    let mut source = "foo.bar + { baz: 1 }[qux]".to_string();
+    let mut idents = ProcessIdentifiers::new(&mut source, false);
+    idents.parse().unwrap();
+    assert_eq!(idents.collect_identifiers(), vec!["foo", "qux"]);
+  }
+}
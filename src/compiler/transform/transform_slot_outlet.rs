@@ -6,30 +6,151 @@ use crate::{
   ElementNodeCodeGen,
   RenderSlotCall,
   TransformContext,
+  ElementTagType,
+  Prop,
+  NormalProp,
+  PropExpression,
+  ExpressionNode,
+  FunctionExpression,
+  Returns,
+  BuildPropResult,
+  CompilerError,
+  ErrorCodes,
+  build_props,
+  get_static_exp,
+  camelize,
+  to_js_string_literal,
 };
 
 pub struct Slot {}
 
 impl Transform for Slot {
-  fn pre_transform(&self, node: &mut Node, _ctx: &mut TransformContext) {
-    if let Node::ElementNode(n) = node {
-      let mut args: Vec<RenderSlotCallArgs> = vec![];
-      args.push(RenderSlotCallArgs::RawText("slot".to_string()));
-      args.push(RenderSlotCallArgs::RawText("default".to_string()));
+  fn pre_transform(&self, node: &mut Node, ctx: &mut TransformContext) {
+    let Node::ElementNode(n) = node else {
+      return;
+    };
+    if n.tag_type != ElementTagType::SlotOutletNode {
+      return;
+    }
+    // 后备内容作为函数参数, 等子节点转换完成后再生成
+    ctx.on_exit(Box::new(|node, ctx| {
+      let (name, props) = process_slot_outlet(node, ctx);
+      let Node::ElementNode(n) = node else {
+        return;
+      };
+      let slots = if ctx.prefix_identifiers { "_ctx.$slots" } else { "$slots" };
+      let mut args = vec![RenderSlotCallArgs::RawText(slots.to_string()), name];
+      // 省略末尾的默认参数 `renderSlot(slots, name, {}, undefined)`
+      let has_fallback = !n.children.borrow().is_empty();
+      if props.is_some() || has_fallback {
+        args.push(match props {
+          Some(props) => RenderSlotCallArgs::PropExpression(props),
+          None => RenderSlotCallArgs::RawText("{}".to_string()),
+        });
+      }
+      if has_fallback {
+        args.push(RenderSlotCallArgs::FunctionExpression(FunctionExpression {
+          params: None,
+          returns: Some(Returns::Children(n.children.clone())),
+          body: None,
+          newline: false,
+          is_slot: false,
+          is_non_scoped_slot: None,
+        }));
+      }
 
-      let code_gen = ElementNodeCodeGen {
+      n.code_gen = Some(ElementNodeCodeGen {
         gen_type: CodeGenType::SlotOutletNodeCodeGen,
         render_slot_call: Some(RenderSlotCall {
           call: "renderSlot".to_string(),
-          args
+          args,
         }),
         ..Default::default()
-      };
-      n.code_gen = Some(code_gen);
-    }
+      });
+    }));
   }
 
   fn post_transform(&self, _node: &mut Node, _ctx: &mut TransformContext) {
-    return;
+  }
+}
+
+/// 插槽名可以是静态的 `name="foo"` 或动态的 `:name="foo"`, 其余属性作为插槽的 props
+fn process_slot_outlet(
+  node: &mut Node,
+  ctx: &mut TransformContext,
+) -> (RenderSlotCallArgs, Option<PropExpression>) {
+  let Node::ElementNode(n) = node else {
+    unreachable!();
+  };
+  let mut name = RenderSlotCallArgs::RawText(to_js_string_literal("default"));
+  let mut non_name_props = vec![];
+  for prop in n.props.borrow().iter() {
+    match prop {
+      Prop::Normal(NormalProp { name: prop_name, value: Some(value), .. }) if prop_name == "name" => {
+        name = RenderSlotCallArgs::RawText(to_js_string_literal(&value.content));
+      },
+      Prop::Normal(p) => {
+        // 没有值的属性不会传给插槽
+        if p.value.is_some() {
+          let mut p = p.clone();
+          p.name = camelize(&p.name);
+          non_name_props.push(Prop::Normal(p));
+        }
+      },
+      Prop::Directive(d) if d.name == "bind"
+        && d.arg.as_ref().and_then(get_static_exp).is_some_and(|arg| arg.content == "name") => {
+        match &d.exp {
+          Some(ExpressionNode::SimpleExpressionNode(exp)) => {
+            name = RenderSlotCallArgs::SimpleExpressionNode(exp.clone());
+          },
+          Some(ExpressionNode::CompoundExpressionNode(exp)) => {
+            name = RenderSlotCallArgs::CompoundExpressionNode(exp.clone());
+          },
+          None => {},
+        }
+      },
+      Prop::Directive(d) => {
+        let mut d = d.clone();
+        if d.name == "bind" {
+          if let Some(ExpressionNode::SimpleExpressionNode(arg)) = &mut d.arg {
+            if arg.is_static {
+              arg.content = camelize(&arg.content);
+            }
+          }
+        }
+        non_name_props.push(Prop::Directive(d));
+      },
+    }
+  }
+  if non_name_props.is_empty() {
+    return (name, None);
+  }
+  let BuildPropResult { props, directive, .. } = build_props(node, non_name_props, ctx);
+  if let Some(dir) = directive.first() {
+    ctx.on_error(CompilerError::new(ErrorCodes::XVSlotUnexpectedDirectiveOnSlotOutlet, Some(dir.loc), ""));
+  }
+  (name, props)
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::{compile, CompilerOptions, ErrorCodes};
+
+  fn render(source: &str) -> String {
+    let code = compile(source, CompilerOptions::default()).code;
+    code[code.find("function render").unwrap()..].to_string()
+  }
+
+  #[test]
+  fn test_slot_outlet() {
+    assert_eq!(render("<div><slot></slot></div>"), "function render(_ctx, _cache) {\n  return (_openBlock(), _createElementBlock(\"div\", null, [\n    _renderSlot(_ctx.$slots, \"default\")\n  ]))\n}");
+    assert_eq!(render("<div><slot :name=\"foo\" foo-bar=\"a\" :baz=\"b\" disabled></slot></div>"), "function render(_ctx, _cache) {\n  return (_openBlock(), _createElementBlock(\"div\", null, [\n    _renderSlot(_ctx.$slots, _ctx.foo, {\n      fooBar: \"a\",\n      baz: _ctx.b\n    })\n  ]))\n}");
+    assert_eq!(render("<div><slot name=\"foo\">fallback {{ a }}</slot></div>"), "function render(_ctx, _cache) {\n  return (_openBlock(), _createElementBlock(\"div\", null, [\n    _renderSlot(_ctx.$slots, \"foo\", {}, () => [\n      _createTextVNode(\"fallback \" + _toDisplayString(_ctx.a), 1 /* TEXT */)\n    ])\n  ]))\n}");
+  }
+
+  #[test]
+  fn test_unexpected_directive() {
+    let result = compile("<div><slot v-foo></slot></div>", CompilerOptions::default());
+    assert_eq!(result.errors.iter().map(|e| e.code).collect::<Vec<_>>(), vec![ErrorCodes::XVSlotUnexpectedDirectiveOnSlotOutlet]);
   }
 }
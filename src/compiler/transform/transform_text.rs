@@ -1,124 +1,128 @@
 use crate::{
-  Transform, 
+  Transform,
   Node,
+  ElementTagType,
   CompoundExpressionNode,
   CompoundExpressionNodeChild,
   TextCallNode,
   TextCallNodeContent,
+  TemplateChildNode,
+  CallExpression,
+  CallExpressionArgument,
+  ConstantTypes,
+  PatchFlags,
   TransformContext,
+  Children,
+  get_constant_type,
+  format_patch_flag,
+  CREATE_TEXT,
 };
 
 
 pub struct TransformText;
 
 impl Transform for TransformText {
-  #[allow(unused_variables)]
-  fn pre_transform(&self, node: &mut Node, _ctx: &mut TransformContext) {
-    return;
+  fn pre_transform(&self, _node: &mut Node, _ctx: &mut TransformContext) {
   }
 
-  fn post_transform(&self, node: &mut Node, _ctx: &mut TransformContext) {
-    let children = get_node_children(node);
-    if let Some(children) = children {
-      process_children(children);
+  fn post_transform(&self, node: &mut Node, ctx: &mut TransformContext) {
+    // 只有根节点和原生元素的单个文本子节点可以直接作为 children 字符串,
+    // 组件(插槽)、`<template>`、v-if 分支和 v-for 的子节点总是 vnode 数组
+    let (containers, allow_single_text) = match node {
+      Node::Root(root) => (vec![root.children.clone()], true),
+      // 有运行时指令时, TransformElement 会再把单个文本子节点转换为 vnode
+      Node::ElementNode(el) => (vec![el.children.clone()], el.tag_type == ElementTagType::PlainElementNode),
+      Node::If(if_node) => (if_node.branches.iter().map(|b| b.children.clone()).collect(), false),
+      Node::IFBranch(branch) => (vec![branch.children.clone()], false),
+      Node::For(for_node) => (vec![for_node.children.clone()], false),
+      _ => return,
+    };
+    for children in containers.iter() {
+      process_children(children, allow_single_text, ctx);
     }
   }
 }
 
-fn get_node_children(node: &mut Node) -> Option<&mut Vec<Node>> {
-  match node {
-    Node::ElementNode(n) => Some(&mut n.children),
-    Node::Root(n) => Some(&mut n.children),
-    _ => None
-  }
-}
-
-// 1. bar {{ foo }} baz 这种情况下要把, 要把3个节点合并为一个节点CompoundNode。
-// 2. 检查父元素如果只有单个子元素直接返回即可。
-// 3. 如果父元素有多个子元素，那么需要为每个文本节点, 创建一个TextCall。
-
-fn process_children(children: &mut Vec<Node>) {
+/// 1. 相邻的文本和插值合并为一个 CompoundExpression: `"foo " + _toDisplayString(bar)`
+/// 2. 只有一个文本子节点并且允许时, 直接作为元素的 children, 不需要创建 vnode
+/// 3. 否则每个文本子节点转换为 `createTextVNode` 调用
+fn process_children(children: &Children, allow_single_text: bool, ctx: &mut TransformContext) {
   let mut has_text = false;
-  let mut has_compound = false;
-
-  for i in 0..children.len() {
-
-    let child =&mut children[i];
-    let is_text: Option<CompoundExpressionNodeChild> = child.clone().into();
-    if let Some(prev ) = is_text {
-      has_text = true;
-      let mut j = i + 1;
-      loop {
-        if j >= children.len() {
-          break;
+  let mut merged = Vec::with_capacity(children.borrow().len());
+  for child in children.borrow().iter() {
+    let Some(text) = as_compound_child(&child.borrow()) else {
+      merged.push(child.clone());
+      continue;
+    };
+    has_text = true;
+    let prev = merged.last().filter(|prev| {
+      matches!(&*prev.borrow(), Node::Text(_) | Node::Interpolation(_) | Node::CompoundExpression(_))
+    });
+    match prev {
+      Some(prev) => {
+        let mut prev = prev.borrow_mut();
+        if !matches!(&*prev, Node::CompoundExpression(_)) {
+          let first = as_compound_child(&prev).unwrap();
+          *prev = Node::CompoundExpression(CompoundExpressionNode { children: vec![first] });
         }
-        let next = &mut children[j];
-        let is_text: Option<CompoundExpressionNodeChild> = next.clone().into();
-
-        if let Some(next) = is_text {
-          if !has_compound {
-            has_compound = true;
-            children[i] = Node::CompoundExpression(CompoundExpressionNode {
-              children: vec![prev.clone()]
-            });
-          }
-          if let Node::CompoundExpression(n) = &mut children[i] {
-            n.children.push(CompoundExpressionNodeChild::RawText(" + ".to_string()));
-            n.children.push(next);
-            j += 1;
-          }
-          children.remove(j); // 删除节点, j要回退1
-        } else {
-          has_compound = false;
-          break;
+        if let Node::CompoundExpression(compound) = &mut *prev {
+          compound.children.push(CompoundExpressionNodeChild::RawText(" + ".to_string()));
+          compound.children.push(text);
         }
-      }
+      },
+      None => merged.push(child.clone()),
     }
   }
+  // 合并后的 CompoundExpression 只由相邻的文本产生, 原有的表达式节点不会被合并进来
+  *children.borrow_mut() = merged;
 
-  if !has_text || children.len() == 1 {
+  if !has_text || (allow_single_text && children.borrow().len() == 1) {
     return;
   }
-
-  for child in children {
-    let text_call_node_child: Option<TextCallNodeContent> = child.into();
-    if let Some(text) = text_call_node_child {
-
-      *child = Node::TextCall(TextCallNode {
-        content: text,
-        loc: Default::default()
-      })
-    }
-  }
+  create_text_calls(children, ctx);
 }
 
-impl From<Node> for Option<CompoundExpressionNodeChild> {
-  fn from(node: Node) -> Self {
-    match node {
-      Node::Text(n) => {
-        Some(CompoundExpressionNodeChild::TextNode(n))
-      },
-      Node::Interpolation(n) => {
-        Some(CompoundExpressionNodeChild::InterpolationNode(n))
-      },
-      _ => None
+/// 文本子节点转换为 `createTextVNode` 调用
+pub fn create_text_calls(children: &Children, ctx: &mut TransformContext) {
+  for child in children.borrow().iter() {
+    let content = match &*child.borrow() {
+      Node::Text(text) => TextCallNodeContent::TextNode(text.clone()),
+      Node::Interpolation(n) => TextCallNodeContent::InterpolationNode(n.clone()),
+      Node::CompoundExpression(n) => TextCallNodeContent::CompoundExpressionNode(n.clone()),
+      _ => continue,
+    };
+    let mut arguments = vec![];
+    // 单个空格是 createTextVNode 的默认值, 可以省略
+    if !matches!(&content, TextCallNodeContent::TextNode(text) if text.content == " ") {
+      arguments.push(CallExpressionArgument::TemplateChildNode(match &content {
+        TextCallNodeContent::TextNode(n) => TemplateChildNode::TextNode(n.clone()),
+        TextCallNodeContent::InterpolationNode(n) => TemplateChildNode::InterpolationNode(n.clone()),
+        TextCallNodeContent::CompoundExpressionNode(n) => TemplateChildNode::CompoundExpressionNode(n.clone()),
+      }));
+    }
+    if !ctx.ssr && get_constant_type(&child.borrow()) == ConstantTypes::NotConstant {
+      arguments.push(CallExpressionArgument::RawText(format_patch_flag(PatchFlags::TEXT)));
     }
+    let loc = match &content {
+      TextCallNodeContent::TextNode(n) => n.loc,
+      _ => Default::default(),
+    };
+    *child.borrow_mut() = Node::TextCall(TextCallNode {
+      content,
+      code_gen: CallExpression {
+        callee: CREATE_TEXT.to_string(),
+        arguments,
+      },
+      hoisted: None,
+      loc,
+    });
   }
 }
 
-impl From<&mut Node> for Option<TextCallNodeContent> {
-  fn from(node: &mut Node) -> Self {
-    match node {
-      Node::Text(n) => {
-        Some(TextCallNodeContent::TextNode(n.clone()))
-      },
-      Node::Interpolation(n) => {
-        Some(TextCallNodeContent::InterpolationNode(n.clone()))
-      },
-      Node::CompoundExpression(n) => {
-        Some(TextCallNodeContent::CompoundExpressionNode(n.clone()))
-      },
-      _ => None
-    }
+fn as_compound_child(node: &Node) -> Option<CompoundExpressionNodeChild> {
+  match node {
+    Node::Text(n) => Some(CompoundExpressionNodeChild::TextNode(n.clone())),
+    Node::Interpolation(n) => Some(CompoundExpressionNodeChild::InterpolationNode(n.clone())),
+    _ => None,
   }
-}
\ No newline at end of file
+}
@@ -1,38 +1,187 @@
 use crate::{
-  DirectiveTransform, 
-  DirectiveTransformRes, 
-  DirectiveProp, 
-  TransformContext, ExpressionNode, Property, JsChildNode
+  DirectiveTransform,
+  DirectiveTransformRes,
+  DirectiveProp,
+  TransformContext, ExpressionNode, Property, JsChildNode, SimpleExpressionNode, ConstantTypes,
+  CompilerError, ErrorCodes, camelize, process_expression, CAMELIZE,
 };
 
 pub struct TransformBind;
 
 impl DirectiveTransform for TransformBind {
     fn transform(
-      &self, 
-      dir: &mut DirectiveProp, 
-      _node: &mut crate::Node, 
-      _ctx: &mut TransformContext) -> DirectiveTransformRes {
-        let DirectiveProp { 
-          arg, 
-          exp, .. } = dir;
-
-        if let Some(ExpressionNode::SimpleExpressionNode(exp)) = exp {
-          if exp.content == "" {
-            todo!("emit error ErrorCodes.X_V_BIND_NO_EXPRESSION");
+      &self,
+      dir: &mut DirectiveProp,
+      _node: &mut crate::Node,
+      ctx: &mut TransformContext) -> DirectiveTransformRes {
+        let Some(ExpressionNode::SimpleExpressionNode(mut key)) = dir.arg.clone() else {
+          return DirectiveTransformRes { properties: vec![], need_runtime: false };
+        };
+
+        // `:foo` 没有值时绑定同名的变量 `foo`
+        let is_empty_exp = !matches!(
+          &dir.exp,
+          Some(ExpressionNode::SimpleExpressionNode(exp)) if !exp.content.trim().is_empty()
+        );
+        if is_empty_exp {
+          if !key.is_static {
+            ctx.on_error(CompilerError::new(
+              ErrorCodes::XVBindInvalidSameNameArgument,
+              Some(key.loc),
+              "",
+            ));
+            return DirectiveTransformRes {
+              properties: vec![Property {
+                key: ExpressionNode::SimpleExpressionNode(key.clone()),
+                value: Box::new(JsChildNode::ExpressionNode(ExpressionNode::SimpleExpressionNode(
+                  SimpleExpressionNode {
+                    content: "".to_string(),
+                    is_static: true,
+                    constant_type: ConstantTypes::CanStringify,
+                    loc: key.loc,
+                  }
+                ))),
+              }],
+              need_runtime: false,
+            };
+          }
+          if resolve_same_name_shorthand(dir) {
+            if let Some(ExpressionNode::SimpleExpressionNode(exp)) = &mut dir.exp {
+              process_expression(exp, ctx, false, false);
+            }
+          }
+        }
+
+        // 动态参数的值可能是 null/undefined, 作为 key 时需要转换为空字符串
+        if !key.is_static {
+          key.content = format!("{} || \"\"", key.content);
+        }
+
+        if dir.modifiers.iter().any(|m| m == "camel") {
+          if key.is_static {
+            key.content = camelize(&key.content);
+          } else {
+            key.content = format!("{}({})", ctx.helper(CAMELIZE), key.content);
+          }
+        }
+
+        // `.prop` 和 `.attr` 强制作为 DOM property 或 attribute 设置, SSR 中没有区别
+        if !ctx.ssr {
+          if dir.modifiers.iter().any(|m| m == "prop") {
+            inject_prefix(&mut key, ".");
+          }
+          if dir.modifiers.iter().any(|m| m == "attr") {
+            inject_prefix(&mut key, "^");
           }
-        } else {
-          todo!("emit error ErrorCodes.X_V_BIND_NO_EXPRESSION");
         }
 
-        DirectiveTransformRes { 
+        DirectiveTransformRes {
           properties: vec![
             Property {
-              key: arg.clone().unwrap(),
-              value: Box::new(JsChildNode::ExpressionNode(exp.clone().unwrap()))
+              key: ExpressionNode::SimpleExpressionNode(key),
+              value: Box::new(JsChildNode::ExpressionNode(dir.exp.clone().unwrap()))
             }
-          ], 
-          need_runtime: false 
+          ],
+          need_runtime: false
         }
     }
 }
+
+/// 同名简写 `:foo-bar` 没有值时, 绑定驼峰化的同名变量 `fooBar`, 返回是否补上了表达式
+pub fn resolve_same_name_shorthand(dir: &mut DirectiveProp) -> bool {
+  if dir.name != "bind" {
+    return false;
+  }
+  let is_empty_exp = !matches!(
+    &dir.exp,
+    Some(ExpressionNode::SimpleExpressionNode(exp)) if !exp.content.trim().is_empty()
+  );
+  match &dir.arg {
+    Some(ExpressionNode::SimpleExpressionNode(arg)) if is_empty_exp && arg.is_static => {
+      dir.exp = Some(ExpressionNode::SimpleExpressionNode(SimpleExpressionNode {
+        content: camelize(&arg.content),
+        is_static: false,
+        constant_type: ConstantTypes::NotConstant,
+        loc: arg.loc,
+      }));
+      true
+    },
+    _ => false,
+  }
+}
+
+fn inject_prefix(key: &mut SimpleExpressionNode, prefix: &str) {
+  if key.is_static {
+    key.content = format!("{}{}", prefix, key.content);
+  } else {
+    key.content = format!("`{}${{{}}}`", prefix, key.content);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::{
+    Parser, Node, Prop, TransformContext, DirectiveTransform, ExpressionNode, JsChildNode,
+    TransformBind, ErrorCodes,
+  };
+
+  fn transform_bind(source: &str, ctx: &mut TransformContext) -> Vec<(String, String)> {
+    let mut parser = Parser::new(source);
+    let ast = parser.parse();
+    let node = ast.children().unwrap().borrow()[0].clone();
+    let mut node = node.borrow_mut();
+    let mut dir = match &*node {
+      Node::ElementNode(el) => match &el.props.borrow()[0] {
+        Prop::Directive(d) => d.clone(),
+        _ => panic!("expected directive"),
+      },
+      _ => panic!("expected element"),
+    };
+    let res = TransformBind.transform(&mut dir, &mut node, ctx);
+    res.properties.iter().map(|p| {
+      let key = match &p.key {
+        ExpressionNode::SimpleExpressionNode(key) => key.content.clone(),
+        _ => panic!("expected simple key"),
+      };
+      let value = match &*p.value {
+        JsChildNode::ExpressionNode(ExpressionNode::SimpleExpressionNode(exp)) => exp.content.clone(),
+        _ => panic!("expected simple value"),
+      };
+      (key, value)
+    }).collect()
+  }
+
+  fn bind(source: &str) -> (String, String) {
+    let mut ctx = TransformContext::new("hello.vue".to_string());
+    transform_bind(source, &mut ctx).remove(0)
+  }
+
+  #[test]
+  fn test_camel_modifier() {
+    assert_eq!(bind("<svg :view-box.camel=\"box\"/>"), ("viewBox".to_string(), "box".to_string()));
+    assert_eq!(bind("<svg :[name].camel=\"box\"/>"), ("_camelize(name || \"\")".to_string(), "box".to_string()));
+  }
+
+  #[test]
+  fn test_prop_and_attr_modifiers() {
+    assert_eq!(bind("<div :inner-html.prop=\"html\"/>"), (".inner-html".to_string(), "html".to_string()));
+    assert_eq!(bind("<div .textContent=\"text\"/>"), (".textContent".to_string(), "text".to_string()));
+    assert_eq!(bind("<div :aria-label.attr=\"label\"/>"), ("^aria-label".to_string(), "label".to_string()));
+    assert_eq!(bind("<div :[name].prop=\"value\"/>"), ("`.${name || \"\"}`".to_string(), "value".to_string()));
+  }
+
+  #[test]
+  fn test_same_name_shorthand() {
+    assert_eq!(bind("<div :id/>"), ("id".to_string(), "_ctx.id".to_string()));
+    assert_eq!(bind("<div :foo-bar/>"), ("foo-bar".to_string(), "_ctx.fooBar".to_string()));
+    assert_eq!(bind("<div .inner-text/>"), (".inner-text".to_string(), "_ctx.innerText".to_string()));
+  }
+
+  #[test]
+  fn test_same_name_shorthand_dynamic_arg() {
+    let mut ctx = TransformContext::new("hello.vue".to_string());
+    transform_bind("<div :[name]/>", &mut ctx);
+    assert_eq!(ctx.errors.len(), 1);
+    assert_eq!(ctx.errors[0].code, ErrorCodes::XVBindInvalidSameNameArgument);
+  }
+}
@@ -1,13 +1,253 @@
-use crate::{Node, Transform, TransformContext};
+use std::{cell::RefCell, rc::Rc};
+
+use regex::Regex;
+
+use crate::{
+  Node, Transform, TransformContext, Prop, ExpressionNode, SimpleExpressionNode, ConstantTypes,
+  ForNode, process_expression, collect_param_bindings, CompilerError, ErrorCodes, is_slot_template,
+};
 
 pub struct TransformVFor;
 
 impl Transform for TransformVFor {
   fn pre_transform(&self, node: &mut Node, ctx: &mut TransformContext) {
-    return;
+    let Node::ElementNode(el) = node else {
+      return;
+    };
+    // 插槽模板上的 v-for 由 build_slots 处理
+    if is_slot_template(el) {
+      return;
+    }
+    let dir = el.props.borrow().iter().find_map(|p| match p {
+      Prop::Directive(d) if d.name == "for" => Some(d.clone()),
+      _ => None,
+    });
+    let Some(dir) = dir else {
+      return;
+    };
+    // 出错时保留原来的元素, 当作没有 v-for 渲染
+    let result = match &dir.exp {
+      Some(ExpressionNode::SimpleExpressionNode(exp)) if !exp.content.trim().is_empty() => {
+        parse_for_expression(&exp.content)
+      },
+      _ => {
+        ctx.on_error(CompilerError::new(ErrorCodes::XVForNoExpression, Some(dir.loc), ""));
+        return;
+      },
+    };
+    let Some(result) = result else {
+      ctx.on_error(CompilerError::new(ErrorCodes::XVForMalformedExpression, Some(dir.loc), ""));
+      return;
+    };
+
+    // 数据源在 v-for 作用域之外求值
+    let mut source = SimpleExpressionNode {
+      content: result.source,
+      is_static: false,
+      constant_type: ConstantTypes::NotConstant,
+      loc: dir.loc,
+    };
+    process_expression(&mut source, ctx, false, false);
+
+    let alias = |content: Option<String>| content.map(|content| {
+      ExpressionNode::SimpleExpressionNode(SimpleExpressionNode {
+        content,
+        is_static: false,
+        constant_type: ConstantTypes::NotConstant,
+        loc: dir.loc,
+      })
+    });
+
+    let mut child = el.clone();
+    let props = el.props.borrow()
+      .iter()
+      .filter(|p| !matches!(p, Prop::Directive(d) if d.name == "for"))
+      .cloned()
+      .collect();
+    child.props = Rc::new(RefCell::new(props));
+
+    let for_node = ForNode {
+      source: ExpressionNode::SimpleExpressionNode(source),
+      value_alias: alias(result.value),
+      key_alias: alias(result.key),
+      object_index_alias: alias(result.index),
+      children: Rc::new(RefCell::new(vec![Rc::new(RefCell::new(Node::ElementNode(child)))])),
+      loc: el.loc,
+    };
+    let bindings = get_for_bindings(&for_node, ctx.is_ts);
+    ctx.add_identifiers(&bindings);
+    ctx.scopes.v_for += 1;
+    ctx.replace_node(Node::For(for_node));
+    // 子节点转换完成后离开 v-for 的作用域
+    ctx.on_exit(Box::new(move |_, ctx| {
+      ctx.remove_identifiers(&bindings);
+      ctx.scopes.v_for -= 1;
+    }));
+  }
+
+  fn post_transform(&self, _node: &mut Node, _ctx: &mut TransformContext) {}
+}
+
+/// v-for 别名中声明的变量
+pub fn get_for_bindings(for_node: &ForNode, is_ts: bool) -> Vec<String> {
+  [&for_node.value_alias, &for_node.key_alias, &for_node.object_index_alias]
+    .into_iter()
+    .flatten()
+    .flat_map(|alias| match alias {
+      ExpressionNode::SimpleExpressionNode(alias) => collect_param_bindings(&alias.content, is_ts),
+      _ => vec![],
+    })
+    .collect()
+}
+
+#[derive(Debug, PartialEq)]
+pub struct ForParseResult {
+  pub source: String,
+  pub value: Option<String>,
+  pub key: Option<String>,
+  pub index: Option<String>,
+}
+
+/// 解析 `item in list`, `(item, index) of list` 形式的 v-for 表达式
+pub fn parse_for_expression(content: &str) -> Option<ForParseResult> {
+  let reg = Regex::new(r"^([\s\S]*?)\s+(?:in|of)\s+([\s\S]*)$").unwrap();
+  let caps = reg.captures(content.trim())?;
+  let alias = caps[1].trim();
+  let alias = alias
+    .strip_prefix('(')
+    .and_then(|a| a.strip_suffix(')'))
+    .unwrap_or(alias)
+    .trim();
+  let source = caps[2].trim();
+  if source.is_empty() {
+    return None;
+  }
+
+  // 解构的 value 中可能包含逗号, 只从末尾匹配 key 和 index
+  let iterator_reg = Regex::new(r",([^,\}\]]*)(?:,([^,\}\]]*))?$").unwrap();
+  let non_empty = |s: &str| {
+    let s = s.trim();
+    if s.is_empty() { None } else { Some(s.to_string()) }
+  };
+  let (value, key, index) = match iterator_reg.captures(alias) {
+    Some(caps) => (
+      non_empty(&alias[..caps.get(0).unwrap().start()]),
+      non_empty(&caps[1]),
+      caps.get(2).and_then(|m| non_empty(m.as_str())),
+    ),
+    None => (non_empty(alias), None, None),
+  };
+
+  Some(ForParseResult {
+    source: source.to_string(),
+    value,
+    key,
+    index,
+  })
+}
+#[cfg(test)]
+mod tests {
+  use std::{cell::RefCell, rc::Rc};
+
+  use crate::{
+    Parser, TransformRunner, TransformContext, Expression, TransformVFor, TrackSlotScopes,
+    Node, ExpressionNode, parse_for_expression, ForParseResult, ErrorCodes,
+  };
+
+  fn interpolation_contents(source: &str) -> Vec<String> {
+    let mut parser = Parser::new(source);
+    let ast = Rc::new(RefCell::new(parser.parse()));
+    let ctx = TransformContext::new("hello.vue".to_string());
+    let mut runner = TransformRunner::new(
+      vec![Box::new(TransformVFor), Box::new(TrackSlotScopes), Box::new(Expression {})],
+      ctx,
+    );
+    runner.transform(ast.clone());
+    assert!(runner.ctx.identifiers.is_empty());
+    assert_eq!(runner.ctx.scopes.v_for, 0);
+
+    let mut contents = vec![];
+    collect_interpolations(&ast.borrow(), &mut contents);
+    contents
+  }
+
+  fn collect_interpolations(node: &Node, contents: &mut Vec<String>) {
+    if let Node::Interpolation(i) = node {
+      if let ExpressionNode::SimpleExpressionNode(exp) = &i.content {
+        contents.push(exp.content.clone());
+      }
+    }
+    if let Some(children) = node.children() {
+      for child in children.borrow().iter() {
+        collect_interpolations(&child.borrow(), contents);
+      }
+    }
+  }
+
+  #[test]
+  fn test_parse_for_expression() {
+    assert_eq!(
+      parse_for_expression("(item, index) of list"),
+      Some(ForParseResult {
+        source: "list".to_string(),
+        value: Some("item".to_string()),
+        key: Some("index".to_string()),
+        index: None,
+      })
+    );
+    assert_eq!(
+      parse_for_expression("({ a, b }, key, i) in items"),
+      Some(ForParseResult {
+        source: "items".to_string(),
+        value: Some("{ a, b }".to_string()),
+        key: Some("key".to_string()),
+        index: Some("i".to_string()),
+      })
+    );
+    assert_eq!(
+      parse_for_expression("(, i) in items"),
+      Some(ForParseResult { source: "items".to_string(), value: None, key: Some("i".to_string()), index: None })
+    );
+    assert_eq!(parse_for_expression("items"), None);
+  }
+
+  #[test]
+  fn test_v_for_errors() {
+    let errors = |source: &str| {
+      let mut parser = Parser::new(source);
+      let ast = Rc::new(RefCell::new(parser.parse()));
+      let mut runner = TransformRunner::new(
+        vec![Box::new(TransformVFor)],
+        TransformContext::new("hello.vue".to_string()),
+      );
+      runner.transform(ast.clone());
+      runner.ctx.errors.iter().map(|e| e.code).collect::<Vec<_>>()
+    };
+    assert_eq!(errors("<div v-for=\"items\"></div>"), vec![ErrorCodes::XVForMalformedExpression]);
+    assert_eq!(errors("<div v-for></div>"), vec![ErrorCodes::XVForNoExpression]);
+  }
+
+  #[test]
+  fn test_v_for_alias_not_prefixed() {
+    let contents = interpolation_contents(
+      "<div v-for=\"(item, index) in list\">{{ item }}{{ index + foo }}</div>{{ item }}"
+    );
+    assert_eq!(contents, vec!["item", "index + _ctx.foo", "_ctx.item"]);
+  }
+
+  #[test]
+  fn test_v_for_destructured_alias() {
+    let contents = interpolation_contents(
+      "<div v-for=\"({ a, b: [c] }, i) in list\">{{ a + b + c + i }}</div>"
+    );
+    assert_eq!(contents, vec!["a + _ctx.b + c + i"]);
   }
 
-  fn post_transform(&self, node: &mut Node, ctx: &mut TransformContext) {
-    return;
-  } 
+  #[test]
+  fn test_slot_props_not_prefixed() {
+    let contents = interpolation_contents(
+      "<Comp v-slot=\"{ item }\">{{ item + foo }}</Comp>"
+    );
+    assert_eq!(contents, vec!["item + _ctx.foo"]);
+  }
 }
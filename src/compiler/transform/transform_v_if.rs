@@ -1,50 +1,177 @@
-use crate::{Node, Transform, TransformContext, Prop, IfNode};
+use crate::{
+  Node, Transform, TransformContext, Prop, IfNode, ExpressionNode, SimpleExpressionNode,
+  ConstantTypes, CompilerError, ErrorCodes, process_expression, inject_prop, key_property, is_slot_template,
+};
 
 pub struct TransformVIf;
 
 impl Transform for TransformVIf {
   fn pre_transform(&self, node: &mut Node, ctx: &mut TransformContext) {
-    if let Node::ElementNode(n) = node {
-      for p in n.props.clone().iter() {
-        if let Prop::Directive(d) = p {
-          if d.name == "if" {
-            let if_branch = node.create_if_branch_by_element_base(d.clone());
-            let if_node = IfNode { 
-              branches: vec![if_branch],
-              loc: Default::default()
-            };
-            node.replace_node(Node::If(if_node))
-          } else {
-            if let Some(parent) = ctx.parent.clone() {
-              let mut slibing = parent.borrow_mut();
-              let slibing = slibing.children_mut();
-              let mut index = slibing.borrow_mut().iter().position(|x| *x == node.clone()).unwrap();
-              while index >= 0 {
-                let mut node = slibing.borrow_mut();
-                let node = node.get_mut(index).unwrap();
-                if let Node::Text(text) = node {
-                  if text.content.trim().is_empty() {
-                    slibing.borrow_mut().remove(index);
-                  }
-                  index -= 1;
-                  continue;
-                }
-  
-                if let Node::IFBranch(n) = node {
-                  slibing.borrow_mut().remove(index);
-                  let branch = node.create_if_branch_by_element_base(d.clone());
-                  n.children.push(Node::IFBranch(branch));
-                }
-                break;
-              }
-            }
-          }
-        }
+    let dir = match node {
+      // 插槽模板上的 v-if 由 build_slots 处理
+      Node::ElementNode(n) if is_slot_template(n) => return,
+      Node::ElementNode(n) => n.props.borrow().iter().find_map(|p| match p {
+        Prop::Directive(d) if d.name == "if" || d.name == "else-if" || d.name == "else" => {
+          Some(d.clone())
+        },
+        _ => None,
+      }),
+      // 合并到 IfNode 中的 v-else 分支, 以及 SSR 回退时重新转换的 IfNode
+      Node::If(_) | Node::IFBranch(_) => {
+        ctx.on_exit(Box::new(create_branch_codegen));
+        return;
+      },
+      _ => return,
+    };
+    let Some(mut dir) = dir else {
+      return;
+    };
+
+    if dir.name != "else" {
+      match &mut dir.exp {
+        Some(ExpressionNode::SimpleExpressionNode(exp)) if !exp.content.trim().is_empty() => {
+          process_expression(exp, ctx, false, false);
+        },
+        // 缺少条件时报告错误, 按 `true` 继续编译
+        _ => {
+          ctx.on_error(CompilerError::new(ErrorCodes::XVIfNoExpression, Some(dir.loc), ""));
+          dir.exp = Some(ExpressionNode::SimpleExpressionNode(SimpleExpressionNode {
+            content: "true".to_string(),
+            is_static: false,
+            constant_type: ConstantTypes::NotConstant,
+            loc: dir.loc,
+          }));
+        },
+      }
+    }
+
+    let mut branch = node.create_if_branch_by_element_base(dir.clone());
+    if dir.name == "if" {
+      // 同一个父节点下的所有分支使用不同的 key
+      branch.key = ctx.siblings.as_ref().map_or(0, |siblings| {
+        siblings.borrow()[..ctx.current_index()]
+          .iter()
+          .map(|n| match &*n.borrow() {
+            Node::If(n) => n.branches.len(),
+            _ => 0,
+          })
+          .sum()
+      });
+      ctx.replace_node(Node::If(IfNode { 
+        branches: vec![branch],
+        loc: dir.loc,
+      }));
+      ctx.on_exit(Box::new(create_branch_codegen));
+      return;
+    }
+
+    let Some(siblings) = ctx.siblings.clone() else {
+      return;
+    };
+    let mut index = ctx.current_index();
+    let mut found_if = false;
+    while index > 0 {
+      index -= 1;
+      let prev_node = siblings.borrow()[index].clone();
+      let mut prev = prev_node.borrow_mut();
+      match &mut *prev {
+        // v-if 和 v-else 之间的空白和注释会被丢弃
+        Node::Text(text) if text.content.trim().is_empty() => {
+          ctx.remove_node(Some(&prev_node));
+        },
+        Node::Comment(_) => {
+          ctx.remove_node(Some(&prev_node));
+        },
+        Node::If(n) => {
+          branch.key = n.branches[0].key + n.branches.len();
+          n.branches.push(branch.clone());
+          found_if = true;
+          break;
+        },
+        _ => break,
       }
     }
+    if !found_if {
+      // 保留原来的元素, 当作没有 v-else 渲染
+      ctx.on_error(CompilerError::new(ErrorCodes::XVElseNoAdjacentIf, Some(dir.loc), ""));
+      return;
+    }
+    // 分支已经合并到 IfNode 中, 从父节点中移除后单独遍历分支的子节点
+    ctx.remove_node(None);
+    ctx.traverse_node(Node::IFBranch(branch));
   }
 
   fn post_transform(&self, _node: &mut Node, _ctx: &mut TransformContext) {
     return;
   }
 }
+
+/// 分支只有一个元素时, 元素自身作为 block 并带上分支的 key, 避免不同分支的元素被复用
+fn create_branch_codegen(node: &mut Node, _ctx: &mut TransformContext) {
+  let branches = match node {
+    Node::If(if_node) => if_node.branches.iter().collect::<Vec<_>>(),
+    Node::IFBranch(branch) => vec![&*branch],
+    _ => return,
+  };
+  for branch in branches {
+    let children = branch.children.borrow().clone();
+    let [child] = &children[..] else {
+      continue;
+    };
+    let mut child = child.borrow_mut();
+    if let Node::ElementNode(el) = &mut *child {
+      // v-memo 的 vnode 包在 withMemo 中, key 同样加在 vnode 上
+      let vnode = el.code_gen.as_mut().and_then(|c| match &mut c.memo_expression {
+        Some(memo) => Some(&mut *memo.value),
+        None => c.vnode_call.as_mut(),
+      });
+      if let Some(vnode) = vnode {
+        vnode.is_block = true;
+        inject_prop(vnode, key_property(branch.key));
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::{cell::RefCell, rc::Rc};
+
+  use crate::{Node, Parser, TransformRunner, TransformContext, TransformVIf, ErrorCodes};
+
+  fn transform(source: &str) -> (Rc<RefCell<Node>>, Vec<ErrorCodes>) {
+    let mut parser = Parser::new(source);
+    let ast = Rc::new(RefCell::new(parser.parse()));
+    let mut runner = TransformRunner::new(
+      vec![Box::new(TransformVIf)],
+      TransformContext::new("hello.vue".to_string()),
+    );
+    runner.transform(ast.clone());
+    (ast, runner.ctx.errors.iter().map(|e| e.code).collect())
+  }
+
+  fn errors(source: &str) -> Vec<ErrorCodes> {
+    transform(source).1
+  }
+
+  #[test]
+  fn test_branches_removed_from_parent() {
+    let (ast, _) = transform("<div v-if=\"a\"></div>\n<p v-else-if=\"b\"></p>\n<span v-else></span><i v-if=\"c\"></i>");
+    let children = ast.borrow().children().unwrap();
+    let children = children.borrow();
+    let keys = children.iter()
+      .map(|n| match &*n.borrow() {
+        Node::If(n) => n.branches.iter().map(|b| b.key).collect(),
+        _ => vec![],
+      })
+      .collect::<Vec<_>>();
+    assert_eq!(keys, vec![vec![0, 1, 2], vec![3]]);
+  }
+
+  #[test]
+  fn test_v_if_errors() {
+    assert_eq!(errors("<div v-if=\"\"></div>"), vec![ErrorCodes::XVIfNoExpression]);
+    assert_eq!(errors("<div v-else></div>"), vec![ErrorCodes::XVElseNoAdjacentIf]);
+    assert_eq!(errors("<div></div><div v-else-if=\"ok\"></div>"), vec![ErrorCodes::XVElseNoAdjacentIf]);
+  }
+}
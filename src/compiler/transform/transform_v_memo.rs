@@ -0,0 +1,45 @@
+use crate::{Node, Transform, TransformContext, Prop, ElementTagType, MemoExpression};
+
+/// `v-memo` 的依赖没有变化时复用上一次渲染的 vnode
+pub struct TransformMemo;
+
+impl Transform for TransformMemo {
+  fn pre_transform(&self, node: &mut Node, ctx: &mut TransformContext) {
+    let Node::ElementNode(el) = node else {
+      return;
+    };
+    if ctx.ssr || !el.props.borrow().iter().any(|p| matches!(p, Prop::Directive(d) if d.name == "memo")) {
+      return;
+    }
+    // 依赖表达式在 Expression 中加上前缀, 退出时再读取
+    ctx.on_exit(Box::new(|node, ctx| {
+      let Node::ElementNode(el) = node else {
+        return;
+      };
+      let deps = el.props.borrow().iter().find_map(|p| match p {
+        Prop::Directive(d) if d.name == "memo" => d.exp.clone(),
+        _ => None,
+      });
+      let Some(deps) = deps else {
+        return;
+      };
+      let is_component = el.tag_type == ElementTagType::ComponentNode;
+      let Some(code_gen) = el.code_gen.as_mut() else {
+        return;
+      };
+      let Some(mut vnode) = code_gen.vnode_call.take() else {
+        return;
+      };
+      // 非组件的子树作为 block, 复用时整体跳过
+      if !is_component {
+        vnode.is_block = true;
+      }
+      let index = ctx.cached;
+      ctx.cached += 1;
+      code_gen.memo_expression = Some(MemoExpression { deps, value: Box::new(vnode), index });
+    }));
+  }
+
+  fn post_transform(&self, _node: &mut Node, _ctx: &mut TransformContext) {
+  }
+}
@@ -1,83 +1,262 @@
-use crate::{DirectiveTransform, DirectiveTransformRes, DirectiveProp, Node, TransformContext, ExpressionNode, SimpleExpressionNode, ConstantTypes, Expression, CompoundExpressionNode, CompoundExpressionNodeChild, Property, JsChildNode};
+use crate::{
+  DirectiveTransform, DirectiveTransformRes, DirectiveProp, Node, TransformContext, ExpressionNode,
+  SimpleExpressionNode, ConstantTypes, CompoundExpressionNode, CompoundExpressionNodeChild,
+  Property, JsChildNode, ElementTagType, BindingTypes, process_expression, is_member_expression,
+  is_simple_identifier, IS_REF, CompilerError, ErrorCodes,
+};
 
 pub struct TransformVModel;
 
 impl DirectiveTransform for TransformVModel {
   fn transform(
-      &self, dir: 
-      &mut DirectiveProp, 
-      node: &mut Node, 
+      &self, dir:
+      &mut DirectiveProp,
+      node: &mut Node,
       ctx: &mut TransformContext) -> DirectiveTransformRes {
-      let DirectiveProp { 
-        arg, 
-        exp, .. } = dir;
+      // 出错时报告错误并忽略这个指令, 继续转换
+      let error = |code: ErrorCodes, ctx: &mut TransformContext| {
+        ctx.on_error(CompilerError::new(code, Some(dir.loc), ""));
+        DirectiveTransformRes { properties: vec![], need_runtime: false }
+      };
+      let exp = match &dir.exp {
+        Some(ExpressionNode::SimpleExpressionNode(exp)) if !exp.content.trim().is_empty() => exp.clone(),
+        _ => return error(ErrorCodes::XVModelNoExpression, ctx),
+      };
 
-      if exp.is_none() || arg.is_none() {
-        todo!("emit error ErrorCodes.X_V_MODEL_NO_EXPRESSION")
+      let raw_exp = exp.content.clone();
+      let binding_type = ctx.binding_metadata.get(&raw_exp).copied();
+      if matches!(binding_type, Some(BindingTypes::Props) | Some(BindingTypes::PropsAliased)) {
+        return error(ErrorCodes::XVModelOnProps, ctx);
+      }
+      // 内联模式下 setup 中的 ref 可以直接作为 v-model 的目标
+      let maybe_ref = ctx.inline && matches!(
+        binding_type,
+        Some(BindingTypes::SetupLet) | Some(BindingTypes::SetupRef) | Some(BindingTypes::SetupMaybeRef)
+      );
+      if !is_member_expression(&raw_exp) && !maybe_ref {
+        return error(ErrorCodes::XVModelMalformedExpression, ctx);
+      }
+      if is_simple_identifier(&raw_exp) && ctx.identifiers.contains_key(&raw_exp) {
+        return error(ErrorCodes::XVModelOnScopeVariable, ctx);
       }
 
-      let raw_str = match exp.clone().unwrap() {
-        ExpressionNode::SimpleExpressionNode(exp) => {
-          exp.content.clone()
-        },
-        _ => {
-          "".to_string()
-        }
-      };
+      let mut exp = exp;
+      process_expression(&mut exp, ctx, false, false);
+      dir.exp = Some(ExpressionNode::SimpleExpressionNode(exp.clone()));
 
-      let mut simple = SimpleExpressionNode {
-        content: "modelValue".to_string(),
-        is_static: false,
-        constant_type: ConstantTypes::NotConstant,
-        loc: Default::default(),
-      };
-      let prop_name = if let Some(ExpressionNode::SimpleExpressionNode(arg)) = arg {
-        arg
-      } else {
-        &simple
+      let (prop_name, event_name) = match &dir.arg {
+        Some(ExpressionNode::SimpleExpressionNode(arg)) if arg.is_static => (
+          ExpressionNode::SimpleExpressionNode(arg.clone()),
+          ExpressionNode::SimpleExpressionNode(static_key(format!("onUpdate:{}", arg.content))),
+        ),
+        Some(arg) => (
+          arg.clone(),
+          ExpressionNode::CompoundExpressionNode(CompoundExpressionNode {
+            children: [
+              vec![CompoundExpressionNodeChild::RawText("\"onUpdate:\" + ".to_string())],
+              compound_children(arg),
+            ].concat()
+          }),
+        ),
+        None => (
+          ExpressionNode::SimpleExpressionNode(static_key("modelValue".to_string())),
+          ExpressionNode::SimpleExpressionNode(static_key("onUpdate:modelValue".to_string())),
+        ),
       };
 
-      let event_name = "onUpdate:modelValue".to_string();
-
       let event_arg = if ctx.is_ts {
         "($event: any)".to_string()
       } else {
         "$event".to_string()
       };
-      
-      let exp = match exp.clone().unwrap() {
-        ExpressionNode::SimpleExpressionNode(exp) => { exp },
-        ExpressionNode::CompoundExpressionNode(_exp) => {
-          todo!("")
+
+      let assign_exp = match binding_type {
+        Some(binding_type) if ctx.inline && is_simple_identifier(&raw_exp) => {
+          let content = if binding_type == BindingTypes::SetupRef {
+            format!("{} => (({}).value = $event)", event_arg, raw_exp)
+          } else {
+            // `let` 变量和可能是 ref 的变量需要在运行时判断
+            let alt_assignment = if binding_type == BindingTypes::SetupLet {
+              format!("{} = $event", raw_exp)
+            } else {
+              "null".to_string()
+            };
+            format!(
+              "{} => ({}({}) ? ({}).value = $event : {})",
+              event_arg, ctx.helper(IS_REF), raw_exp, raw_exp, alt_assignment
+            )
+          };
+          ExpressionNode::SimpleExpressionNode(SimpleExpressionNode {
+            content,
+            is_static: false,
+            constant_type: ConstantTypes::NotConstant,
+            loc: exp.loc,
+          })
         },
+        _ => ExpressionNode::CompoundExpressionNode(CompoundExpressionNode {
+          children: vec![
+            CompoundExpressionNodeChild::RawText(format!("{} => ((", event_arg)),
+            CompoundExpressionNodeChild::SimpleExpressionNode(exp.clone()),
+            CompoundExpressionNodeChild::RawText(") = $event)".to_string()),
+          ]
+        }),
       };
 
-      let assign_exp = CompoundExpressionNode {
-        children: vec![
-          CompoundExpressionNodeChild::RawText(format!("{} => ((", event_arg)),
-          CompoundExpressionNodeChild::SimpleExpressionNode(exp),
-          CompoundExpressionNodeChild::RawText(") = $event)".to_string()),
-        ]
-      };
+      let mut props = vec![
+        Property {
+          key: prop_name.clone(),
+          value: Box::new(JsChildNode::ExpressionNode(ExpressionNode::SimpleExpressionNode(exp))),
+        },
+        Property {
+          key: event_name,
+          value: Box::new(JsChildNode::ExpressionNode(assign_exp)),
+        },
+      ];
 
-      let mut props = vec![];
-      props.push(Property {
-        key: ExpressionNode::SimpleExpressionNode(prop_name.to_owned()),
-        value: Box::new(JsChildNode::ExpressionNode(dir.exp.clone().unwrap())),
-      });
-      props.push(Property { 
-        key: ExpressionNode::SimpleExpressionNode(SimpleExpressionNode {
-          content: event_name,
-          is_static: true,
-          constant_type: ConstantTypes::NotConstant,
-          loc: Default::default(),
-        }),
-        value: Box::new(JsChildNode::ExpressionNode(ExpressionNode::CompoundExpressionNode(assign_exp))),
-      });
+      // 组件上的修饰符通过 `modelModifiers` 传递
+      let is_component = matches!(node, Node::ElementNode(el) if el.tag_type == ElementTagType::ComponentNode);
+      if is_component && !dir.modifiers.is_empty() {
+        let modifiers = dir.modifiers
+          .iter()
+          .map(|m| {
+            let key = if is_simple_identifier(m) { m.clone() } else { format!("\"{}\"", m) };
+            format!("{}: true", key)
+          })
+          .collect::<Vec<_>>()
+          .join(", ");
+        let modifiers_key = match &prop_name {
+          ExpressionNode::SimpleExpressionNode(arg) if arg.is_static => {
+            ExpressionNode::SimpleExpressionNode(static_key(format!("{}Modifiers", arg.content)))
+          },
+          _ => ExpressionNode::CompoundExpressionNode(CompoundExpressionNode {
+            children: [
+              vec![CompoundExpressionNodeChild::RawText("(".to_string())],
+              compound_children(&prop_name),
+              vec![CompoundExpressionNodeChild::RawText(") + \"Modifiers\"".to_string())],
+            ].concat()
+          }),
+        };
+        props.push(Property {
+          key: modifiers_key,
+          value: Box::new(JsChildNode::ExpressionNode(ExpressionNode::SimpleExpressionNode(
+            SimpleExpressionNode {
+              content: format!("{{ {} }}", modifiers),
+              is_static: false,
+              constant_type: ConstantTypes::CanHoist,
+              loc: dir.loc,
+            }
+          ))),
+        });
+      }
 
-      DirectiveTransformRes { 
-        properties: props, 
-        need_runtime: false 
+      DirectiveTransformRes {
+        properties: props,
+        need_runtime: false
       }
   }
-}
\ No newline at end of file
+}
+
+fn static_key(content: String) -> SimpleExpressionNode {
+  SimpleExpressionNode {
+    content,
+    is_static: true,
+    constant_type: ConstantTypes::CanStringify,
+    loc: Default::default(),
+  }
+}
+
+fn compound_children(exp: &ExpressionNode) -> Vec<CompoundExpressionNodeChild> {
+  match exp {
+    ExpressionNode::SimpleExpressionNode(exp) => {
+      vec![CompoundExpressionNodeChild::SimpleExpressionNode(exp.clone())]
+    },
+    ExpressionNode::CompoundExpressionNode(exp) => exp.children.clone(),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::{
+    Parser, Node, Prop, TransformContext, DirectiveTransform, DirectiveTransformRes, JsChildNode,
+    ExpressionNode, CompoundExpressionNodeChild, BindingTypes, TransformVModel, ErrorCodes,
+  };
+
+  fn transform_v_model(source: &str, ctx: &mut TransformContext) -> DirectiveTransformRes {
+    let mut parser = Parser::new(source);
+    let ast = parser.parse();
+    let node = ast.children().unwrap().borrow()[0].clone();
+    let mut node = node.borrow_mut();
+    let mut dir = match &*node {
+      Node::ElementNode(el) => match &el.props.borrow()[0] {
+        Prop::Directive(d) => d.clone(),
+        _ => panic!("expected directive"),
+      },
+      _ => panic!("expected element"),
+    };
+    TransformVModel.transform(&mut dir, &mut node, ctx)
+  }
+
+  fn value(res: &DirectiveTransformRes, index: usize) -> String {
+    match &*res.properties[index].value {
+      JsChildNode::ExpressionNode(ExpressionNode::SimpleExpressionNode(exp)) => exp.content.clone(),
+      JsChildNode::ExpressionNode(ExpressionNode::CompoundExpressionNode(exp)) => {
+        exp.children.iter().map(|child| match child {
+          CompoundExpressionNodeChild::SimpleExpressionNode(exp) => exp.content.clone(),
+          CompoundExpressionNodeChild::RawText(text) => text.clone(),
+          _ => panic!("unexpected compound child"),
+        }).collect()
+      },
+      _ => panic!("expected expression"),
+    }
+  }
+
+  #[test]
+  fn test_v_model_prefixed() {
+    let mut ctx = TransformContext::new("hello.vue".to_string());
+    let res = transform_v_model("<input v-model=\"foo.bar\" />", &mut ctx);
+    assert_eq!(value(&res, 0), "_ctx.foo.bar");
+    assert_eq!(value(&res, 1), "$event => ((_ctx.foo.bar) = $event)");
+  }
+
+  #[test]
+  fn test_v_model_inline_refs() {
+    let mut ctx = TransformContext::new("hello.vue".to_string());
+    ctx.inline = true;
+    ctx.binding_metadata.insert("r".to_string(), BindingTypes::SetupRef);
+    ctx.binding_metadata.insert("m".to_string(), BindingTypes::SetupMaybeRef);
+    ctx.binding_metadata.insert("l".to_string(), BindingTypes::SetupLet);
+
+    let res = transform_v_model("<input v-model=\"r\" />", &mut ctx);
+    assert_eq!(value(&res, 0), "r.value");
+    assert_eq!(value(&res, 1), "$event => ((r).value = $event)");
+
+    let res = transform_v_model("<input v-model=\"m\" />", &mut ctx);
+    assert_eq!(value(&res, 0), "_unref(m)");
+    assert_eq!(value(&res, 1), "$event => (_isRef(m) ? (m).value = $event : null)");
+
+    let res = transform_v_model("<input v-model=\"l\" />", &mut ctx);
+    assert_eq!(value(&res, 1), "$event => (_isRef(l) ? (l).value = $event : l = $event)");
+  }
+
+  #[test]
+  fn test_v_model_setup_binding_non_inline() {
+    let mut ctx = TransformContext::new("hello.vue".to_string());
+    ctx.binding_metadata.insert("count".to_string(), BindingTypes::SetupRef);
+    let res = transform_v_model("<input v-model=\"count\" />", &mut ctx);
+    assert_eq!(value(&res, 1), "$event => (($setup.count) = $event)");
+  }
+
+  #[test]
+  fn test_v_model_errors() {
+    let errors = |source: &str, scope: &[&str]| {
+      let mut ctx = TransformContext::new("hello.vue".to_string());
+      ctx.add_identifiers(&scope.iter().map(|s| s.to_string()).collect::<Vec<_>>());
+      let res = transform_v_model(source, &mut ctx);
+      assert!(res.properties.is_empty());
+      ctx.errors.iter().map(|e| e.code).collect::<Vec<_>>()
+    };
+    assert_eq!(errors("<input v-model=\"\" />", &[]), vec![ErrorCodes::XVModelNoExpression]);
+    assert_eq!(errors("<input v-model=\"a + b\" />", &[]), vec![ErrorCodes::XVModelMalformedExpression]);
+    assert_eq!(errors("<input v-model=\"item\" />", &["item"]), vec![ErrorCodes::XVModelOnScopeVariable]);
+  }
+}
@@ -0,0 +1,284 @@
+use crate::{
+  DirectiveTransform,
+  DirectiveTransformRes,
+  DirectiveProp,
+  Node,
+  TransformContext,
+  ExpressionNode,
+  SimpleExpressionNode,
+  CompoundExpressionNode,
+  CompoundExpressionNodeChild,
+  ConstantTypes,
+  Property,
+  JsChildNode,
+  ElementTagType,
+  process_expression,
+  has_scope_ref,
+  is_member_expression,
+  is_fn_expression,
+  camelize,
+  to_handler_key,
+  TO_HANDLER_KEY,
+  CompilerError,
+  ErrorCodes,
+};
+
+pub struct TransformVOn;
+
+impl DirectiveTransform for TransformVOn {
+  fn transform(
+    &self,
+    dir: &mut DirectiveProp,
+    node: &mut Node,
+    ctx: &mut TransformContext) -> DirectiveTransformRes {
+    let is_component = match node {
+      Node::ElementNode(el) => el.tag_type == ElementTagType::ComponentNode,
+      _ => false,
+    };
+
+    let event_name = match &dir.arg {
+      Some(ExpressionNode::SimpleExpressionNode(arg)) if arg.is_static => {
+        let mut raw_name = arg.content.clone();
+        if let Some(name) = raw_name.strip_prefix("vue:") {
+          raw_name = format!("vnode-{}", name);
+        }
+        // 原生元素上带大写字母的事件名需要保持原样
+        let has_upper = raw_name.chars().any(|c| c.is_ascii_uppercase());
+        let content = if is_component || raw_name.starts_with("vnode") || !has_upper {
+          to_handler_key(&camelize(&raw_name))
+        } else {
+          format!("on:{}", raw_name)
+        };
+        ExpressionNode::SimpleExpressionNode(SimpleExpressionNode {
+          content,
+          is_static: true,
+          constant_type: ConstantTypes::CanStringify,
+          loc: arg.loc,
+        })
+      },
+      Some(ExpressionNode::SimpleExpressionNode(arg)) => {
+        ExpressionNode::CompoundExpressionNode(CompoundExpressionNode {
+          children: vec![
+            CompoundExpressionNodeChild::RawText(format!("{}(", ctx.helper(TO_HANDLER_KEY))),
+            CompoundExpressionNodeChild::SimpleExpressionNode(arg.clone()),
+            CompoundExpressionNodeChild::RawText(")".to_string()),
+          ]
+        })
+      },
+      Some(arg) => arg.clone(),
+      // 没有参数的 `v-on="obj"` 在 build_props 中处理, 这里只会是缺少表达式的 `v-on`
+      None => {
+        ctx.on_error(CompilerError::new(ErrorCodes::XVOnNoExpression, Some(dir.loc), ""));
+        return DirectiveTransformRes { properties: vec![], need_runtime: false };
+      },
+    };
+
+    let mut exp = match &dir.exp {
+      Some(ExpressionNode::SimpleExpressionNode(exp)) if !exp.content.trim().is_empty() => {
+        Some(exp.clone())
+      },
+      _ => None,
+    };
+    let mut should_cache = ctx.cache_handlers && exp.is_none() && !ctx.in_v_once;
+
+    let mut value = None;
+    if let Some(exp) = &mut exp {
+      let is_member_exp = is_member_expression(&exp.content);
+      let is_inline_statement = !(is_member_exp || is_fn_expression(&exp.content));
+      let has_multiple_statements = exp.content.contains(';');
+
+      let event = ["$event".to_string()];
+      if is_inline_statement {
+        ctx.add_identifiers(&event);
+      }
+      // 只有内联的处理函数可以写多条语句
+      process_expression(exp, ctx, false, has_multiple_statements);
+      if is_inline_statement {
+        ctx.remove_identifiers(&event);
+      }
+
+      should_cache = ctx.cache_handlers &&
+        // v-once 中的内容只会渲染一次, 不需要缓存
+        !ctx.in_v_once &&
+        // 常量不需要缓存
+        exp.constant_type == ConstantTypes::NotConstant &&
+        // 传给组件的成员表达式需要保留原函数, 以便保留参数个数
+        !(is_member_exp && is_component) &&
+        // 引用了 v-for/v-slot 作用域变量的函数每次都要重新创建
+        !has_scope_ref(&ExpressionNode::SimpleExpressionNode(exp.clone()), ctx);
+
+      // 把成员表达式转换为调用, 这样缓存的函数总能拿到最新的值
+      if should_cache && is_member_exp {
+        exp.content = format!("{0} && {0}(...args)", exp.content);
+      }
+
+      if is_inline_statement || (should_cache && is_member_exp) {
+        let params = if is_inline_statement {
+          if ctx.is_ts { "($event: any)" } else { "$event" }
+        } else if ctx.is_ts {
+          "\n//@ts-ignore\n(...args)"
+        } else {
+          "(...args)"
+        };
+        let (open, close) = if has_multiple_statements { ("{", "}") } else { ("(", ")") };
+        value = Some(ExpressionNode::CompoundExpressionNode(CompoundExpressionNode {
+          children: vec![
+            CompoundExpressionNodeChild::RawText(format!("{} => {}", params, open)),
+            CompoundExpressionNodeChild::SimpleExpressionNode(exp.clone()),
+            CompoundExpressionNodeChild::RawText(close.to_string()),
+          ]
+        }));
+      } else {
+        value = Some(ExpressionNode::SimpleExpressionNode(exp.clone()));
+      }
+      dir.exp = Some(ExpressionNode::SimpleExpressionNode(exp.clone()));
+    }
+
+    let value = value.unwrap_or_else(|| {
+      ExpressionNode::SimpleExpressionNode(SimpleExpressionNode {
+        content: "() => {}".to_string(),
+        is_static: false,
+        constant_type: ConstantTypes::NotConstant,
+        loc: dir.loc,
+      })
+    });
+
+    let mut value = JsChildNode::ExpressionNode(value);
+    if should_cache {
+      value = JsChildNode::CacheExpression(ctx.cache(value, false));
+    }
+
+    DirectiveTransformRes {
+      properties: vec![
+        Property {
+          key: event_name,
+          value: Box::new(value),
+        }
+      ],
+      need_runtime: false
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::{
+    Parser, Node, Prop, TransformContext, DirectiveTransform, DirectiveTransformRes,
+    JsChildNode, ExpressionNode, CompoundExpressionNodeChild, TransformVOn, ErrorCodes,
+  };
+
+  fn transform_v_on(source: &str, cache_handlers: bool) -> (DirectiveTransformRes, usize) {
+    let mut ctx = TransformContext::new("hello.vue".to_string());
+    ctx.cache_handlers = cache_handlers;
+    let res = transform_v_on_with_ctx(source, &mut ctx);
+    (res, ctx.cached)
+  }
+
+  fn transform_v_on_with_ctx(source: &str, ctx: &mut TransformContext) -> DirectiveTransformRes {
+    let mut parser = Parser::new(source);
+    let ast = parser.parse();
+    let node = ast.children().unwrap().borrow()[0].clone();
+    let mut node = node.borrow_mut();
+    let mut dir = match &*node {
+      Node::ElementNode(el) => match &el.props.borrow()[0] {
+        Prop::Directive(d) => d.clone(),
+        _ => panic!("expected directive"),
+      },
+      _ => panic!("expected element"),
+    };
+    TransformVOn.transform(&mut dir, &mut node, ctx)
+  }
+
+  fn key(res: &DirectiveTransformRes) -> String {
+    match &res.properties[0].key {
+      ExpressionNode::SimpleExpressionNode(key) => key.content.clone(),
+      _ => panic!("expected static key"),
+    }
+  }
+
+  #[test]
+  fn test_event_name() {
+    let (res, _) = transform_v_on("<div @click=\"onClick\"></div>", false);
+    assert_eq!(key(&res), "onClick");
+    let (res, _) = transform_v_on("<div @foo-bar=\"onClick\"></div>", false);
+    assert_eq!(key(&res), "onFooBar");
+    let (res, _) = transform_v_on("<div @vue:mounted=\"onMount\"></div>", false);
+    assert_eq!(key(&res), "onVnodeMounted");
+  }
+
+  #[test]
+  fn test_no_expression() {
+    let mut ctx = TransformContext::new("hello.vue".to_string());
+    let res = transform_v_on_with_ctx("<div v-on></div>", &mut ctx);
+    assert!(res.properties.is_empty());
+    assert_eq!(ctx.errors.len(), 1);
+    assert_eq!(ctx.errors[0].code, ErrorCodes::XVOnNoExpression);
+  }
+
+  #[test]
+  fn test_no_cache_by_default() {
+    let (res, cached) = transform_v_on("<div @click=\"onClick\"></div>", false);
+    assert_eq!(cached, 0);
+    assert!(matches!(*res.properties[0].value, JsChildNode::ExpressionNode(_)));
+  }
+
+  #[test]
+  fn test_cache_member_expression() {
+    let (res, cached) = transform_v_on("<div @click=\"foo.bar\"></div>", true);
+    assert_eq!(cached, 1);
+    match &*res.properties[0].value {
+      JsChildNode::CacheExpression(cache) => {
+        assert_eq!(cache.index, 0);
+        match &*cache.value {
+          JsChildNode::ExpressionNode(ExpressionNode::CompoundExpressionNode(exp)) => {
+            assert_eq!(
+              exp.children[0],
+              CompoundExpressionNodeChild::RawText("(...args) => (".to_string())
+            );
+          },
+          _ => panic!("expected compound expression"),
+        }
+      },
+      _ => panic!("expected cache expression"),
+    }
+  }
+
+  #[test]
+  fn test_cache_inline_statement() {
+    let (res, cached) = transform_v_on("<div @click=\"foo($event)\"></div>", true);
+    assert_eq!(cached, 1);
+    match &*res.properties[0].value {
+      JsChildNode::CacheExpression(cache) => match &*cache.value {
+        JsChildNode::ExpressionNode(ExpressionNode::CompoundExpressionNode(exp)) => {
+          assert_eq!(
+            exp.children[0],
+            CompoundExpressionNodeChild::RawText("$event => (".to_string())
+          );
+        },
+        _ => panic!("expected compound expression"),
+      },
+      _ => panic!("expected cache expression"),
+    }
+  }
+
+  #[test]
+  fn test_do_not_cache_scope_ref() {
+    let mut parser = Parser::new("<div @click=\"foo(item)\"></div>");
+    let ast = parser.parse();
+    let node = ast.children().unwrap().borrow()[0].clone();
+    let mut node = node.borrow_mut();
+    let mut dir = match &*node {
+      Node::ElementNode(el) => match &el.props.borrow()[0] {
+        Prop::Directive(d) => d.clone(),
+        _ => panic!("expected directive"),
+      },
+      _ => panic!("expected element"),
+    };
+    let mut ctx = TransformContext::new("hello.vue".to_string());
+    ctx.cache_handlers = true;
+    ctx.identifiers.insert("item".to_string(), 1);
+    let res = TransformVOn.transform(&mut dir, &mut node, &mut ctx);
+    assert_eq!(ctx.cached, 0);
+    assert!(matches!(*res.properties[0].value, JsChildNode::ExpressionNode(_)));
+  }
+}
@@ -0,0 +1,34 @@
+use crate::{Node, Transform, TransformContext, Prop, JsChildNode};
+
+/// `v-once` 的元素只渲染一次, vnode 缓存在 `_cache` 中
+pub struct TransformOnce;
+
+impl Transform for TransformOnce {
+  fn pre_transform(&self, node: &mut Node, ctx: &mut TransformContext) {
+    let Node::ElementNode(el) = node else {
+      return;
+    };
+    let has_once = el.props.borrow().iter().any(|p| matches!(p, Prop::Directive(d) if d.name == "once"));
+    // 嵌套的 v-once 已经包含在外层的缓存中
+    if !has_once || ctx.in_v_once || ctx.ssr {
+      return;
+    }
+    // 子树中的事件处理函数不需要再单独缓存
+    ctx.in_v_once = true;
+    ctx.on_exit(Box::new(|node, ctx| {
+      ctx.in_v_once = false;
+      let Node::ElementNode(el) = node else {
+        return;
+      };
+      let Some(code_gen) = el.code_gen.as_mut() else {
+        return;
+      };
+      if let Some(vnode) = code_gen.vnode_call.take() {
+        code_gen.cache_expression = Some(ctx.cache(JsChildNode::VnodeCall(vnode), true));
+      }
+    }));
+  }
+
+  fn post_transform(&self, _node: &mut Node, _ctx: &mut TransformContext) {
+  }
+}
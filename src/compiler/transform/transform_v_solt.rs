@@ -1 +1,440 @@
-pub fn build_slot() {}
\ No newline at end of file
+use std::{cell::RefCell, rc::Rc};
+
+use crate::{
+  Node, Transform, TransformContext, ElementTagType, ElementNodeBase, Prop, DirectiveProp,
+  ExpressionNode, SimpleExpressionNode, ConstantTypes, ObjectExpression, Property, JsChildNode,
+  FunctionExpression, Params, Returns, Children, VnodeCallChildren, CallExpression,
+  CallExpressionArgument, ArrayExpression, ElementValue, ConditionalExpression, ForParseResult,
+  CompilerError, ErrorCodes, find_prop, collect_param_bindings, has_scope_ref, get_static_exp,
+  parse_for_expression, process_expression, CREATE_SLOTS, RENDER_LIST,
+};
+
+/// 记录组件或 `<template>` 上 v-slot 声明的插槽参数, 子节点中引用这些参数时不需要加 `_ctx.` 前缀
+pub struct TrackSlotScopes;
+
+impl Transform for TrackSlotScopes {
+  fn pre_transform(&self, node: &mut Node, ctx: &mut TransformContext) {
+    if let Some(slot_props) = get_slot_props(node, ctx.is_ts) {
+      ctx.add_identifiers(&slot_props);
+      ctx.scopes.v_slot += 1;
+    }
+  }
+
+  fn post_transform(&self, node: &mut Node, ctx: &mut TransformContext) {
+    if let Some(slot_props) = get_slot_props(node, ctx.is_ts) {
+      ctx.remove_identifiers(&slot_props);
+      ctx.scopes.v_slot -= 1;
+    }
+  }
+}
+
+fn get_slot_props(node: &Node, is_ts: bool) -> Option<Vec<String>> {
+  let Node::ElementNode(el) = node else {
+    return None;
+  };
+  if el.tag_type != ElementTagType::ComponentNode && el.tag_type != ElementTagType::TemplateNode {
+    return None;
+  }
+  match find_prop(el.clone(), "slot") {
+    Some(Prop::Directive(dir)) => match &dir.exp {
+      Some(ExpressionNode::SimpleExpressionNode(exp)) if !exp.content.trim().is_empty() => {
+        Some(collect_param_bindings(&exp.content, is_ts))
+      },
+      _ => Some(vec![]),
+    },
+    _ => None,
+  }
+}
+
+/// 插槽模板上的 v-for 不会生成 ForNode: 数据源在这里加上前缀, 别名在插槽名和插槽内容中不加前缀
+pub struct TrackVForSlotScopes;
+
+impl Transform for TrackVForSlotScopes {
+  fn pre_transform(&self, node: &mut Node, ctx: &mut TransformContext) {
+    let Node::ElementNode(el) = node else {
+      return;
+    };
+    if !is_slot_template(el) {
+      return;
+    }
+    let mut props = el.props.borrow_mut();
+    let exp = props.iter_mut().find_map(|p| match p {
+      Prop::Directive(DirectiveProp { name, exp: Some(ExpressionNode::SimpleExpressionNode(exp)), .. })
+        if name == "for" => Some(exp),
+      _ => None,
+    });
+    // 无法解析的表达式在 build_slots 中报告错误
+    let Some(exp) = exp else {
+      return;
+    };
+    let Some(result) = parse_for_expression(&exp.content) else {
+      return;
+    };
+    let mut source = SimpleExpressionNode {
+      content: result.source.clone(),
+      is_static: false,
+      constant_type: ConstantTypes::NotConstant,
+      loc: exp.loc,
+    };
+    process_expression(&mut source, ctx, false, false);
+    // 加上前缀的数据源写回表达式, build_slots 中重新解析
+    let content = exp.content.trim();
+    exp.content = format!("{}{}", &content[..content.len() - result.source.len()], source.content);
+    ctx.add_identifiers(&for_alias_bindings(&result, ctx.is_ts));
+  }
+
+  fn post_transform(&self, node: &mut Node, ctx: &mut TransformContext) {
+    let Node::ElementNode(el) = node else {
+      return;
+    };
+    if !is_slot_template(el) {
+      return;
+    }
+    let result = el.props.borrow().iter().find_map(|p| match p {
+      Prop::Directive(DirectiveProp { name, exp: Some(ExpressionNode::SimpleExpressionNode(exp)), .. })
+        if name == "for" => parse_for_expression(&exp.content),
+      _ => None,
+    });
+    if let Some(result) = result {
+      ctx.remove_identifiers(&for_alias_bindings(&result, ctx.is_ts));
+    }
+  }
+}
+
+fn for_alias_bindings(result: &ForParseResult, is_ts: bool) -> Vec<String> {
+  [&result.value, &result.key, &result.index]
+    .into_iter()
+    .flatten()
+    .flat_map(|alias| collect_param_bindings(alias, is_ts))
+    .collect()
+}
+
+/// 带 v-slot 的 `<template>` 是组件的插槽, 上面的 v-if/v-for 由 build_slots 处理
+pub fn is_slot_template(el: &ElementNodeBase) -> bool {
+  el.tag_type == ElementTagType::TemplateNode &&
+    el.props.borrow().iter().any(|p| matches!(p, Prop::Directive(d) if d.name == "slot"))
+}
+
+/// 把组件的子节点编译为插槽对象: `<template #name>` 是具名插槽, 其余子节点组成默认插槽。
+/// 带 v-if/v-for 的插槽模板在运行时通过 `createSlots` 合并到插槽对象中。
+/// 返回插槽对象以及插槽是否需要在每次更新时重新生成
+pub fn build_slots(el: &ElementNodeBase, ctx: &mut TransformContext) -> (VnodeCallChildren, bool) {
+  // 插槽内容引用了外层 v-for/v-slot 的变量时, 父组件更新后插槽也需要更新
+  let mut has_dynamic_slots = if ctx.prefix_identifiers {
+    has_scope_ref_in_children(&el.children, ctx)
+  } else {
+    ctx.scopes.v_for > 0 || ctx.scopes.v_slot > 0
+  };
+
+  let mut properties = vec![];
+  let mut dynamic_slots: Vec<JsChildNode> = vec![];
+  if let Some(Prop::Directive(dir)) = find_prop(el.clone(), "slot") {
+    // 组件上的 v-slot 声明的是默认插槽, 所有子节点都属于它
+    properties.push(Property {
+      key: static_key("default"),
+      value: Box::new(build_slot_fn(&dir, el.children.clone())),
+    });
+  } else {
+    let mut implicit_default_children = vec![];
+    let mut seen_slot_names: Vec<String> = vec![];
+    let mut has_named_default_slot = false;
+    let mut conditional_branch_index = 0;
+    let children = el.children.borrow().clone();
+    for (index, child) in children.iter().enumerate() {
+      let template = match &*child.borrow() {
+        Node::ElementNode(template) if is_slot_template(template) => template.clone(),
+        Node::Comment(_) => continue,
+        _ => {
+          implicit_default_children.push(child.clone());
+          continue;
+        },
+      };
+      let find_dir = |names: &[&str]| template.props.borrow().iter().find_map(|p| match p {
+        Prop::Directive(d) if names.contains(&d.name.as_str()) => Some(d.clone()),
+        _ => None,
+      });
+      let Some(slot_dir) = find_dir(&["slot"]) else {
+        continue;
+      };
+      let slot_name = slot_dir.arg.clone().unwrap_or_else(|| static_key("default"));
+      let static_slot_name = get_static_exp(&slot_name).map(|name| name.content.clone());
+      if static_slot_name.is_none() {
+        has_dynamic_slots = true;
+      }
+      let slot_fn = build_slot_fn(&slot_dir, template.children.clone());
+
+      if let Some(v_if) = find_dir(&["if"]) {
+        has_dynamic_slots = true;
+        let slot = build_dynamic_slot(slot_name, slot_fn, Some(conditional_branch_index));
+        conditional_branch_index += 1;
+        dynamic_slots.push(build_conditional_slot(v_if.exp, slot));
+      } else if let Some(v_else) = find_dir(&["else-if", "else"]) {
+        // 前一个插槽模板(跳过注释和空白)必须带 v-if 或 v-else-if
+        let prev = children[..index].iter().rev().find(|prev| !matches!(
+          &*prev.borrow(),
+          Node::Comment(_) | Node::Text(_) if is_whitespace_or_comment(&prev.borrow())
+        ));
+        let has_adjacent_if = prev.is_some_and(|prev| matches!(
+          &*prev.borrow(),
+          Node::ElementNode(prev) if is_slot_template(prev) && prev.props.borrow().iter().any(|p| {
+            matches!(p, Prop::Directive(d) if d.name == "if" || d.name == "else-if")
+          })
+        ));
+        let conditional = dynamic_slots.last_mut().and_then(|slot| match slot {
+          JsChildNode::ConditionalExpression(conditional) => Some(conditional),
+          _ => None,
+        });
+        let (true, Some(conditional)) = (has_adjacent_if, conditional) else {
+          ctx.on_error(CompilerError::new(ErrorCodes::XVElseNoAdjacentIf, Some(v_else.loc), ""));
+          continue;
+        };
+        let slot = build_dynamic_slot(slot_name, slot_fn, Some(conditional_branch_index));
+        conditional_branch_index += 1;
+        let alternate = match v_else.exp {
+          Some(_) => build_conditional_slot(v_else.exp, slot),
+          None => slot,
+        };
+        append_alternate(conditional, alternate);
+      } else if let Some(v_for) = find_dir(&["for"]) {
+        has_dynamic_slots = true;
+        let result = match &v_for.exp {
+          Some(ExpressionNode::SimpleExpressionNode(exp)) => parse_for_expression(&exp.content),
+          _ => None,
+        };
+        let Some(result) = result else {
+          ctx.on_error(CompilerError::new(ErrorCodes::XVForMalformedExpression, Some(v_for.loc), ""));
+          continue;
+        };
+        dynamic_slots.push(build_slot_list(&result, build_dynamic_slot(slot_name, slot_fn, None)));
+      } else {
+        if let Some(name) = static_slot_name {
+          if seen_slot_names.contains(&name) {
+            ctx.on_error(CompilerError::new(ErrorCodes::XVSlotDuplicateSlotNames, Some(slot_dir.loc), ""));
+            continue;
+          }
+          has_named_default_slot |= name == "default";
+          seen_slot_names.push(name);
+        }
+        properties.push(Property { key: slot_name, value: Box::new(slot_fn) });
+      }
+    }
+
+    let default_content = implicit_default_children
+      .iter()
+      .find(|child| !is_whitespace_or_comment(&child.borrow()));
+    match default_content {
+      // 已经有显式的默认插槽, 其余的子节点无处渲染
+      Some(child) if has_named_default_slot => {
+        let loc = match &*child.borrow() {
+          Node::ElementNode(child) => child.loc,
+          Node::Text(text) => text.loc,
+          _ => el.loc,
+        };
+        ctx.on_error(CompilerError::new(ErrorCodes::XVSlotExtraneousDefaultSlotChildren, Some(loc), ""));
+      },
+      Some(_) => {
+        let dir = DirectiveProp {
+          name: "slot".to_string(),
+          arg: None,
+          exp: None,
+          modifiers: vec![],
+          loc: el.loc,
+        };
+        let children = Rc::new(RefCell::new(implicit_default_children));
+        properties.push(Property { key: static_key("default"), value: Box::new(build_slot_fn(&dir, children)) });
+      },
+      None => {},
+    }
+  }
+
+  // `_` 告诉运行时插槽是否稳定, 稳定的插槽在父组件更新时不需要强制更新子组件
+  let slot_flag = if has_dynamic_slots {
+    "2 /* DYNAMIC */"
+  } else if has_forwarded_slots(&el.children) {
+    "3 /* FORWARDED */"
+  } else {
+    "1 /* STABLE */"
+  };
+  properties.push(Property {
+    key: static_key("_"),
+    value: Box::new(JsChildNode::ExpressionNode(ExpressionNode::SimpleExpressionNode(
+      SimpleExpressionNode {
+        content: slot_flag.to_string(),
+        is_static: false,
+        constant_type: ConstantTypes::CanStringify,
+        loc: Default::default(),
+      }
+    ))),
+  });
+  let slots = ObjectExpression { properties };
+  if dynamic_slots.is_empty() {
+    return (VnodeCallChildren::SlotsExpression(slots), has_dynamic_slots);
+  }
+  let dynamic_slots = ArrayExpression {
+    elements: dynamic_slots.into_iter().map(ElementValue::ExpressionNode).collect(),
+  };
+  let call = CallExpression {
+    callee: CREATE_SLOTS.to_string(),
+    arguments: vec![
+      CallExpressionArgument::JsChildNode(Box::new(JsChildNode::ObjectExpression(slots))),
+      CallExpressionArgument::JsChildNode(Box::new(JsChildNode::ArrayExpression(dynamic_slots))),
+    ],
+  };
+  (VnodeCallChildren::DynamicSlotsExpression(call), has_dynamic_slots)
+}
+
+fn is_whitespace_or_comment(node: &Node) -> bool {
+  match node {
+    Node::Comment(_) => true,
+    Node::Text(text) => text.content.trim().is_empty(),
+    _ => false,
+  }
+}
+
+/// `_withCtx((props) => [...])`
+fn build_slot_fn(dir: &DirectiveProp, children: Children) -> JsChildNode {
+  let params = match &dir.exp {
+    Some(ExpressionNode::SimpleExpressionNode(exp)) if exp.content.trim().is_empty() => None,
+    Some(exp) => Some(Params::ExpressionNode(exp.clone())),
+    None => None,
+  };
+  JsChildNode::FunctionExpression(FunctionExpression {
+    params,
+    returns: Some(Returns::Children(children)),
+    body: None,
+    newline: false,
+    is_slot: true,
+    is_non_scoped_slot: None,
+  })
+}
+
+/// `{ name, fn, key }`, 条件分支中的插槽用 key 区分
+fn build_dynamic_slot(name: ExpressionNode, slot_fn: JsChildNode, index: Option<usize>) -> JsChildNode {
+  let mut properties = vec![
+    Property { key: static_key("name"), value: Box::new(JsChildNode::ExpressionNode(name)) },
+    Property { key: static_key("fn"), value: Box::new(slot_fn) },
+  ];
+  if let Some(index) = index {
+    properties.push(Property {
+      key: static_key("key"),
+      value: Box::new(JsChildNode::ExpressionNode(static_key(&index.to_string()))),
+    });
+  }
+  JsChildNode::ObjectExpression(ObjectExpression { properties })
+}
+
+/// `cond ? slot : undefined`
+fn build_conditional_slot(test: Option<ExpressionNode>, slot: JsChildNode) -> JsChildNode {
+  let test = test.unwrap_or_else(|| ExpressionNode::SimpleExpressionNode(SimpleExpressionNode {
+    content: "true".to_string(),
+    is_static: false,
+    constant_type: ConstantTypes::CanStringify,
+    loc: Default::default(),
+  }));
+  JsChildNode::ConditionalExpression(ConditionalExpression {
+    test: Box::new(JsChildNode::ExpressionNode(test)),
+    consequent: Box::new(slot),
+    alternate: Box::new(JsChildNode::ExpressionNode(ExpressionNode::SimpleExpressionNode(
+      SimpleExpressionNode {
+        content: "undefined".to_string(),
+        is_static: false,
+        constant_type: ConstantTypes::CanStringify,
+        loc: Default::default(),
+      }
+    ))),
+    newline: true,
+  })
+}
+
+/// v-else/v-else-if 接在条件表达式最后一个分支的 alternate 上
+fn append_alternate(conditional: &mut ConditionalExpression, alternate: JsChildNode) {
+  match &mut *conditional.alternate {
+    JsChildNode::ConditionalExpression(next) => append_alternate(next, alternate),
+    last => *last = alternate,
+  }
+}
+
+/// `_renderList(source, (item) => { return { name, fn } })`
+fn build_slot_list(result: &ForParseResult, slot: JsChildNode) -> JsChildNode {
+  let aliases = [&result.value, &result.key, &result.index];
+  let len = aliases.iter().rposition(|a| a.is_some()).map_or(0, |i| i + 1);
+  let params = aliases[..len]
+    .iter()
+    .enumerate()
+    .map(|(index, alias)| (*alias).clone().unwrap_or_else(|| "_".repeat(index + 1)))
+    .collect::<Vec<_>>()
+    .join(", ");
+  let source = ExpressionNode::SimpleExpressionNode(SimpleExpressionNode {
+    content: result.source.clone(),
+    is_static: false,
+    constant_type: ConstantTypes::NotConstant,
+    loc: Default::default(),
+  });
+  JsChildNode::CallExpression(CallExpression {
+    callee: RENDER_LIST.to_string(),
+    arguments: vec![
+      CallExpressionArgument::JsChildNode(Box::new(JsChildNode::ExpressionNode(source))),
+      CallExpressionArgument::JsChildNode(Box::new(JsChildNode::FunctionExpression(FunctionExpression {
+        params: Some(Params::RawText(params)),
+        returns: Some(Returns::JsChildNode(Box::new(slot))),
+        body: None,
+        newline: true,
+        is_slot: false,
+        is_non_scoped_slot: None,
+      }))),
+    ],
+  })
+}
+
+fn static_key(name: &str) -> ExpressionNode {
+  ExpressionNode::SimpleExpressionNode(SimpleExpressionNode {
+    content: name.to_string(),
+    is_static: true,
+    constant_type: ConstantTypes::CanStringify,
+    loc: Default::default(),
+  })
+}
+
+/// 子节点中是否有表达式引用了当前作用域中的变量
+fn has_scope_ref_in_children(children: &Children, ctx: &TransformContext) -> bool {
+  children.borrow().iter().any(|child| has_scope_ref_in_node(&child.borrow(), ctx))
+}
+
+fn has_scope_ref_in_node(node: &Node, ctx: &TransformContext) -> bool {
+  match node {
+    Node::ElementNode(el) => {
+      let in_props = el.props.borrow().iter().any(|prop| match prop {
+        Prop::Directive(dir) => [&dir.arg, &dir.exp]
+          .into_iter()
+          .flatten()
+          .any(|exp| has_scope_ref(exp, ctx)),
+        Prop::Normal(_) => false,
+      });
+      in_props || has_scope_ref_in_children(&el.children, ctx)
+    },
+    Node::Interpolation(interpolation) => has_scope_ref(&interpolation.content, ctx),
+    Node::CompoundExpression(exp) => {
+      has_scope_ref(&ExpressionNode::CompoundExpressionNode(exp.clone()), ctx)
+    },
+    Node::If(if_node) => if_node.branches.iter().any(|branch| {
+      branch.condition.as_ref().is_some_and(|exp| has_scope_ref(exp, ctx)) ||
+        has_scope_ref_in_children(&branch.children, ctx)
+    }),
+    Node::For(for_node) => {
+      has_scope_ref(&for_node.source, ctx) || has_scope_ref_in_children(&for_node.children, ctx)
+    },
+    _ => false,
+  }
+}
+
+/// 插槽内容中有 `<slot>` 时, 插槽是从父组件转发来的, 需要跟随父组件的插槽更新
+fn has_forwarded_slots(children: &Children) -> bool {
+  children.borrow().iter().any(|child| match &*child.borrow() {
+    Node::ElementNode(el) => {
+      el.tag_type == ElementTagType::SlotOutletNode || has_forwarded_slots(&el.children)
+    },
+    Node::If(if_node) => if_node.branches.iter().any(|branch| has_forwarded_slots(&branch.children)),
+    Node::For(for_node) => has_forwarded_slots(&for_node.children),
+    _ => false,
+  })
+}
@@ -1,9 +1,12 @@
-use std::{fs::File};
+use std::{cell::RefCell, fs::File, rc::Rc};
 use std::io::Read;
 use std::path::Path;
 use regex::Regex;
 
-use crate::{Node};
+use crate::{
+  Node, VnodeCall, Property, PropExpression, ObjectExpression, CallExpression,
+  CallExpressionArgument, ExpressionNode, MERGE_PROPS, Prop, Position,
+};
 
 pub fn read_file_sync(path: &Path) -> String {
   let mut file = File::open(path).unwrap();
@@ -12,6 +15,18 @@ pub fn read_file_sync(path: &Path) -> String {
   buffer.to_string()
 }
 
+/// 组件和指令在渲染函数中对应的变量名: `foo-bar` -> `_component_foo_bar`
+pub fn to_valid_asset_id(name: &str, asset_type: &str) -> String {
+  let id: String = name.chars()
+    .map(|c| match c {
+      '-' => "_".to_string(),
+      c if c.is_ascii_alphanumeric() || c == '_' => c.to_string(),
+      c => (c as u32).to_string(),
+    })
+    .collect();
+  format!("_{}_{}", asset_type, id)
+}
+
 pub fn is_text_node(node: &Node) -> bool {
   match node {
     Node::Text(_) | Node::Interpolation(_) => true,
@@ -26,6 +41,47 @@ pub fn is_compound_expression(node: &Node) -> bool {
   }
 }
 
+pub fn is_member_expression(content: &str) -> bool {
+  let reg = Regex::new(
+    r"^\s*[A-Za-z_$][\w$]*(?:\s*(?:\?\.|\.)\s*[A-Za-z_$][\w$]*|\s*\[[^\]]+\])*\s*$"
+  ).unwrap();
+  reg.is_match(content)
+}
+
+pub fn is_fn_expression(content: &str) -> bool {
+  let reg = Regex::new(
+    r"^\s*([\w$_]+|(async\s*)?\([^)]*?\))\s*(:[^=]+)?=>|^\s*(async\s+)?function(?:\s+[\w$]+)?\s*\("
+  ).unwrap();
+  reg.is_match(content)
+}
+
+/// `onClick`, `onUpdate:modelValue` 等事件监听的 prop 名
+pub fn is_on(name: &str) -> bool {
+  name.len() > 2 && name.starts_with("on") && !name.as_bytes()[2].is_ascii_lowercase()
+}
+
+/// `foo-bar` -> `fooBar`
+pub fn camelize(content: &str) -> String {
+  let reg = Regex::new(r"-(\w)").unwrap();
+  reg.replace_all(content, |caps: &regex::Captures| caps[1].to_uppercase()).to_string()
+}
+
+pub fn capitalize(content: &str) -> String {
+  let mut chars = content.chars();
+  match chars.next() {
+    Some(first) => first.to_uppercase().collect::<String>() + chars.as_str(),
+    None => String::new(),
+  }
+}
+
+/// `click` -> `onClick`
+pub fn to_handler_key(content: &str) -> String {
+  if content.is_empty() {
+    return String::new();
+  }
+  format!("on{}", capitalize(content))
+}
+
 pub fn is_simple_identifier(content: &str) -> bool {
   let reg = Regex::new(r"^\d|[^\$\w]").unwrap();
   !reg.is_match(content)
@@ -43,6 +99,12 @@ const GLOBALS: [&str; 25] = [
     "Map", "Set", "JSON", "Intl", "globalThis", "arguments", "console",
 ];
 
+const LITERALS: [&str; 4] = ["true", "false", "null", "this"];
+
+pub fn is_literal_white_list(name: &str) -> bool {
+  LITERALS.contains(&name)
+}
+
 pub fn is_build_in_directive(name: &str) -> bool {
   DIRECTIVES.contains(&name)
 }
@@ -50,3 +112,216 @@ pub fn is_build_in_directive(name: &str) -> bool {
 pub fn is_global_white_list(name: &str) -> bool {
   GLOBALS.contains(&name)
 }
+
+const HTML_VOID_TAGS: [&str; 14] = [
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta",
+    "param", "source", "track", "wbr",
+];
+
+pub fn is_html_void_tag(tag: &str) -> bool {
+  HTML_VOID_TAGS.contains(&tag)
+}
+
+const BOOLEAN_ATTRS: [&str; 27] = [
+    "itemscope", "allowfullscreen", "formnovalidate", "ismap", "nomodule", "novalidate",
+    "readonly", "async", "autofocus", "autoplay", "controls", "default", "defer", "disabled",
+    "hidden", "inert", "loop", "open", "required", "reversed", "scoped", "seamless", "checked",
+    "muted", "multiple", "selected", "playsinline",
+];
+
+/// 值为假时不渲染的布尔属性
+pub fn is_boolean_attr(name: &str) -> bool {
+  BOOLEAN_ATTRS.contains(&name)
+}
+
+/// 从 `start` 开始, 前进 `source` 中的前 `num_bytes` 个字节后的位置
+pub fn advance_position(start: Position, source: &str, num_bytes: usize) -> Position {
+  let mut pos = start;
+  for c in source.get(..num_bytes).unwrap_or(source).chars() {
+    pos.offset += c.len_utf8();
+    if c == '\n' {
+      pos.line += 1;
+      pos.column = 1;
+    } else {
+      pos.column += 1;
+    }
+  }
+  pos
+}
+
+pub fn escape_html(content: &str) -> String {
+  let mut res = String::with_capacity(content.len());
+  for c in content.chars() {
+    match c {
+      '&' => res.push_str("&amp;"),
+      '<' => res.push_str("&lt;"),
+      '>' => res.push_str("&gt;"),
+      '"' => res.push_str("&quot;"),
+      '\'' => res.push_str("&#39;"),
+      _ => res.push(c),
+    }
+  }
+  res
+}
+
+/// 与 `JSON.stringify(str)` 相同, 生成双引号包裹的 js 字符串字面量
+pub fn to_js_string_literal(content: &str) -> String {
+  let mut res = String::with_capacity(content.len() + 2);
+  res.push('"');
+  for c in content.chars() {
+    match c {
+      '"' => res.push_str("\\\""),
+      '\\' => res.push_str("\\\\"),
+      '\n' => res.push_str("\\n"),
+      '\r' => res.push_str("\\r"),
+      '\t' => res.push_str("\\t"),
+      '\u{2028}' => res.push_str("\\u2028"),
+      '\u{2029}' => res.push_str("\\u2029"),
+      c if (c as u32) < 0x20 => res.push_str(&format!("\\u{:04x}", c as u32)),
+      _ => res.push(c),
+    }
+  }
+  res.push('"');
+  res
+}
+
+/// 解析静态的 style, `color: red; font-size: 12px` -> `[("color", "red"), ("font-size", "12px")]`,
+/// 重复的属性保留第一次出现的位置和最后一次的值
+pub fn parse_string_style(css: &str) -> Vec<(String, String)> {
+  let comment_reg = Regex::new(r"/\*[\s\S]*?\*/").unwrap();
+  let css = comment_reg.replace_all(css, "");
+  // 括号中的分号不是分隔符, 例如 `url(data:image/png;base64,...)`
+  let mut items = vec![];
+  let mut depth = 0;
+  let mut start = 0;
+  for (i, c) in css.char_indices() {
+    match c {
+      '(' => depth += 1,
+      ')' if depth > 0 => depth -= 1,
+      ';' if depth == 0 => {
+        items.push(&css[start..i]);
+        start = i + 1;
+      },
+      _ => {},
+    }
+  }
+  items.push(&css[start..]);
+
+  let mut styles: Vec<(String, String)> = vec![];
+  for item in items {
+    let Some((name, value)) = item.split_once(':') else {
+      continue;
+    };
+    let (name, value) = (name.trim().to_string(), value.trim().to_string());
+    match styles.iter_mut().find(|(n, _)| *n == name) {
+      Some(style) => style.1 = value,
+      None => styles.push((name, value)),
+    }
+  }
+  styles
+}
+
+/// 把解析后的 style 生成为对象字面量, 与 `JSON.stringify` 的结果相同
+pub fn stringify_style(styles: &[(String, String)]) -> String {
+  let entries = styles
+    .iter()
+    .map(|(name, value)| format!("{}:{}", to_js_string_literal(name), to_js_string_literal(value)))
+    .collect::<Vec<_>>();
+  format!("{{{}}}", entries.join(","))
+}
+
+/// 向 vnode 的 props 中注入一个属性, 已经存在同名属性时不覆盖
+pub fn inject_prop(vnode: &mut VnodeCall, prop: Property) {
+  let name = match &prop.key {
+    ExpressionNode::SimpleExpressionNode(key) if key.is_static => Some(key.content.clone()),
+    _ => None,
+  };
+  let has_prop = |obj: &ObjectExpression| obj.properties.iter().any(|p| match (&p.key, &name) {
+    (ExpressionNode::SimpleExpressionNode(key), Some(name)) => key.is_static && &key.content == name,
+    _ => false,
+  });
+
+  match &mut vnode.props {
+    None => {
+      vnode.props = Some(PropExpression::ObjectExpression(ObjectExpression {
+        properties: vec![prop],
+      }));
+    },
+    Some(PropExpression::ObjectExpression(obj)) => {
+      if !has_prop(obj) {
+        obj.properties.insert(0, prop);
+      }
+    },
+    Some(PropExpression::CallExpression(call)) if call.callee == MERGE_PROPS => {
+      let first = call.arguments.first_mut();
+      match first {
+        Some(CallExpressionArgument::PropExpression(PropExpression::ObjectExpression(obj))) => {
+          if !has_prop(obj) {
+            obj.properties.insert(0, prop);
+          }
+        },
+        _ => {
+          call.arguments.insert(0, CallExpressionArgument::PropExpression(
+            PropExpression::ObjectExpression(ObjectExpression { properties: vec![prop] })
+          ));
+        },
+      }
+    },
+    // `v-bind="obj"` 或 `toHandlers(obj)` 需要和注入的属性合并
+    Some(props) => {
+      let props = props.clone();
+      vnode.props = Some(PropExpression::CallExpression(CallExpression {
+        callee: MERGE_PROPS.to_string(),
+        arguments: vec![
+          CallExpressionArgument::PropExpression(PropExpression::ObjectExpression(ObjectExpression {
+            properties: vec![prop],
+          })),
+          CallExpressionArgument::PropExpression(props),
+        ],
+      }));
+    },
+  }
+}
+
+/// 元素上是否有 `key` 或 `:key`
+pub fn has_key_prop(props: &[Prop]) -> bool {
+  props.iter().any(|p| match p {
+    Prop::Normal(p) => p.name == "key",
+    Prop::Directive(d) => d.name == "bind" && matches!(
+      &d.arg,
+      Some(ExpressionNode::SimpleExpressionNode(arg)) if arg.is_static && arg.content == "key"
+    ),
+  })
+}
+
+/// 深拷贝子节点, 拷贝出的树可以单独做 transform 而不影响原来的树
+pub fn deep_clone_children(
+  children: &Rc<RefCell<Vec<Rc<RefCell<Node>>>>>
+) -> Rc<RefCell<Vec<Rc<RefCell<Node>>>>> {
+  let nodes = children.borrow()
+    .iter()
+    .map(|child| Rc::new(RefCell::new(deep_clone_node(&child.borrow()))))
+    .collect();
+  Rc::new(RefCell::new(nodes))
+}
+
+pub fn deep_clone_node(node: &Node) -> Node {
+  let mut node = node.clone();
+  match &mut node {
+    Node::Root(root) => root.children = deep_clone_children(&root.children),
+    Node::ElementNode(el) => {
+      let props = el.props.borrow().clone();
+      el.props = Rc::new(RefCell::new(props));
+      el.children = deep_clone_children(&el.children);
+    },
+    Node::If(if_node) => {
+      for branch in if_node.branches.iter_mut() {
+        branch.children = deep_clone_children(&branch.children);
+      }
+    },
+    Node::IFBranch(branch) => branch.children = deep_clone_children(&branch.children),
+    Node::For(for_node) => for_node.children = deep_clone_children(&for_node.children),
+    _ => {},
+  }
+  node
+}